@@ -5,7 +5,9 @@ import kotlinx.coroutines.withContext
 import org.json.JSONArray
 import org.json.JSONObject
 import java.io.BufferedReader
+import java.io.File
 import java.io.InputStreamReader
+import java.io.OutputStream
 import java.net.HttpURLConnection
 import java.net.URL
 import java.security.MessageDigest
@@ -252,6 +254,23 @@ internal suspend fun uploadImmichAsset(
     bytes: ByteArray,
     fileName: String,
     mimeType: String
+): ImmichUploadResult = uploadImmichAssetContent(config, bytes.size.toLong(), { it.write(bytes) }, fileName, mimeType)
+
+// Streams the file into the request, so large exports never have to fit in the Java heap.
+internal suspend fun uploadImmichAsset(
+    config: ImmichConfig,
+    file: File,
+    fileName: String,
+    mimeType: String
+): ImmichUploadResult =
+    uploadImmichAssetContent(config, file.length(), { out -> file.inputStream().use { it.copyTo(out) } }, fileName, mimeType)
+
+private suspend fun uploadImmichAssetContent(
+    config: ImmichConfig,
+    contentLength: Long,
+    writeContent: (OutputStream) -> Unit,
+    fileName: String,
+    mimeType: String
 ): ImmichUploadResult {
     return withContext(Dispatchers.IO) {
         runCatching {
@@ -325,7 +344,7 @@ internal suspend fun uploadImmichAsset(
 
             val totalLength =
                 parts.sumOf { it.size.toLong() } +
-                    bytes.size.toLong() +
+                    contentLength +
                     fileFooterPart().size.toLong() +
                     closing.size.toLong()
 
@@ -334,7 +353,7 @@ internal suspend fun uploadImmichAsset(
 
             connection.outputStream.use { stream ->
                 parts.forEach { stream.write(it) }
-                stream.write(bytes)
+                writeContent(stream)
                 stream.write(fileFooterPart())
                 stream.write(closing)
             }
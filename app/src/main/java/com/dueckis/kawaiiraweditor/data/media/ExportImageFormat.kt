@@ -3,9 +3,12 @@ package com.dueckis.kawaiiraweditor.data.media
 internal enum class ExportImageFormat(
     val label: String,
     val extension: String,
-    val mimeType: String
+    val mimeType: String,
+    val nativeName: String
 ) {
-    Jpeg("JPEG", "jpg", "image/jpeg"),
-    Png("PNG", "png", "image/png"),
-    Webp("WebP", "webp", "image/webp")
+    Jpeg("JPEG", "jpg", "image/jpeg", "jpeg"),
+    Png("PNG", "png", "image/png", "png"),
+    Webp("WebP", "webp", "image/webp", "webp"),
+    JpegXl("JPEG XL", "jxl", "image/jxl", "jpegXl"),
+    UltraHdr("Ultra HDR", "jpg", "image/jpeg", "ultraHdr")
 }
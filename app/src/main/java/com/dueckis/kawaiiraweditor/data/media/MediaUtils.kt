@@ -6,6 +6,7 @@ import android.graphics.Bitmap
 import android.graphics.BitmapFactory
 import android.net.Uri
 import android.os.Build
+import android.os.ParcelFileDescriptor
 import android.provider.MediaStore
 import android.provider.OpenableColumns
 import android.util.Log
@@ -27,11 +28,18 @@ internal fun saveJpegToPictures(
     context: Context,
     jpegBytes: ByteArray,
     relativePath: String? = null
+): Uri? = saveEncodedToPictures(context, jpegBytes, ExportImageFormat.Jpeg, relativePath)
+
+internal fun saveEncodedToPictures(
+    context: Context,
+    bytes: ByteArray,
+    format: ExportImageFormat,
+    relativePath: String? = null
 ): Uri? {
-    val filename = "IRIDIS_${SimpleDateFormat("yyyyMMdd_HHmmss", Locale.US).format(Date())}.jpg"
+    val filename = "IRIDIS_${SimpleDateFormat("yyyyMMdd_HHmmss", Locale.US).format(Date())}.${format.extension}"
     val contentValues = ContentValues().apply {
         put(MediaStore.MediaColumns.DISPLAY_NAME, filename)
-        put(MediaStore.MediaColumns.MIME_TYPE, "image/jpeg")
+        put(MediaStore.MediaColumns.MIME_TYPE, format.mimeType)
         if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.Q) {
             put(MediaStore.MediaColumns.RELATIVE_PATH, relativePath?.trim().takeIf { !it.isNullOrBlank() } ?: "Pictures/IRIDIS")
             put(MediaStore.MediaColumns.IS_PENDING, 1)
@@ -41,7 +49,7 @@ internal fun saveJpegToPictures(
     val uri = resolver.insert(MediaStore.Images.Media.EXTERNAL_CONTENT_URI, contentValues)
     if (uri != null) {
         try {
-            resolver.openOutputStream(uri)?.use { it.write(jpegBytes) }
+            resolver.openOutputStream(uri)?.use { it.write(bytes) }
             if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.Q) {
                 contentValues.clear()
                 contentValues.put(MediaStore.MediaColumns.IS_PENDING, 0)
@@ -55,12 +63,12 @@ internal fun saveJpegToPictures(
     return uri
 }
 
-internal fun saveBitmapToPictures(
+// Lets `write` fill a new Pictures entry through its file descriptor instead of a byte array.
+internal fun saveToPictures(
     context: Context,
-    bitmap: Bitmap,
     format: ExportImageFormat,
-    quality: Int,
-    relativePath: String? = null
+    relativePath: String? = null,
+    write: (ParcelFileDescriptor) -> Boolean
 ): Uri? {
     val filename = "IRIDIS_${SimpleDateFormat("yyyyMMdd_HHmmss", Locale.US).format(Date())}.${format.extension}"
     val contentValues = ContentValues().apply {
@@ -72,30 +80,30 @@ internal fun saveBitmapToPictures(
         }
     }
     val resolver = context.contentResolver
-    val uri = resolver.insert(MediaStore.Images.Media.EXTERNAL_CONTENT_URI, contentValues)
-    if (uri != null) {
-        try {
-            resolver.openOutputStream(uri)?.use { out ->
-                val compressFormat =
-                    when (format) {
-                        ExportImageFormat.Jpeg -> Bitmap.CompressFormat.JPEG
-                        ExportImageFormat.Png -> Bitmap.CompressFormat.PNG
-                        ExportImageFormat.Webp ->
-                            if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.R) Bitmap.CompressFormat.WEBP_LOSSY else Bitmap.CompressFormat.WEBP
-                    }
-                val ok = bitmap.compress(compressFormat, quality.coerceIn(1, 100), out)
-                if (!ok) throw IllegalStateException("Bitmap.compress returned false")
-            }
-            if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.Q) {
-                contentValues.clear()
-                contentValues.put(MediaStore.MediaColumns.IS_PENDING, 0)
-                resolver.update(uri, contentValues, null, null)
-            }
-        } catch (e: Exception) {
-            resolver.delete(uri, null, null)
-            return null
+    val uri = resolver.insert(MediaStore.Images.Media.EXTERNAL_CONTENT_URI, contentValues) ?: return null
+    val pfd = resolver.openFileDescriptor(uri, "rw")
+    if (pfd == null) {
+        resolver.delete(uri, null, null)
+        return null
+    }
+
+    val success = pfd.use { descriptor ->
+        runCatching { write(descriptor) }.getOrElse {
+            Log.e("MediaUtils", "Failed to write image", it)
+            false
         }
     }
+
+    if (!success) {
+        resolver.delete(uri, null, null)
+        return null
+    }
+
+    if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.Q) {
+        contentValues.clear()
+        contentValues.put(MediaStore.MediaColumns.IS_PENDING, 0)
+        resolver.update(uri, contentValues, null, null)
+    }
     return uri
 }
 
@@ -19,10 +19,17 @@ object LibRawDecoder {
     external fun createSession(rawData: ByteArray): Long
     external fun releaseSession(handle: Long)
 
-    external fun decodeFromSession(handle: Long, adjustmentsJson: String): ByteArray?
-    external fun lowlowdecodeFromSession(handle: Long, adjustmentsJson: String): ByteArray?
-    external fun lowdecodeFromSession(handle: Long, adjustmentsJson: String): ByteArray?
+    external fun decodeFromSession(handle: Long, adjustmentsJson: String): NativeRender?
+    external fun lowlowdecodeFromSession(handle: Long, adjustmentsJson: String): NativeRender?
+    external fun lowdecodeFromSession(handle: Long, adjustmentsJson: String): NativeRender?
     external fun decodeFullResFromSession(handle: Long, adjustmentsJson: String): ByteArray?
+    external fun renderMaskFromSession(
+        handle: Long,
+        adjustmentsJson: String,
+        maskId: String,
+        subMaskId: String?,
+        alpha: Boolean
+    ): ByteArray?
 
     external fun exportFromSession(
         handle: Long,
@@ -31,8 +38,25 @@ object LibRawDecoder {
         lowRamMode: Boolean
     ): ByteArray?
 
+    // Streams the export into `fd`, an empty seekable file opened for writing; the caller keeps ownership of it.
+    external fun exportFromSessionToFd(
+        handle: Long,
+        adjustmentsJson: String,
+        maxDimension: Int,
+        lowRamMode: Boolean,
+        fd: Int
+    ): Boolean
+
     external fun getMetadataJsonFromSession(handle: Long): String?
 
+    external fun analyzeUprightFromSession(handle: Long, adjustmentsJson: String): String?
+
+    external fun detectDustSpots(handles: LongArray): String?
+
+    external fun bakeCubeLut(adjustmentsJson: String, size: Int): String?
+
+    external fun computeAutoCrop(adjustmentsJson: String, width: Int, height: Int, aspectRatio: Float): String?
+
     external fun decode(rawData: ByteArray, adjustmentsJson: String): ByteArray?
     external fun lowlowdecode(rawData: ByteArray, adjustmentsJson: String): ByteArray?
     external fun lowdecode(rawData: ByteArray, adjustmentsJson: String): ByteArray?
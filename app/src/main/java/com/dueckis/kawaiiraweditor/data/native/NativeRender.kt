@@ -0,0 +1,7 @@
+package com.dueckis.kawaiiraweditor.data.native
+
+// A session preview render: the JPEG, and its histograms as JSON when the request asked for them.
+class NativeRender(
+    val jpeg: ByteArray,
+    val histogramJson: String?
+)
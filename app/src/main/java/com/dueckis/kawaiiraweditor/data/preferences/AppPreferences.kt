@@ -196,6 +196,24 @@ internal class AppPreferences(context: Context) {
         prefs.edit().putString(KEY_REPLAY_EXPORT_QUALITY, quality.preferenceValue).apply()
     }
 
+    fun isExportStripLocationEnabled(): Boolean = prefs.getBoolean(KEY_EXPORT_STRIP_LOCATION, false)
+
+    fun setExportStripLocationEnabled(enabled: Boolean) {
+        prefs.edit().putBoolean(KEY_EXPORT_STRIP_LOCATION, enabled).apply()
+    }
+
+    fun isExportStripIdentifiersEnabled(): Boolean = prefs.getBoolean(KEY_EXPORT_STRIP_IDENTIFIERS, false)
+
+    fun setExportStripIdentifiersEnabled(enabled: Boolean) {
+        prefs.edit().putBoolean(KEY_EXPORT_STRIP_IDENTIFIERS, enabled).apply()
+    }
+
+    fun isExportCopyrightOnlyEnabled(): Boolean = prefs.getBoolean(KEY_EXPORT_COPYRIGHT_ONLY, false)
+
+    fun setExportCopyrightOnlyEnabled(enabled: Boolean) {
+        prefs.edit().putBoolean(KEY_EXPORT_COPYRIGHT_ONLY, enabled).apply()
+    }
+
     fun ensureDefaultMaskRenameTagsSeeded() {
         if (!prefs.contains(KEY_MASK_RENAME_TAGS)) {
             setMaskRenameTags(DEFAULT_MASK_RENAME_TAGS)
@@ -226,6 +244,9 @@ internal class AppPreferences(context: Context) {
         private const val KEY_TUTORIAL_COMPLETED = "tutorial_completed"
         private const val KEY_AI_ASSISTANCE_LEVEL = "ai_assistance_level"
         private const val KEY_REPLAY_EXPORT_QUALITY = "replay_export_quality"
+        private const val KEY_EXPORT_STRIP_LOCATION = "export_strip_location"
+        private const val KEY_EXPORT_STRIP_IDENTIFIERS = "export_strip_identifiers"
+        private const val KEY_EXPORT_COPYRIGHT_ONLY = "export_copyright_only"
         private const val DEFAULT_IMMICH_LOCAL_EXPORT_RELATIVE_PATH = "Pictures/IRIDIS/Immich"
         private val DEFAULT_MASK_RENAME_TAGS = listOf(
             "Subject",
@@ -1,8 +1,89 @@
 package com.dueckis.kawaiiraweditor.domain
 
+import kotlin.math.ceil
+import kotlin.math.exp
+import kotlin.math.roundToInt
+import org.json.JSONObject
+
 data class HistogramData(
     val red: FloatArray,
     val green: FloatArray,
     val blue: FloatArray,
     val luma: FloatArray
-)
+) {
+    companion object {
+        // Parses the `output` bins of the histogram JSON returned with a native render.
+        fun fromNativeJson(json: String): HistogramData? {
+            val output = runCatching { JSONObject(json).getJSONObject("output") }.getOrNull() ?: return null
+            fun channel(name: String): FloatArray? {
+                val bins = output.optJSONArray(name) ?: return null
+                if (bins.length() != 256) return null
+                return FloatArray(256) { bins.optDouble(it, 0.0).toFloat() }.also {
+                    applyGaussianSmoothing(it, 2.5f)
+                    normalizeHistogramRange(it, 0.99f)
+                }
+            }
+            return HistogramData(
+                red = channel("red") ?: return null,
+                green = channel("green") ?: return null,
+                blue = channel("blue") ?: return null,
+                luma = channel("luma") ?: return null
+            )
+        }
+
+        private fun applyGaussianSmoothing(histogram: FloatArray, sigma: Float) {
+            if (sigma <= 0f) return
+            val kernelRadius = ceil(sigma * 3f).toInt()
+            if (kernelRadius <= 0 || kernelRadius >= histogram.size) return
+
+            val kernelSize = 2 * kernelRadius + 1
+            val kernel = FloatArray(kernelSize)
+            var kernelSum = 0f
+
+            val twoSigmaSq = 2f * sigma * sigma
+            for (i in 0 until kernelSize) {
+                val x = (i - kernelRadius).toFloat()
+                val v = exp((-x * x / twoSigmaSq).toDouble()).toFloat()
+                kernel[i] = v
+                kernelSum += v
+            }
+
+            if (kernelSum > 0f) {
+                for (i in kernel.indices) {
+                    kernel[i] /= kernelSum
+                }
+            }
+
+            val original = histogram.copyOf()
+            val len = histogram.size
+            for (i in 0 until len) {
+                var smoothed = 0f
+                for (k in 0 until kernelSize) {
+                    val offset = k - kernelRadius
+                    val sampleIndex = (i + offset).coerceIn(0, len - 1)
+                    smoothed += original[sampleIndex] * kernel[k]
+                }
+                histogram[i] = smoothed
+            }
+        }
+
+        private fun normalizeHistogramRange(histogram: FloatArray, percentileClip: Float) {
+            if (histogram.isEmpty()) return
+            val sorted = histogram.copyOf()
+            sorted.sort()
+            val clipIndex = ((sorted.size - 1) * percentileClip).roundToInt().coerceIn(0, sorted.size - 1)
+            val maxVal = sorted[clipIndex]
+
+            if (maxVal > 1e-6f) {
+                val scale = 1f / maxVal
+                for (i in histogram.indices) {
+                    histogram[i] = (histogram[i] * scale).coerceAtMost(1f)
+                }
+            } else {
+                for (i in histogram.indices) {
+                    histogram[i] = 0f
+                }
+            }
+        }
+    }
+}
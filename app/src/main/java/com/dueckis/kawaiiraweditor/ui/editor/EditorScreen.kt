@@ -136,7 +136,6 @@ import com.dueckis.kawaiiraweditor.data.native.LibRawDecoder
 import com.dueckis.kawaiiraweditor.data.preferences.AppPreferences
 import com.dueckis.kawaiiraweditor.data.storage.ProjectStorage
 import com.dueckis.kawaiiraweditor.domain.HistogramData
-import com.dueckis.kawaiiraweditor.domain.HistogramUtils
 import com.dueckis.kawaiiraweditor.domain.ai.AiEnvironmentMaskGenerator
 import com.dueckis.kawaiiraweditor.domain.ai.AiSubjectMaskGenerator
 import com.dueckis.kawaiiraweditor.domain.ai.ModelInfo
@@ -145,9 +144,8 @@ import com.dueckis.kawaiiraweditor.domain.ai.U2NetOnnxSegmenter
 import com.dueckis.kawaiiraweditor.domain.ai.missingModels
 import com.dueckis.kawaiiraweditor.domain.editor.EditorHistoryEntry
 import com.dueckis.kawaiiraweditor.ui.editor.components.ExportButton
-import com.dueckis.kawaiiraweditor.ui.editor.controls.AutoCropParams
 import com.dueckis.kawaiiraweditor.ui.editor.controls.EditorControlsContent
-import com.dueckis.kawaiiraweditor.ui.editor.controls.computeMaxCropNormalized
+import com.dueckis.kawaiiraweditor.ui.editor.controls.computeAutoCrop
 import com.dueckis.kawaiiraweditor.ui.editor.masking.newSubMaskState
 import kotlinx.coroutines.CancellationException
 import kotlinx.coroutines.Dispatchers
@@ -1542,17 +1540,9 @@ internal fun EditorScreen(
 
         val w = cropBaseWidthPx ?: return@LaunchedEffect
         val h = cropBaseHeightPx ?: return@LaunchedEffect
-        val baseRatio = w.toFloat().coerceAtLeast(1f) / h.toFloat().coerceAtLeast(1f)
 
         if (adjustments.crop == null) {
-            val auto =
-                computeMaxCropNormalized(
-                    AutoCropParams(
-                        baseAspectRatio = baseRatio,
-                        rotationDegrees = adjustments.rotation,
-                        aspectRatio = adjustments.aspectRatio
-                    )
-                )
+            val auto = computeAutoCrop(adjustments, w, h)
             cropDraft = auto
             if (adjustments.rotation != 0f || adjustments.aspectRatio != null) {
                 applyAdjustmentsPreservingMasks(adjustments.copy(crop = auto))
@@ -1815,8 +1805,8 @@ internal fun EditorScreen(
         }
         val bmp = withContext(renderDispatcher) {
             runCatching { LibRawDecoder.lowdecodeFromSession(handle, json) }.getOrNull()
-                ?.decodeToBitmap()
-                ?: runCatching { LibRawDecoder.decodeFromSession(handle, json) }.getOrNull()?.decodeToBitmap()
+                ?.jpeg?.decodeToBitmap()
+                ?: runCatching { LibRawDecoder.decodeFromSession(handle, json) }.getOrNull()?.jpeg?.decodeToBitmap()
         }
         if (bmp != null) return bmp
 
@@ -2044,17 +2034,6 @@ internal fun EditorScreen(
 
     LaunchedEffect(sessionHandle) { metadataJson = null }
 
-    LaunchedEffect(displayBitmap) {
-        val bmp = displayBitmap ?: run {
-            histogramData = null
-            return@LaunchedEffect
-        }
-        if (bmp.width < 512 && bmp.height < 512) return@LaunchedEffect
-        delay(80)
-        if (displayBitmap !== bmp) return@LaunchedEffect
-        histogramData = withContext(Dispatchers.Default) { HistogramUtils.calculateHistogram(bmp) }
-    }
-
     LaunchedEffect(adjustments, masks, isDraggingMaskHandle, isComparingOriginal) {
         if (isDraggingMaskHandle) return@LaunchedEffect
         if (isCropMode && !isComparingOriginal) return@LaunchedEffect
@@ -2265,8 +2244,8 @@ internal fun EditorScreen(
                 if (!isViewportRequest) {
                     val superLowBitmap =
                         withContext(renderDispatcher) {
-                            val bytes = runCatching { LibRawDecoder.lowlowdecodeFromSession(handle, requestJson) }.getOrNull()
-                            bytes?.decodeToBitmap()
+                            val render = runCatching { LibRawDecoder.lowlowdecodeFromSession(handle, requestJson) }.getOrNull()
+                            render?.jpeg?.decodeToBitmap()
                         }
                     if (superLowBitmap != null) updateBitmapForRequest(version = requestVersion, quality = 0, bitmap = superLowBitmap)
 
@@ -2279,8 +2258,8 @@ internal fun EditorScreen(
 
                 val lowBitmap =
                     withContext(renderDispatcher) {
-                        val bytes = runCatching { LibRawDecoder.lowdecodeFromSession(handle, requestJson) }.getOrNull()
-                        bytes?.decodeToBitmap()
+                        val render = runCatching { LibRawDecoder.lowdecodeFromSession(handle, requestJson) }.getOrNull()
+                        render?.jpeg?.decodeToBitmap()
                     }
                 if (lowBitmap != null) updateBitmapForRequest(version = requestVersion, quality = 1, bitmap = lowBitmap)
 
@@ -2292,14 +2271,29 @@ internal fun EditorScreen(
             }
 
             isLoading = true
+            // The full edited frame brings its histogram along, so it always matches the bitmap.
+            val wantsHistogram = requestTarget == RenderTarget.Edited && requestPreviewRoi == null
+            var histogramJson: String? = null
             val fullBitmap =
                 withContext(renderDispatcher) {
-                    val bytes = runCatching { LibRawDecoder.decodeFromSession(handle, requestJson) }.getOrNull()
-                    bytes?.decodeToBitmap()
+                    val json =
+                        if (wantsHistogram) {
+                            JSONObject(requestJson).apply {
+                                put("preview", (optJSONObject("preview") ?: JSONObject()).put("histogram", true))
+                            }.toString()
+                        } else {
+                            requestJson
+                        }
+                    val render = runCatching { LibRawDecoder.decodeFromSession(handle, json) }.getOrNull()
+                    histogramJson = render?.histogramJson
+                    render?.jpeg?.decodeToBitmap()
                 }
             isLoading = false
 
             val isLatest = requestVersion == renderVersion.get()
+            if (wantsHistogram && isLatest && fullBitmap != null) {
+                histogramData = histogramJson?.let { withContext(Dispatchers.Default) { HistogramData.fromNativeJson(it) } }
+            }
             if (requestTarget == RenderTarget.Edited && requestPreviewRoi == null && isLatest) {
                 errorMessage = if (fullBitmap == null) "Failed to render preview." else null
             }
@@ -2449,7 +2443,7 @@ internal fun EditorScreen(
         val baseJson = AdjustmentState().toJson(emptyList())
         val bytes =
             withContext(renderDispatcher) {
-                runCatching { LibRawDecoder.decodeFromSession(handle, baseJson) }.getOrNull()
+                runCatching { LibRawDecoder.decodeFromSession(handle, baseJson) }.getOrNull()?.jpeg
             } ?: return null
         val decoded = bytes.decodeToBitmap() ?: return null
         aiEnvironmentSourceBitmap = decoded
@@ -2937,17 +2931,8 @@ internal fun EditorScreen(
                             isStraightenActive = isCropMode && isStraightenActive && !isComparingOriginal,
                             onStraightenResult = { rotation ->
                                 beginEditInteraction()
-                                val baseRatio =
-                                    (cropBaseWidthPx?.toFloat()?.coerceAtLeast(1f) ?: 1f) /
-                                        (cropBaseHeightPx?.toFloat()?.coerceAtLeast(1f) ?: 1f)
                                 val autoCrop =
-                                    computeMaxCropNormalized(
-                                        AutoCropParams(
-                                            baseAspectRatio = baseRatio,
-                                            rotationDegrees = rotation,
-                                            aspectRatio = adjustments.aspectRatio
-                                        )
-                                    )
+                                    computeAutoCrop(adjustments.copy(rotation = rotation), cropBaseWidthPx ?: 0, cropBaseHeightPx ?: 0)
                                 cropDraft = autoCrop
                                 rotationDraft = null
                                 isStraightenActive = false
@@ -10,9 +10,8 @@ import android.content.ActivityNotFoundException
 import android.content.ClipData
 import android.content.Context
 import android.content.Intent
-import android.graphics.Bitmap
 import android.net.Uri
-import android.os.Build
+import android.os.ParcelFileDescriptor
 import android.os.SystemClock
 import android.util.Base64
 import androidx.compose.foundation.layout.Arrangement
@@ -44,10 +43,8 @@ import com.dueckis.kawaiiraweditor.data.immich.ImmichUploadResult
 import com.dueckis.kawaiiraweditor.data.immich.addImmichAssetsToAlbum
 import com.dueckis.kawaiiraweditor.data.immich.uploadImmichAsset
 import com.dueckis.kawaiiraweditor.data.media.ExportImageFormat
-import com.dueckis.kawaiiraweditor.data.media.decodeToBitmap
 import com.dueckis.kawaiiraweditor.data.media.exportReplayVideo
-import com.dueckis.kawaiiraweditor.data.media.saveBitmapToPictures
-import com.dueckis.kawaiiraweditor.data.media.saveJpegToPictures
+import com.dueckis.kawaiiraweditor.data.media.saveToPictures
 import com.dueckis.kawaiiraweditor.data.model.AdjustmentState
 import com.dueckis.kawaiiraweditor.data.model.MaskState
 import com.dueckis.kawaiiraweditor.data.native.LibRawDecoder
@@ -57,38 +54,30 @@ import kotlinx.coroutines.CoroutineDispatcher
 import kotlinx.coroutines.Dispatchers
 import kotlinx.coroutines.launch
 import kotlinx.coroutines.withContext
-import java.io.ByteArrayOutputStream
+import org.json.JSONObject
+import java.io.File
 import java.text.SimpleDateFormat
 import java.util.Date
 import java.util.Locale
 
-private fun encodeExportBytes(
-    fullBytes: ByteArray,
-    options: ExportOptions,
-    allowPassthroughJpeg: Boolean
-): ByteArray? {
-    if (allowPassthroughJpeg && options.format == ExportImageFormat.Jpeg && options.quality == 96) return fullBytes
-    val bitmap = fullBytes.decodeToBitmap() ?: return null
-    val output = ByteArrayOutputStream()
-    val compressFormat =
-        when (options.format) {
-            ExportImageFormat.Jpeg -> Bitmap.CompressFormat.JPEG
-            ExportImageFormat.Png -> Bitmap.CompressFormat.PNG
-            ExportImageFormat.Webp ->
-                if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.R) Bitmap.CompressFormat.WEBP_LOSSY
-                else Bitmap.CompressFormat.WEBP
-        }
-    val ok = bitmap.compress(compressFormat, options.quality.coerceIn(1, 100), output)
-    bitmap.recycle()
-    val encoded = if (ok) output.toByteArray() else return null
-    if (encoded.isEmpty()) return null
-    val valid =
-        when (options.format) {
-            ExportImageFormat.Jpeg -> encoded.size >= 2 && encoded[0] == 0xFF.toByte() && encoded[1] == 0xD8.toByte()
-            ExportImageFormat.Png -> encoded.size >= 8 && encoded[0] == 0x89.toByte() && encoded[1] == 0x50.toByte()
-            ExportImageFormat.Webp -> encoded.size >= 12 && encoded.copyOfRange(0, 4).contentEquals("RIFF".toByteArray())
+private fun withExportOptions(editsJson: String, options: ExportOptions): String {
+    val export = JSONObject().apply {
+        put("format", options.format.nativeName)
+        put("quality", options.quality.coerceIn(1, 100))
+        options.resizeMode?.let { mode ->
+            put(
+                "size",
+                JSONObject().apply {
+                    put("mode", mode.nativeName)
+                    put("value", options.resizeValue)
+                    put("dontEnlarge", options.dontEnlarge)
+                }
+            )
         }
-    return if (valid) encoded else null
+        put("sharpening", options.sharpening.nativeName)
+        put("metadata", exportMetadataJson(options.stripLocation, options.stripIdentifiers, options.copyrightOnly))
+    }
+    return JSONObject(editsJson).put("export", export).toString()
 }
 
 private fun buildXmpSidecarForIridis(editsJson: String): ByteArray {
@@ -168,8 +157,12 @@ internal fun ExportButton(
             ExportOptions(
                 format = ExportImageFormat.Jpeg,
                 quality = 90,
-                resizeLongEdgePx = null,
-                dontEnlarge = true
+                resizeMode = null,
+                resizeValue = ExportResizeMode.LongEdge.defaultValue,
+                dontEnlarge = true,
+                stripLocation = appPreferences.isExportStripLocationEnabled(),
+                stripIdentifiers = appPreferences.isExportStripIdentifiersEnabled(),
+                copyrightOnly = appPreferences.isExportCopyrightOnlyEnabled()
             )
         )
     }
@@ -296,45 +289,32 @@ internal fun ExportButton(
                     currentAdjustments.toJson(currentMasks)
                 }
 
-                val maxDim = options.resizeLongEdgePx ?: 0
-
-                val fullBytes = withContext(nativeDispatcher) {
-                    runCatching {
-                        LibRawDecoder.exportFromSession(
-                            sessionHandle,
-                            currentJson,
-                            maxDim,
-                            options.lowRamMode
-                        )
-                    }.getOrNull()
-                }
-
-                if (fullBytes == null) {
-                    onExportComplete(false, "Export failed (Out of Memory). Try Low RAM Mode.")
-                    return@launch
-                }
+                val exportJson = withExportOptions(currentJson, options)
+                // The native side writes the file itself, so the encoded image never lands on the Java heap.
+                fun exportTo(pfd: ParcelFileDescriptor): Boolean =
+                    LibRawDecoder.exportFromSessionToFd(
+                        sessionHandle,
+                        exportJson,
+                        // Sizing travels in the export payload.
+                        0,
+                        options.lowRamMode,
+                        pfd.fd
+                    )
 
                 when (destination) {
                     ExportDestination.Local -> {
-                        val savedUri = withContext(Dispatchers.IO) {
-                            if (options.format == ExportImageFormat.Jpeg && options.quality == 96) {
-                                saveJpegToPictures(context, fullBytes)
-                            } else {
-                                val bitmap = withContext(Dispatchers.Default) {
-                                    fullBytes.decodeToBitmap()
-                                } ?: return@withContext null
-
-                                saveBitmapToPictures(
-                                    context,
-                                    bitmap,
-                                    options.format,
-                                    options.quality
-                                ).also { bitmap.recycle() }
+                        var exportFailed = false
+                        val savedUri = withContext(nativeDispatcher) {
+                            saveToPictures(context, options.format) { pfd ->
+                                exportFailed = true
+                                exportTo(pfd).also { exportFailed = !it }
                             }
                         }
 
                         if (savedUri != null) {
                             onExportComplete(true, "Saved to $savedUri")
+                        } else if (exportFailed) {
+                            onExportComplete(false, "Export failed (Out of Memory). Try Low RAM Mode.")
                         } else {
                             onExportComplete(false, "Export failed: could not save image.")
                         }
@@ -346,21 +326,35 @@ internal fun ExportButton(
                             onExportComplete(false, "Immich is not configured.")
                             return@launch
                         }
-                        val selectedAlbumId = targetImmichAlbumId?.takeIf { it.isNotBlank() }
-                        val exportBytes = withContext(Dispatchers.Default) {
-                            encodeExportBytes(fullBytes, options, allowPassthroughJpeg = false)
+                        val exportFile = File(context.cacheDir, "export_${System.currentTimeMillis()}.${options.format.extension}")
+                        val exported = withContext(nativeDispatcher) {
+                            runCatching {
+                                val mode =
+                                    ParcelFileDescriptor.MODE_READ_WRITE or
+                                        ParcelFileDescriptor.MODE_CREATE or
+                                        ParcelFileDescriptor.MODE_TRUNCATE
+                                ParcelFileDescriptor.open(exportFile, mode).use(::exportTo)
+                            }.getOrDefault(false)
                         }
-                        if (exportBytes == null) {
-                            onExportComplete(false, "Export failed: could not encode image.")
+                        if (!exported) {
+                            exportFile.delete()
+                            onExportComplete(false, "Export failed (Out of Memory). Try Low RAM Mode.")
                             return@launch
                         }
+
+                        val selectedAlbumId = targetImmichAlbumId?.takeIf { it.isNotBlank() }
                         val fileName = buildExportFileName(sourceFileName, options.format)
-                        val upload = uploadImmichAsset(
-                            config = config,
-                            bytes = exportBytes,
-                            fileName = fileName,
-                            mimeType = options.format.mimeType
-                        )
+                        val upload =
+                            try {
+                                uploadImmichAsset(
+                                    config = config,
+                                    file = exportFile,
+                                    fileName = fileName,
+                                    mimeType = options.format.mimeType
+                                )
+                            } finally {
+                                exportFile.delete()
+                            }
                         val uploadedAssetId = upload.assetId
                         if (!uploadedAssetId.isNullOrBlank()) {
                             if (!selectedAlbumId.isNullOrBlank()) {
@@ -438,6 +432,7 @@ internal fun ExportButton(
             },
             onConfirm = { options ->
                 lastOptions = options
+                appPreferences.saveExportMetadataOptions(options)
                 if (isExporting || sessionHandle == 0L) return@ExportOptionsDialog
                 pendingOptions = options
                 val immichConfig = resolveImmichConfig()
@@ -48,3 +48,34 @@ internal fun ExportFormatDropdown(
         }
     }
 }
+
+@Composable
+internal fun <T> ExportOptionDropdown(
+    value: T,
+    options: List<T>,
+    label: (T) -> String,
+    onValueChange: (T) -> Unit,
+    enabled: Boolean
+) {
+    var expanded by remember { mutableStateOf(false) }
+    Box(modifier = Modifier.height(36.dp)) {
+        OutlinedButton(
+            onClick = { expanded = true },
+            modifier = Modifier.height(36.dp),
+            enabled = enabled
+        ) {
+            Text(label(value))
+        }
+        DropdownMenu(expanded = expanded && enabled, onDismissRequest = { expanded = false }) {
+            options.forEach { option ->
+                DropdownMenuItem(
+                    text = { Text(label(option)) },
+                    onClick = {
+                        expanded = false
+                        onValueChange(option)
+                    }
+                )
+            }
+        }
+    }
+}
@@ -1,11 +1,61 @@
 package com.dueckis.kawaiiraweditor.ui.editor.components
 
 import com.dueckis.kawaiiraweditor.data.media.ExportImageFormat
+import com.dueckis.kawaiiraweditor.data.preferences.AppPreferences
+import org.json.JSONObject
 
 internal data class ExportOptions(
     val format: ExportImageFormat,
     val quality: Int,
-    val resizeLongEdgePx: Int?,
+    // Null keeps the full resolution.
+    val resizeMode: ExportResizeMode?,
+    val resizeValue: Int,
     val dontEnlarge: Boolean,
-    val lowRamMode: Boolean = false
+    val sharpening: ExportSharpening = ExportSharpening.None,
+    val lowRamMode: Boolean = false,
+    val stripLocation: Boolean = false,
+    val stripIdentifiers: Boolean = false,
+    val copyrightOnly: Boolean = false
 )
+
+internal enum class ExportResizeMode(
+    val label: String,
+    val unit: String,
+    val nativeName: String,
+    val defaultValue: Int,
+    val valueRange: IntRange
+) {
+    LongEdge("Long edge", "px", "longEdge", 2048, 64..20000),
+    ShortEdge("Short edge", "px", "shortEdge", 1080, 64..20000),
+    Megapixels("Megapixels", "MP", "megapixels", 12, 1..400),
+    Percentage("Percentage", "%", "percentage", 50, 1..400)
+}
+
+internal enum class ExportSharpening(val label: String, val nativeName: String) {
+    None("None", "none"),
+    Screen("Screen", "screen"),
+    Matte("Matte paper", "matte"),
+    Glossy("Glossy paper", "glossy")
+}
+
+// Metadata privacy settings as the native `export.metadata` payload. Bulk exports have no options
+// dialog and use the choices last saved from it.
+internal fun exportMetadataJson(stripLocation: Boolean, stripIdentifiers: Boolean, copyrightOnly: Boolean): JSONObject =
+    JSONObject().apply {
+        put("stripLocation", stripLocation)
+        put("stripIdentifiers", stripIdentifiers)
+        put("copyrightOnly", copyrightOnly)
+    }
+
+internal fun AppPreferences.exportMetadataJson(): JSONObject =
+    exportMetadataJson(
+        stripLocation = isExportStripLocationEnabled(),
+        stripIdentifiers = isExportStripIdentifiersEnabled(),
+        copyrightOnly = isExportCopyrightOnlyEnabled()
+    )
+
+internal fun AppPreferences.saveExportMetadataOptions(options: ExportOptions) {
+    setExportStripLocationEnabled(options.stripLocation)
+    setExportStripIdentifiersEnabled(options.stripIdentifiers)
+    setExportCopyrightOnlyEnabled(options.copyrightOnly)
+}
@@ -45,13 +45,18 @@ internal fun ExportOptionsDialog(
 ) {
     var format by remember(initial) { mutableStateOf(initial.format) }
     var quality by remember(initial) { mutableIntStateOf(initial.quality.coerceIn(1, 100)) }
-    var resizeEnabled by remember(initial) { mutableStateOf(initial.resizeLongEdgePx != null) }
-    var longEdgeText by remember(initial) { mutableStateOf((initial.resizeLongEdgePx ?: 2048).toString()) }
+    var resizeEnabled by remember(initial) { mutableStateOf(initial.resizeMode != null) }
+    var resizeMode by remember(initial) { mutableStateOf(initial.resizeMode ?: ExportResizeMode.LongEdge) }
+    var resizeText by remember(initial) { mutableStateOf(initial.resizeValue.toString()) }
     var dontEnlarge by remember(initial) { mutableStateOf(initial.dontEnlarge) }
+    var sharpening by remember(initial) { mutableStateOf(initial.sharpening) }
     var lowRamMode by remember(initial) { mutableStateOf(initial.lowRamMode) }
+    var stripLocation by remember(initial) { mutableStateOf(initial.stripLocation) }
+    var stripIdentifiers by remember(initial) { mutableStateOf(initial.stripIdentifiers) }
+    var copyrightOnly by remember(initial) { mutableStateOf(initial.copyrightOnly) }
 
-    val longEdgeValue = longEdgeText.toIntOrNull()?.coerceIn(64, 20000)
-    val isLongEdgeValid = !resizeEnabled || longEdgeValue != null
+    val resizeValue = resizeText.toIntOrNull()?.takeIf { it in resizeMode.valueRange }
+    val isResizeValid = !resizeEnabled || resizeValue != null
 
     AlertDialog(
         onDismissRequest = { if (!isLoading) onDismissRequest() },
@@ -91,7 +96,7 @@ internal fun ExportOptionsDialog(
                         )
                     }
 
-                    if (format != ExportImageFormat.Png) {
+                    if (format != ExportImageFormat.Png && format != ExportImageFormat.JpegXl) {
                         Column(verticalArrangement = Arrangement.spacedBy(4.dp)) {
                             Row(modifier = Modifier.fillMaxWidth(), horizontalArrangement = Arrangement.SpaceBetween) {
                                 Text("Quality", style = MaterialTheme.typography.bodyMedium)
@@ -132,15 +137,34 @@ internal fun ExportOptionsDialog(
                     }
 
                     if (resizeEnabled) {
+                        Row(
+                            modifier = Modifier.fillMaxWidth(),
+                            horizontalArrangement = Arrangement.SpaceBetween,
+                            verticalAlignment = Alignment.CenterVertically
+                        ) {
+                            Text("Size by", style = MaterialTheme.typography.bodyMedium)
+                            ExportOptionDropdown(
+                                value = resizeMode,
+                                options = ExportResizeMode.values().toList(),
+                                label = { it.label },
+                                onValueChange = {
+                                    if (it != resizeMode) resizeText = it.defaultValue.toString()
+                                    resizeMode = it
+                                },
+                                enabled = !isLoading
+                            )
+                        }
                         OutlinedTextField(
-                            value = longEdgeText,
-                            onValueChange = { longEdgeText = it.filter(Char::isDigit).take(5) },
+                            value = resizeText,
+                            onValueChange = { resizeText = it.filter(Char::isDigit).take(5) },
                             modifier = Modifier.fillMaxWidth(),
-                            label = { Text("Long edge (px)") },
-                            isError = !isLongEdgeValid,
+                            label = { Text("${resizeMode.label} (${resizeMode.unit})") },
+                            isError = !isResizeValid,
                             enabled = !isLoading,
                             supportingText = {
-                                if (!isLongEdgeValid) Text("Enter a number (e.g. 2048)")
+                                if (!isResizeValid) {
+                                    Text("Enter ${resizeMode.valueRange.first}–${resizeMode.valueRange.last}")
+                                }
                             },
                             keyboardOptions = KeyboardOptions(keyboardType = KeyboardType.Number),
                             singleLine = true
@@ -159,6 +183,61 @@ internal fun ExportOptionsDialog(
                         }
                     }
 
+                    Row(
+                        modifier = Modifier.fillMaxWidth(),
+                        horizontalArrangement = Arrangement.SpaceBetween,
+                        verticalAlignment = Alignment.CenterVertically
+                    ) {
+                        Text("Output sharpening", style = MaterialTheme.typography.bodyMedium)
+                        ExportOptionDropdown(
+                            value = sharpening,
+                            options = ExportSharpening.values().toList(),
+                            label = { it.label },
+                            onValueChange = { sharpening = it },
+                            enabled = !isLoading
+                        )
+                    }
+
+                    Row(
+                        modifier = Modifier.fillMaxWidth(),
+                        horizontalArrangement = Arrangement.SpaceBetween,
+                        verticalAlignment = Alignment.CenterVertically
+                    ) {
+                        Text("Keep only copyright", style = MaterialTheme.typography.bodyMedium)
+                        Switch(
+                            checked = copyrightOnly,
+                            onCheckedChange = { copyrightOnly = it },
+                            enabled = !isLoading
+                        )
+                    }
+
+                    if (!copyrightOnly) {
+                        Row(
+                            modifier = Modifier.fillMaxWidth(),
+                            horizontalArrangement = Arrangement.SpaceBetween,
+                            verticalAlignment = Alignment.CenterVertically
+                        ) {
+                            Text("Remove location", style = MaterialTheme.typography.bodyMedium)
+                            Switch(
+                                checked = stripLocation,
+                                onCheckedChange = { stripLocation = it },
+                                enabled = !isLoading
+                            )
+                        }
+                        Row(
+                            modifier = Modifier.fillMaxWidth(),
+                            horizontalArrangement = Arrangement.SpaceBetween,
+                            verticalAlignment = Alignment.CenterVertically
+                        ) {
+                            Text("Remove serial numbers & owner", style = MaterialTheme.typography.bodyMedium)
+                            Switch(
+                                checked = stripIdentifiers,
+                                onCheckedChange = { stripIdentifiers = it },
+                                enabled = !isLoading
+                            )
+                        }
+                    }
+
                     Row(
                         modifier = Modifier.fillMaxWidth(),
                         horizontalArrangement = Arrangement.SpaceBetween,
@@ -180,15 +259,20 @@ internal fun ExportOptionsDialog(
         },
         confirmButton = {
             TextButton(
-                enabled = isLongEdgeValid && !isLoading,
+                enabled = isResizeValid && !isLoading,
                 onClick = {
                     onConfirm(
                         ExportOptions(
                             format = format,
                             quality = quality.coerceIn(1, 100),
-                            resizeLongEdgePx = if (resizeEnabled) longEdgeValue else null,
+                            resizeMode = if (resizeEnabled) resizeMode else null,
+                            resizeValue = resizeValue ?: resizeMode.defaultValue,
                             dontEnlarge = dontEnlarge,
-                            lowRamMode = lowRamMode
+                            sharpening = sharpening,
+                            lowRamMode = lowRamMode,
+                            stripLocation = stripLocation,
+                            stripIdentifiers = stripIdentifiers,
+                            copyrightOnly = copyrightOnly
                         )
                     )
                 }
@@ -0,0 +1,25 @@
+package com.dueckis.kawaiiraweditor.ui.editor.controls
+
+import com.dueckis.kawaiiraweditor.data.model.AdjustmentState
+import com.dueckis.kawaiiraweditor.data.model.CropState
+import com.dueckis.kawaiiraweditor.data.native.LibRawDecoder
+import org.json.JSONObject
+
+// Largest crop of the state's aspect ratio that stays inside the rotated and keystoned image,
+// computed by the engine with the same geometry it renders. `frameWidth` x `frameHeight` is the
+// image after the state's orientation steps. Falls back to the full frame.
+internal fun computeAutoCrop(state: AdjustmentState, frameWidth: Int, frameHeight: Int): CropState {
+    val full = CropState(0f, 0f, 1f, 1f)
+    if (frameWidth < 2 || frameHeight < 2) return full
+    val json =
+        runCatching {
+            LibRawDecoder.computeAutoCrop(state.copy(crop = null).toJson(), frameWidth, frameHeight, state.aspectRatio ?: 0f)
+        }.getOrNull() ?: return full
+    val crop = runCatching { JSONObject(json) }.getOrNull() ?: return full
+    return CropState(
+        x = crop.optDouble("x", 0.0).toFloat(),
+        y = crop.optDouble("y", 0.0).toFloat(),
+        width = crop.optDouble("width", 1.0).toFloat(),
+        height = crop.optDouble("height", 1.0).toFloat()
+    ).normalized()
+}
@@ -1,7 +0,0 @@
-package com.dueckis.kawaiiraweditor.ui.editor.controls
-
-internal data class AutoCropParams(
-    val baseAspectRatio: Float,
-    val rotationDegrees: Float,
-    val aspectRatio: Float?
-)
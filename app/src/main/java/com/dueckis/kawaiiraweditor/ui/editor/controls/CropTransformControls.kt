@@ -64,15 +64,8 @@ internal fun CropTransformControls(
     val isCustomActive = adjustments.aspectRatio != null && activePreset == null
 
     fun requestAutoCrop(next: AdjustmentState): AdjustmentState {
-        val ratio = originalRatio ?: return next
-        val crop = computeMaxCropNormalized(
-            AutoCropParams(
-                baseAspectRatio = ratio,
-                rotationDegrees = next.rotation,
-                aspectRatio = next.aspectRatio
-            )
-        )
-        return next.copy(crop = crop)
+        if (baseW == null || baseH == null) return next
+        return next.copy(crop = computeAutoCrop(next, baseW, baseH))
     }
 
     // --- Vertical Stack Layout ---
@@ -106,7 +99,8 @@ internal fun CropTransformControls(
         // 3. Geometry Card
         GeometryCard(
             adjustments = adjustments,
-            originalRatio = originalRatio,
+            baseImageWidthPx = baseW,
+            baseImageHeightPx = baseH,
             onAdjustmentsChange = onAdjustmentsChange
         )
     }
@@ -332,14 +326,17 @@ private fun RotationCard(
 @Composable
 private fun GeometryCard(
     adjustments: AdjustmentState,
-    originalRatio: Float?,
+    baseImageWidthPx: Int?,
+    baseImageHeightPx: Int?,
     onAdjustmentsChange: (AdjustmentState) -> Unit
 ) {
+    val frame = if (baseImageWidthPx != null && baseImageHeightPx != null) baseImageWidthPx to baseImageHeightPx else null
     SectionCard(
         title = "GEOMETRY",
         onReset = {
             // Reset orientation steps and flips
-            rotate90(adjustments.copy(orientationSteps = 0, flipHorizontal = false, flipVertical = false), originalRatio, 0, onAdjustmentsChange)
+            val stepsToReset = (4 - adjustments.orientationSteps % 4) % 4
+            rotate90(adjustments.copy(flipHorizontal = false, flipVertical = false), frame, stepsToReset, onAdjustmentsChange)
         }
     ) {
         Column(verticalArrangement = Arrangement.spacedBy(16.dp)) {
@@ -349,10 +346,10 @@ private fun GeometryCard(
                 Text("Rotate 90°", style = MaterialTheme.typography.labelSmall, color = MaterialTheme.colorScheme.onSurfaceVariant)
                 Row(horizontalArrangement = Arrangement.spacedBy(8.dp)) {
                     GeometryButton(Icons.Default.RotateLeft, "Left") {
-                        rotate90(adjustments, originalRatio, 3, onAdjustmentsChange)
+                        rotate90(adjustments, frame, 3, onAdjustmentsChange)
                     }
                     GeometryButton(Icons.Default.RotateRight, "Right") {
-                        rotate90(adjustments, originalRatio, 1, onAdjustmentsChange)
+                        rotate90(adjustments, frame, 1, onAdjustmentsChange)
                     }
                 }
             }
@@ -481,14 +478,16 @@ private fun CompactNumInput(
     )
 }
 
+// `frame` is the current image size after orientation steps.
 private fun rotate90(
     state: AdjustmentState,
-    originalRatio: Float?,
+    frame: Pair<Int, Int>?,
     stepsToAdd: Int,
     onChange: (AdjustmentState) -> Unit
 ) {
     val nextSteps = ((state.orientationSteps % 4) + stepsToAdd) % 4
-    val nextAspect = state.aspectRatio?.takeIf { it.isFinite() && it != 0f }?.let { 1f / it }
+    val nextAspect =
+        if (stepsToAdd % 2 == 1) state.aspectRatio?.takeIf { it.isFinite() && it != 0f }?.let { 1f / it } else state.aspectRatio
 
     val nextState = state.copy(
         orientationSteps = nextSteps,
@@ -496,18 +495,10 @@ private fun rotate90(
         crop = null,
         aspectRatio = nextAspect
     )
-    val nextBaseRatio = originalRatio?.takeIf { it.isFinite() && it > 0f }?.let { 1f / it }
-
-    if (nextBaseRatio == null) {
+    if (frame == null) {
         onChange(nextState)
     } else {
-        val crop = computeMaxCropNormalized(
-            AutoCropParams(
-                baseAspectRatio = nextBaseRatio,
-                rotationDegrees = nextState.rotation,
-                aspectRatio = nextState.aspectRatio
-            )
-        )
-        onChange(nextState.copy(crop = crop))
+        val (w, h) = if (stepsToAdd % 2 == 1) frame.second to frame.first else frame
+        onChange(nextState.copy(crop = computeAutoCrop(nextState, w, h)))
     }
 }
\ No newline at end of file
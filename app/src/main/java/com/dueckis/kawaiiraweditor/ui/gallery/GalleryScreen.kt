@@ -139,6 +139,7 @@ import com.dueckis.kawaiiraweditor.domain.ai.AiEnvironmentMaskGenerator
 import com.dueckis.kawaiiraweditor.domain.ai.ClipAutoTagger
 import com.dueckis.kawaiiraweditor.domain.ai.ModelInfo
 import com.dueckis.kawaiiraweditor.domain.ai.missingModels
+import com.dueckis.kawaiiraweditor.ui.editor.components.exportMetadataJson
 import kotlinx.coroutines.Dispatchers
 import kotlinx.coroutines.asCoroutineDispatcher
 import kotlinx.coroutines.delay
@@ -764,8 +765,14 @@ internal fun GalleryScreen(
                     continue
                 }
 
+                val exportMetadata = appPreferences.exportMetadataJson()
                 val jpegBytes = withContext(Dispatchers.Default) {
-                    runCatching { LibRawDecoder.decodeFullRes(rawBytes, adjustmentsJson) }.getOrNull()
+                    runCatching {
+                        val edits = JSONObject(adjustmentsJson)
+                        val export = edits.optJSONObject("export") ?: JSONObject()
+                        edits.put("export", export.put("metadata", exportMetadata))
+                        LibRawDecoder.decodeFullRes(rawBytes, edits.toString())
+                    }.getOrNull()
                 }
                 if (jpegBytes == null) {
                     failureCount++
@@ -0,0 +1,133 @@
+// Exposure and focus aids for the preview, drawn over the rendered pixels before JPEG encoding
+// (and after the histogram has been taken). Clipping, zebra and false colour read the exact output
+// levels; focus peaking reads the source detail the sharpening blur sees.
+
+use rayon::prelude::*;
+
+use crate::model::{AnalysisMode, AnalysisPayload};
+use crate::{get_luma, linear_to_srgb};
+
+const HIGHLIGHT_COLOR: [u8; 3] = [255, 0, 0];
+const SHADOW_COLOR: [u8; 3] = [0, 90, 255];
+const PEAKING_COLOR: [u8; 3] = [255, 30, 30];
+// Edge contrast in display units needed at the lowest and highest peaking sensitivity.
+const PEAKING_THRESHOLD_LOW: f32 = 0.14;
+const PEAKING_THRESHOLD_HIGH: f32 = 0.02;
+// Diagonal period and darkening of the zebra stripes, in output pixels.
+const ZEBRA_PERIOD: u32 = 8;
+const ZEBRA_STRENGTH: f32 = 0.7;
+// False colour bands as (lowest, highest) output luminance in percent of the sRGB-encoded level.
+// The grey and skin bands sit where sRGB puts 18 % and 36 % reflectance, not at their Rec.709
+// IRE positions. Levels in between show as grey.
+const ZONES: [(f32, f32, [u8; 3]); 6] = [
+    // Crushed blacks.
+    (0.0, 2.5, [120, 0, 160]),
+    (2.5, 4.0, [0, 80, 255]),
+    // Middle grey, about 46 %.
+    (44.0, 48.0, [70, 200, 60]),
+    // One stop over middle grey, about 63 %, where skin tones usually sit.
+    (61.0, 65.0, [255, 140, 180]),
+    (97.0, 99.0, [255, 220, 0]),
+    (99.0, 101.0, [255, 0, 0]),
+];
+
+pub(crate) struct Analysis {
+    mode: AnalysisMode,
+    per_channel: bool,
+    peaking_threshold: f32,
+    zebra_threshold: f32,
+}
+
+impl Analysis {
+    pub(crate) fn new(payload: &AnalysisPayload) -> Option<Self> {
+        if payload.mode == AnalysisMode::None {
+            return None;
+        }
+        let sensitivity = if payload.peaking_sensitivity.is_finite() { payload.peaking_sensitivity } else { 50.0 };
+        let zebra = if payload.zebra_threshold.is_finite() { payload.zebra_threshold } else { 95.0 };
+        let t = sensitivity.clamp(0.0, 100.0) / 100.0;
+        Some(Self {
+            mode: payload.mode,
+            per_channel: payload.per_channel,
+            peaking_threshold: PEAKING_THRESHOLD_LOW + (PEAKING_THRESHOLD_HIGH - PEAKING_THRESHOLD_LOW) * t,
+            zebra_threshold: zebra.clamp(0.0, 100.0),
+        })
+    }
+
+    // Whether `overlay` needs the source luminance and its sharpening blur.
+    pub(crate) fn needs_detail(&self) -> bool {
+        self.mode == AnalysisMode::FocusPeaking
+    }
+
+    // Draws over interleaved output pixels whose top-left pixel is (origin_x, origin_y) of the
+    // full frame. `detail` returns the source luminance and its sharpening blur at a full-frame
+    // position.
+    pub(crate) fn overlay(
+        &self,
+        rgb: &mut [u8],
+        width: u32,
+        origin: (u32, u32),
+        detail: impl Fn(u32, u32) -> Option<(f32, f32)> + Sync,
+    ) {
+        let width = width.max(1) as usize;
+        rgb.par_chunks_mut(width * 3).enumerate().for_each(|(y, row)| {
+            let y = origin.1 + y as u32;
+            for (x, out) in row.chunks_exact_mut(3).enumerate() {
+                let x = origin.0 + x as u32;
+                let pixel = [out[0], out[1], out[2]];
+                if let Some(color) = self.pixel(x, y, pixel, &detail) {
+                    out.copy_from_slice(&color);
+                }
+            }
+        });
+    }
+
+    fn pixel(&self, x: u32, y: u32, p: [u8; 3], detail: impl Fn(u32, u32) -> Option<(f32, f32)>) -> Option<[u8; 3]> {
+        let level = || get_luma(p.map(|v| v as f32)) / 2.55;
+        match self.mode {
+            AnalysisMode::None => None,
+            AnalysisMode::Clipping => self.clipping(p),
+            AnalysisMode::FocusPeaking => {
+                let (luma, blurred) = detail(x, y)?;
+                // Compared in display gamma so the threshold means the same in shadows and highlights.
+                let energy = (linear_to_srgb(luma) - linear_to_srgb(blurred)).abs();
+                (energy >= self.peaking_threshold).then_some(PEAKING_COLOR)
+            }
+            AnalysisMode::FalseColor => {
+                let level = level();
+                let color = ZONES.iter().find(|(low, high, _)| level >= *low && level < *high).map(|z| z.2);
+                Some(color.unwrap_or([(level * 2.55).round().clamp(0.0, 255.0) as u8; 3]))
+            }
+            AnalysisMode::Zebra => {
+                if level() < self.zebra_threshold || (x + y) % ZEBRA_PERIOD >= ZEBRA_PERIOD / 2 {
+                    return None;
+                }
+                Some(p.map(|v| (v as f32 * (1.0 - ZEBRA_STRENGTH)).round() as u8))
+            }
+        }
+    }
+
+    // Hard clipping only: a level has to be at 0 or 255, not just close to it.
+    fn clipping(&self, p: [u8; 3]) -> Option<[u8; 3]> {
+        if !self.per_channel {
+            let luma = get_luma(p.map(|v| v as f32));
+            return if luma >= 254.5 {
+                Some(HIGHLIGHT_COLOR)
+            } else if luma <= 0.5 {
+                Some(SHADOW_COLOR)
+            } else {
+                None
+            };
+        }
+        // Clipped highlight channels show in their own colour, white when all of them clip;
+        // crushed channels show in the complementary colour, blue when all of them are.
+        if p.contains(&255) {
+            return Some(p.map(|v| if v == 255 { 255 } else { 0 }));
+        }
+        match p.iter().filter(|v| **v == 0).count() {
+            0 => None,
+            3 => Some(SHADOW_COLOR),
+            _ => Some(p.map(|v| if v == 0 { 255 } else { 0 })),
+        }
+    }
+}
@@ -0,0 +1,192 @@
+// Largest crop of a given aspect ratio that stays inside the image after keystone and rotation.
+// Uses the same geometry as `TransformState::map_coord`, so the result never samples outside the
+// source: no black slivers at the edges.
+
+use crate::model::{CropPayload, PerspectivePayload};
+use crate::perspective::Homography;
+
+const EPS: f32 = 1.0e-4;
+
+struct Geometry {
+    width: u32,
+    height: u32,
+    perspective: Option<Homography>,
+    // (sin, cos); `None` below the renderer's rotation threshold.
+    rotation: Option<(f32, f32)>,
+}
+
+impl Geometry {
+    fn center(&self) -> (f32, f32) {
+        ((self.width as f32 - 1.0) / 2.0, (self.height as f32 - 1.0) / 2.0)
+    }
+
+    // Uncropped output position -> source position, as in `TransformState::map_coord`.
+    fn inverse(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let (mut fx, mut fy) = (x, y);
+        if let Some((sin, cos)) = self.rotation {
+            let (cx, cy) = self.center();
+            let (dx, dy) = (fx - cx, fy - cy);
+            fx = dx * cos + dy * sin + cx;
+            fy = -dx * sin + dy * cos + cy;
+        }
+        match self.perspective.as_ref() {
+            Some(perspective) => perspective.unwarp(fx, fy),
+            None => Some((fx, fy)),
+        }
+    }
+
+    fn forward(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        let (mut fx, mut fy) = match self.perspective.as_ref() {
+            Some(perspective) => perspective.warp(x, y)?,
+            None => (x, y),
+        };
+        if let Some((sin, cos)) = self.rotation {
+            let (cx, cy) = self.center();
+            let (dx, dy) = (fx - cx, fy - cy);
+            fx = dx * cos - dy * sin + cx;
+            fy = dx * sin + dy * cos + cy;
+        }
+        Some((fx, fy))
+    }
+
+    // Same bounds as the bicubic samplers: pixel centres from 0 to size - 1.
+    fn covers(&self, x: f32, y: f32) -> bool {
+        let max_x = self.width as f32 - 1.0;
+        let max_y = self.height as f32 - 1.0;
+        self.inverse(x, y)
+            .is_some_and(|(sx, sy)| sx >= -EPS && sy >= -EPS && sx <= max_x + EPS && sy <= max_y + EPS)
+    }
+}
+
+fn solve3(m: [[f64; 3]; 3], rhs: [f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(m);
+    if d.abs() < 1.0e-9 {
+        return None;
+    }
+    let mut out = [0f64; 3];
+    for (col, v) in out.iter_mut().enumerate() {
+        let mut mc = m;
+        for row in 0..3 {
+            mc[row][col] = rhs[row];
+        }
+        *v = det(mc) / d;
+    }
+    Some(out)
+}
+
+// `width` x `height` is the frame the crop is expressed in (after orientation steps).
+// `aspect` is width / height of the crop; `None` keeps the frame's aspect. Returns a normalized
+// crop.
+pub(crate) fn max_inscribed_crop(
+    width: u32,
+    height: u32,
+    rotation_degrees: f32,
+    perspective: &PerspectivePayload,
+    aspect: Option<f32>,
+) -> Option<CropPayload> {
+    if width < 2 || height < 2 {
+        return None;
+    }
+    let rad = rotation_degrees.to_radians();
+    let geometry = Geometry {
+        width,
+        height,
+        perspective: Homography::keystone(perspective, width, height),
+        rotation: (rad.is_finite() && rad.abs() > 0.0001).then(|| rad.sin_cos()),
+    };
+    let ratio = aspect.filter(|a| a.is_finite() && *a > 0.0).unwrap_or(width as f32 / height as f32) as f64;
+
+    // The valid area is the source rectangle carried through keystone and rotation: a convex
+    // quadrilateral.
+    let (max_x, max_y) = (width as f32 - 1.0, height as f32 - 1.0);
+    let mut quad = Vec::with_capacity(4);
+    for (x, y) in [(0.0, 0.0), (max_x, 0.0), (max_x, max_y), (0.0, max_y)] {
+        quad.push(geometry.forward(x, y)?);
+    }
+    let area: f32 = (0..4)
+        .map(|i| {
+            let (a, b) = (quad[i], quad[(i + 1) % 4]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum();
+    let sign = if area > 0.0 { 1.0f64 } else { -1.0 };
+
+    // Rectangle at (x, y) sized (ratio * h, h): every corner must be inside every edge, which is
+    // linear in (x, y, h). Maximize h over the vertices of that feasible region.
+    let mut constraints: Vec<([f64; 3], f64)> = Vec::with_capacity(21);
+    for i in 0..4 {
+        let (a, b) = (quad[i], quad[(i + 1) % 4]);
+        let (a, b) = ((a.0 as f64, a.1 as f64), (b.0 as f64, b.1 as f64));
+        // Inside means sign * cross(b - a, p - a) >= 0, i.e. n . p <= d with a unit normal.
+        let len = (b.0 - a.0).hypot(b.1 - a.1).max(1.0e-9);
+        let n = (sign * (b.1 - a.1) / len, -sign * (b.0 - a.0) / len);
+        let d = n.0 * a.0 + n.1 * a.1;
+        for (u, v) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+            constraints.push(([n.0, n.1, n.0 * u * ratio + n.1 * v], d));
+        }
+    }
+    // It must also stay within the output frame.
+    constraints.push(([-1.0, 0.0, 0.0], 0.0));
+    constraints.push(([0.0, -1.0, 0.0], 0.0));
+    constraints.push(([1.0, 0.0, ratio], max_x as f64));
+    constraints.push(([0.0, 1.0, 1.0], max_y as f64));
+    constraints.push(([0.0, 0.0, -1.0], 0.0));
+
+    let (cx, cy) = geometry.center();
+    let (cx, cy) = (cx as f64, cy as f64);
+    let mut best: Option<([f64; 3], f64)> = None;
+    for i in 0..constraints.len() {
+        for j in i + 1..constraints.len() {
+            for k in j + 1..constraints.len() {
+                let (ci, cj, ck) = (constraints[i], constraints[j], constraints[k]);
+                let Some(p) = solve3([ci.0, cj.0, ck.0], [ci.1, cj.1, ck.1]) else {
+                    continue;
+                };
+                let feasible = constraints.iter().all(|(n, d)| n[0] * p[0] + n[1] * p[1] + n[2] * p[2] <= d + 1.0e-6);
+                if !feasible {
+                    continue;
+                }
+                // Among equally large crops, keep the one closest to the centre.
+                let off = (p[0] + ratio * p[2] / 2.0 - cx).hypot(p[1] + p[2] / 2.0 - cy);
+                let better = match best {
+                    None => true,
+                    Some((b, b_off)) => p[2] > b[2] + 1.0e-3 || (p[2] > b[2] - 1.0e-3 && off < b_off),
+                };
+                if better {
+                    best = Some((p, off));
+                }
+            }
+        }
+    }
+    let ([x, y, h], _) = best?;
+    let (x, y, w, h) = (x as f32, y as f32, (ratio * h) as f32, h as f32);
+
+    // Snap inwards to whole pixels so `crop_rect_pixels` reproduces the rectangle exactly.
+    let mut x0 = x.ceil().max(0.0) as u32;
+    let mut y0 = y.ceil().max(0.0) as u32;
+    let mut x1 = ((x + w).floor().min(max_x) as u32).max(x0);
+    let mut y1 = ((y + h).floor().min(max_y) as u32).max(y0);
+    // Guard against rounding in the solve; the corners are what the renderer will sample.
+    while x1 >= x0 + 2 && y1 >= y0 + 2 {
+        let corners = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)];
+        if corners.iter().all(|&(px, py)| geometry.covers(px as f32, py as f32)) {
+            break;
+        }
+        x0 += 1;
+        y0 += 1;
+        x1 -= 1;
+        y1 -= 1;
+    }
+
+    let (fw, fh) = (width as f32, height as f32);
+    Some(CropPayload {
+        x: x0 as f32 / fw,
+        y: y0 as f32 / fh,
+        width: (x1 - x0 + 1) as f32 / fw,
+        height: (y1 - y0 + 1) as f32 / fh,
+    })
+}
@@ -0,0 +1,294 @@
+// Canvas stage for exports: borders, aspect padding and a caption around the finished photo.
+// Photo rows stream through from the tiled renderer; canvas rows are generated around them so
+// the whole output never has to be held in memory.
+
+use anyhow::Result;
+
+use crate::color_space::OutputTransform;
+use crate::metadata::ExportMetadata;
+use crate::model::{BorderUnit, CanvasBackground, CanvasPayload, CaptionPayload};
+use crate::watermark::{load_font, parse_hex_color, rasterize_text};
+
+// Caption line pitch relative to the font size.
+const LINE_SPACING: f32 = 1.4;
+// Blur radius of the backdrop as a fraction of its longer side.
+const BACKDROP_BLUR: f32 = 0.06;
+
+#[derive(Clone, Copy)]
+pub(crate) struct CanvasLayout {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) photo_x: u32,
+    pub(crate) photo_y: u32,
+}
+
+struct CaptionLine {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    coverage: Vec<f32>,
+}
+
+// Small display-encoded copy of the photo, already blurred.
+struct Backdrop {
+    width: u32,
+    height: u32,
+    rgb: Vec<f32>,
+}
+
+pub(crate) struct Canvas {
+    layout: CanvasLayout,
+    photo_w: u32,
+    photo_h: u32,
+    color: [f32; 3],
+    backdrop: Option<Backdrop>,
+    caption: Vec<CaptionLine>,
+    text_color: [f32; 3],
+    // Canvas rows emitted so far.
+    emitted: u32,
+}
+
+pub(crate) fn caption_lines(caption: Option<&CaptionPayload>, metadata: Option<&ExportMetadata>) -> Vec<String> {
+    let Some(caption) = caption else {
+        return Vec::new();
+    };
+    let mut lines: Vec<String> = caption
+        .text
+        .as_deref()
+        .into_iter()
+        .flat_map(str::lines)
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect();
+    if caption.show_exif {
+        lines.extend(metadata.map(ExportMetadata::caption_line).filter(|l| !l.is_empty()));
+    }
+    lines
+}
+
+fn caption_px(caption: &CaptionPayload, photo_w: u32, photo_h: u32) -> f32 {
+    (caption.size.clamp(0.0, 0.25) * photo_w.max(photo_h) as f32).max(1.0)
+}
+
+// Only depends on sizes, so it can also be evaluated at the natural output size when resolving
+// the export size.
+pub(crate) fn layout(payload: &CanvasPayload, photo_w: u32, photo_h: u32, caption_lines: usize) -> CanvasLayout {
+    let long = photo_w.max(photo_h) as f32;
+    let to_px = |v: f32| -> u32 {
+        let v = if v.is_finite() { v.max(0.0) } else { 0.0 };
+        match payload.unit {
+            BorderUnit::Pixels => v.round() as u32,
+            BorderUnit::Percent => (v / 100.0 * long).round() as u32,
+        }
+    };
+    let mut left = to_px(payload.left.unwrap_or(payload.border));
+    let mut right = to_px(payload.right.unwrap_or(payload.border));
+    let mut top = to_px(payload.top.unwrap_or(payload.border));
+    let mut bottom = to_px(payload.bottom.unwrap_or(payload.border));
+
+    if let Some(caption) = payload.caption.as_ref().filter(|_| caption_lines > 0) {
+        // Half a line of space above and below the text.
+        let line = (caption_px(caption, photo_w, photo_h) * LINE_SPACING).ceil();
+        bottom += ((caption_lines as f32 + 1.0) * line) as u32;
+    }
+
+    let ratio = payload.aspect_width / payload.aspect_height;
+    if ratio.is_finite() && ratio > 0.0 {
+        let w = (left + photo_w + right) as f32;
+        let h = (top + photo_h + bottom) as f32;
+        if w / h < ratio {
+            let extra = ((h * ratio).round() - w).max(0.0) as u32;
+            left += extra / 2;
+            right += extra - extra / 2;
+        } else {
+            let extra = ((w / ratio).round() - h).max(0.0) as u32;
+            top += extra / 2;
+            bottom += extra - extra / 2;
+        }
+    }
+
+    CanvasLayout {
+        width: left + photo_w + right,
+        height: top + photo_h + bottom,
+        photo_x: left,
+        photo_y: top,
+    }
+}
+
+// Three box blurs per axis approximate a Gaussian.
+fn blur(rgb: &mut [f32], width: usize, height: usize, radius: usize) {
+    let mut line = Vec::new();
+    for _ in 0..3 {
+        for y in 0..height {
+            line.clear();
+            line.extend_from_slice(&rgb[y * width * 3..(y + 1) * width * 3]);
+            box_pass(&line, width, |x, v| rgb[(y * width + x) * 3..][..3].copy_from_slice(&v), radius);
+        }
+        for x in 0..width {
+            line.clear();
+            line.extend((0..height).flat_map(|y| {
+                let i = (y * width + x) * 3;
+                [rgb[i], rgb[i + 1], rgb[i + 2]]
+            }));
+            box_pass(&line, height, |y, v| rgb[(y * width + x) * 3..][..3].copy_from_slice(&v), radius);
+        }
+    }
+}
+
+fn box_pass(line: &[f32], len: usize, mut store: impl FnMut(usize, [f32; 3]), radius: usize) {
+    let at = |i: i64| {
+        let i = i.clamp(0, len as i64 - 1) as usize * 3;
+        [line[i], line[i + 1], line[i + 2]]
+    };
+    let r = radius as i64;
+    let norm = 1.0 / (2 * radius + 1) as f32;
+    let mut sum = [0f32; 3];
+    for i in -r..=r {
+        let p = at(i);
+        (0..3).for_each(|c| sum[c] += p[c]);
+    }
+    for i in 0..len as i64 {
+        store(i as usize, sum.map(|s| s * norm));
+        let (add, sub) = (at(i + r + 1), at(i - r));
+        (0..3).for_each(|c| sum[c] += add[c] - sub[c]);
+    }
+}
+
+impl Canvas {
+    // `backdrop` is a small display-encoded rendering of the photo cropped to the canvas aspect,
+    // used for the blurred background.
+    pub(crate) fn new(
+        payload: &CanvasPayload,
+        photo_w: u32,
+        photo_h: u32,
+        metadata: Option<&ExportMetadata>,
+        backdrop: Option<(u32, u32, Vec<f32>)>,
+    ) -> Result<Self> {
+        let lines = caption_lines(payload.caption.as_ref(), metadata);
+        let layout = layout(payload, photo_w, photo_h, lines.len());
+
+        let backdrop = backdrop
+            .filter(|_| payload.background == CanvasBackground::Blur)
+            .map(|(width, height, mut rgb)| {
+                let radius = (width.max(height) as f32 * BACKDROP_BLUR).ceil().max(1.0) as usize;
+                blur(&mut rgb, width as usize, height as usize, radius);
+                Backdrop { width, height, rgb }
+            });
+
+        let mut caption = Vec::new();
+        let mut text_color = [0.0; 3];
+        if let Some(payload) = payload.caption.as_ref().filter(|_| !lines.is_empty()) {
+            let font = load_font(payload.font_data_base64.as_deref())?;
+            text_color = parse_hex_color(&payload.text_color).unwrap_or([0.25; 3]);
+            let px = caption_px(payload, photo_w, photo_h);
+            let line = (px * LINE_SPACING).ceil() as u32;
+            let max_w = (layout.width as f32 * 0.95).max(1.0);
+            let mut slot_y = layout.photo_y + photo_h + line / 2;
+            for text in &lines {
+                let (mut width, mut height, mut coverage) = rasterize_text(&font, text, px);
+                if width as f32 > max_w {
+                    // Shrink lines that would run off the canvas.
+                    (width, height, coverage) = rasterize_text(&font, text, px * max_w / width as f32);
+                }
+                caption.push(CaptionLine {
+                    x: (layout.width.saturating_sub(width)) / 2,
+                    y: slot_y + line.saturating_sub(height) / 2,
+                    width,
+                    height,
+                    coverage,
+                });
+                slot_y += line;
+            }
+        }
+
+        Ok(Self {
+            layout,
+            photo_w,
+            photo_h,
+            color: parse_hex_color(&payload.color).unwrap_or([1.0; 3]),
+            backdrop,
+            caption,
+            text_color,
+            emitted: 0,
+        })
+    }
+
+    pub(crate) fn layout(&self) -> CanvasLayout {
+        self.layout
+    }
+
+    fn background(&self, x: u32, y: u32) -> [f32; 3] {
+        let mut out = match self.backdrop.as_ref() {
+            Some(b) => {
+                // Bilinear upsampling; the backdrop is blurred, so nothing finer is needed.
+                let fx = ((x as f32 + 0.5) * b.width as f32 / self.layout.width as f32 - 0.5).max(0.0);
+                let fy = ((y as f32 + 0.5) * b.height as f32 / self.layout.height as f32 - 0.5).max(0.0);
+                let (x0, y0) = ((fx as u32).min(b.width - 1), (fy as u32).min(b.height - 1));
+                let (x1, y1) = ((x0 + 1).min(b.width - 1), (y0 + 1).min(b.height - 1));
+                let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+                let px = |x: u32, y: u32| {
+                    let i = ((y * b.width + x) * 3) as usize;
+                    [b.rgb[i], b.rgb[i + 1], b.rgb[i + 2]]
+                };
+                let (a, c, d, e) = (px(x0, y0), px(x1, y0), px(x0, y1), px(x1, y1));
+                let mut out = [0f32; 3];
+                for i in 0..3 {
+                    let top = a[i] + (c[i] - a[i]) * tx;
+                    let bottom = d[i] + (e[i] - d[i]) * tx;
+                    out[i] = top + (bottom - top) * ty;
+                }
+                out
+            }
+            None => self.color,
+        };
+        for line in &self.caption {
+            if x >= line.x && y >= line.y && x < line.x + line.width && y < line.y + line.height {
+                let alpha = line.coverage[((y - line.y) * line.width + (x - line.x)) as usize];
+                for (v, t) in out.iter_mut().zip(self.text_color) {
+                    *v += (t - *v) * alpha;
+                }
+            }
+        }
+        out
+    }
+
+    fn emit(&mut self, until: u32, photo: &[f32], photo_start: u32, output: &OutputTransform) -> (Vec<f32>, u32) {
+        let start = self.emitted;
+        let width = self.layout.width;
+        let photo_row = self.photo_w as usize * 3;
+        let mut out = Vec::with_capacity(until.saturating_sub(start) as usize * width as usize * 3);
+        for y in start..until {
+            let row = y
+                .checked_sub(self.layout.photo_y)
+                .filter(|&r| r < self.photo_h)
+                .and_then(|r| r.checked_sub(photo_start))
+                .filter(|&r| (r as usize + 1) * photo_row <= photo.len());
+            for x in 0..width {
+                let inside = x >= self.layout.photo_x && x < self.layout.photo_x + self.photo_w;
+                match row {
+                    Some(r) if inside => {
+                        let i = r as usize * photo_row + (x - self.layout.photo_x) as usize * 3;
+                        out.extend_from_slice(&photo[i..i + 3]);
+                    }
+                    _ => out.extend_from_slice(&output.apply(self.background(x, y))),
+                }
+            }
+        }
+        self.emitted = self.emitted.max(until);
+        (out, start)
+    }
+
+    // Takes finished photo rows starting at photo row `start_y` (already in the output colour
+    // space); returns every canvas row up to the band's last row, and the canvas row they start at.
+    pub(crate) fn push(&mut self, band: &[f32], start_y: u32, output: &OutputTransform) -> (Vec<f32>, u32) {
+        let rows = (band.len() / (self.photo_w as usize * 3).max(1)) as u32;
+        self.emit(self.layout.photo_y + start_y + rows, band, start_y, output)
+    }
+
+    // Emits the rows below the photo.
+    pub(crate) fn finish(&mut self, output: &OutputTransform) -> (Vec<f32>, u32) {
+        self.emit(self.layout.height, &[], self.photo_h, output)
+    }
+}
@@ -0,0 +1,403 @@
+use crate::model::OutputColorSpace;
+use crate::{linear_to_srgb, srgb_to_linear};
+
+type Mat3 = [[f64; 3]; 3];
+
+const D65: (f64, f64) = (0.3127, 0.3290);
+const D50: (f64, f64) = (0.3457, 0.3585);
+
+// ICC PCS illuminant (D50) as stored in profile headers and white point tags.
+const PCS_D50_XYZ: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+const BRADFORD: Mat3 = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+struct Primaries {
+    red: (f64, f64),
+    green: (f64, f64),
+    blue: (f64, f64),
+    white: (f64, f64),
+}
+
+#[derive(Clone, Copy)]
+enum TransferCurve {
+    Srgb,
+    Gamma(f32),
+    // ROMM RGB: gamma 1.8 with a linear toe below 1/512.
+    ProPhoto,
+}
+
+fn primaries(space: OutputColorSpace) -> Primaries {
+    match space {
+        OutputColorSpace::Srgb => Primaries {
+            red: (0.64, 0.33),
+            green: (0.30, 0.60),
+            blue: (0.15, 0.06),
+            white: D65,
+        },
+        OutputColorSpace::DisplayP3 => Primaries {
+            red: (0.680, 0.320),
+            green: (0.265, 0.690),
+            blue: (0.150, 0.060),
+            white: D65,
+        },
+        OutputColorSpace::AdobeRgb => Primaries {
+            red: (0.64, 0.33),
+            green: (0.21, 0.71),
+            blue: (0.15, 0.06),
+            white: D65,
+        },
+        OutputColorSpace::ProPhoto => Primaries {
+            red: (0.7347, 0.2653),
+            green: (0.1596, 0.8404),
+            blue: (0.0366, 0.0001),
+            white: D50,
+        },
+    }
+}
+
+// Red, green, blue and white chromaticities for containers that signal primaries directly.
+pub(crate) fn chromaticities(space: OutputColorSpace) -> [(f64, f64); 4] {
+    let p = primaries(space);
+    [p.red, p.green, p.blue, p.white]
+}
+
+fn transfer_curve(space: OutputColorSpace) -> TransferCurve {
+    match space {
+        OutputColorSpace::Srgb | OutputColorSpace::DisplayP3 => TransferCurve::Srgb,
+        OutputColorSpace::AdobeRgb => TransferCurve::Gamma(563.0 / 256.0),
+        OutputColorSpace::ProPhoto => TransferCurve::ProPhoto,
+    }
+}
+
+fn mat_mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut out = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            out[i][j] = a[i][0] * b[0][j] + a[i][1] * b[1][j] + a[i][2] * b[2][j];
+        }
+    }
+    out
+}
+
+fn mat_vec(m: &Mat3, v: [f64; 3]) -> [f64; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+fn mat_inverse(m: &Mat3) -> Mat3 {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    let inv_det = 1.0 / det;
+    [
+        [
+            (m[1][1] * m[2][2] - m[1][2] * m[2][1]) * inv_det,
+            (m[0][2] * m[2][1] - m[0][1] * m[2][2]) * inv_det,
+            (m[0][1] * m[1][2] - m[0][2] * m[1][1]) * inv_det,
+        ],
+        [
+            (m[1][2] * m[2][0] - m[1][0] * m[2][2]) * inv_det,
+            (m[0][0] * m[2][2] - m[0][2] * m[2][0]) * inv_det,
+            (m[0][2] * m[1][0] - m[0][0] * m[1][2]) * inv_det,
+        ],
+        [
+            (m[1][0] * m[2][1] - m[1][1] * m[2][0]) * inv_det,
+            (m[0][1] * m[2][0] - m[0][0] * m[2][1]) * inv_det,
+            (m[0][0] * m[1][1] - m[0][1] * m[1][0]) * inv_det,
+        ],
+    ]
+}
+
+fn xy_to_xyz(xy: (f64, f64)) -> [f64; 3] {
+    [xy.0 / xy.1, 1.0, (1.0 - xy.0 - xy.1) / xy.1]
+}
+
+fn rgb_to_xyz_matrix(p: &Primaries) -> Mat3 {
+    let r = xy_to_xyz(p.red);
+    let g = xy_to_xyz(p.green);
+    let b = xy_to_xyz(p.blue);
+    let m = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+    let s = mat_vec(&mat_inverse(&m), xy_to_xyz(p.white));
+    [
+        [m[0][0] * s[0], m[0][1] * s[1], m[0][2] * s[2]],
+        [m[1][0] * s[0], m[1][1] * s[1], m[1][2] * s[2]],
+        [m[2][0] * s[0], m[2][1] * s[1], m[2][2] * s[2]],
+    ]
+}
+
+fn bradford_adaptation(src_white: [f64; 3], dst_white: [f64; 3]) -> Mat3 {
+    let src_cone = mat_vec(&BRADFORD, src_white);
+    let dst_cone = mat_vec(&BRADFORD, dst_white);
+    let scale = [
+        [dst_cone[0] / src_cone[0], 0.0, 0.0],
+        [0.0, dst_cone[1] / src_cone[1], 0.0],
+        [0.0, 0.0, dst_cone[2] / src_cone[2]],
+    ];
+    mat_mul(&mat_inverse(&BRADFORD), &mat_mul(&scale, &BRADFORD))
+}
+
+// RGB -> XYZ relative to the D50 PCS, which is what ICC colorant tags expect.
+fn rgb_to_pcs_matrix(space: OutputColorSpace) -> Mat3 {
+    let p = primaries(space);
+    let to_xyz = rgb_to_xyz_matrix(&p);
+    if p.white == D50 {
+        return to_xyz;
+    }
+    let adapt = bradford_adaptation(xy_to_xyz(p.white), PCS_D50_XYZ);
+    mat_mul(&adapt, &to_xyz)
+}
+
+fn encode_transfer(curve: TransferCurve, linear: f32) -> f32 {
+    let v = linear.clamp(0.0, 1.0);
+    match curve {
+        TransferCurve::Srgb => {
+            if v <= 0.0031308 {
+                12.92 * v
+            } else {
+                1.055 * v.powf(1.0 / 2.4) - 0.055
+            }
+        }
+        TransferCurve::Gamma(gamma) => v.powf(1.0 / gamma),
+        TransferCurve::ProPhoto => {
+            if v < 1.0 / 512.0 {
+                16.0 * v
+            } else {
+                v.powf(1.0 / 1.8)
+            }
+        }
+    }
+}
+
+fn decode_transfer(curve: TransferCurve, encoded: f64) -> f64 {
+    let v = encoded.clamp(0.0, 1.0);
+    match curve {
+        TransferCurve::Srgb => {
+            if v <= 0.04045 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        }
+        TransferCurve::Gamma(gamma) => v.powf(gamma as f64),
+        TransferCurve::ProPhoto => {
+            if v < 16.0 / 512.0 {
+                v / 16.0
+            } else {
+                v.powf(1.8)
+            }
+        }
+    }
+}
+
+// Converts the pipeline's display values into the requested output space. For wide spaces those
+// are extended sRGB: colours outside sRGB keep their negative components, sign-mirrored through
+// the sRGB curve, so the only clip happens here, in the target space.
+pub(crate) struct OutputTransform {
+    identity: bool,
+    matrix: [[f32; 3]; 3],
+    curve: TransferCurve,
+}
+
+impl OutputTransform {
+    pub fn new(space: OutputColorSpace) -> Self {
+        let identity = matches!(space, OutputColorSpace::Srgb);
+        let working_to_xyz = rgb_to_xyz_matrix(&primaries(OutputColorSpace::Srgb));
+        let target = primaries(space);
+        let mut to_target_xyz = working_to_xyz;
+        if target.white != D65 {
+            let adapt = bradford_adaptation(xy_to_xyz(D65), xy_to_xyz(target.white));
+            to_target_xyz = mat_mul(&adapt, &working_to_xyz);
+        }
+        let m = mat_mul(&mat_inverse(&rgb_to_xyz_matrix(&target)), &to_target_xyz);
+        let mut matrix = [[0.0f32; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                matrix[i][j] = m[i][j] as f32;
+            }
+        }
+        Self {
+            identity,
+            matrix,
+            curve: transfer_curve(space),
+        }
+    }
+
+    // Whether colours outside sRGB survive to the output.
+    pub fn wide(&self) -> bool {
+        !self.identity
+    }
+
+    // Display-encodes tone-mapped linear sRGB for the display-referred stages.
+    #[inline]
+    pub fn encode(&self, linear: [f32; 3]) -> [f32; 3] {
+        if self.identity {
+            return linear.map(linear_to_srgb);
+        }
+        linear.map(|v| v.signum() * linear_to_srgb(v.abs()))
+    }
+
+    #[inline]
+    pub fn apply(&self, srgb: [f32; 3]) -> [f32; 3] {
+        if self.identity {
+            return srgb;
+        }
+        let lin = srgb.map(|v| v.signum() * srgb_to_linear(v.abs()));
+        let m = &self.matrix;
+        [
+            encode_transfer(self.curve, m[0][0] * lin[0] + m[0][1] * lin[1] + m[0][2] * lin[2]),
+            encode_transfer(self.curve, m[1][0] * lin[0] + m[1][1] * lin[1] + m[1][2] * lin[2]),
+            encode_transfer(self.curve, m[2][0] * lin[0] + m[2][1] * lin[1] + m[2][2] * lin[2]),
+        ]
+    }
+}
+
+fn profile_description(space: OutputColorSpace) -> &'static str {
+    match space {
+        OutputColorSpace::Srgb => "sRGB IEC61966-2.1",
+        OutputColorSpace::DisplayP3 => "Display P3",
+        OutputColorSpace::AdobeRgb => "Compatible with Adobe RGB (1998)",
+        OutputColorSpace::ProPhoto => "ProPhoto RGB (ROMM)",
+    }
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+fn xyz_tag(xyz: [f64; 3]) -> Vec<u8> {
+    let mut tag = Vec::with_capacity(20);
+    tag.extend_from_slice(b"XYZ ");
+    tag.extend_from_slice(&[0; 4]);
+    for v in xyz {
+        tag.extend_from_slice(&s15_fixed16(v));
+    }
+    tag
+}
+
+fn curve_tag(curve: TransferCurve) -> Vec<u8> {
+    let mut tag = Vec::new();
+    tag.extend_from_slice(b"curv");
+    tag.extend_from_slice(&[0; 4]);
+    match curve {
+        TransferCurve::Gamma(gamma) => {
+            tag.extend_from_slice(&1u32.to_be_bytes());
+            // u8Fixed8Number
+            let fixed = (gamma as f64 * 256.0).round() as u16;
+            tag.extend_from_slice(&fixed.to_be_bytes());
+        }
+        TransferCurve::Srgb | TransferCurve::ProPhoto => {
+            const ENTRIES: u32 = 1024;
+            tag.extend_from_slice(&ENTRIES.to_be_bytes());
+            for i in 0..ENTRIES {
+                let encoded = i as f64 / (ENTRIES - 1) as f64;
+                let linear = decode_transfer(curve, encoded);
+                let value = (linear * 65535.0).round().clamp(0.0, 65535.0) as u16;
+                tag.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+    tag
+}
+
+fn text_description_tag(text: &str) -> Vec<u8> {
+    let mut tag = Vec::new();
+    tag.extend_from_slice(b"desc");
+    tag.extend_from_slice(&[0; 4]);
+    tag.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    // Empty Unicode and ScriptCode descriptions.
+    tag.extend_from_slice(&[0; 4]);
+    tag.extend_from_slice(&[0; 4]);
+    tag.extend_from_slice(&[0; 2]);
+    tag.push(0);
+    tag.extend_from_slice(&[0; 67]);
+    tag
+}
+
+fn text_tag(text: &str) -> Vec<u8> {
+    let mut tag = Vec::new();
+    tag.extend_from_slice(b"text");
+    tag.extend_from_slice(&[0; 4]);
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    tag
+}
+
+// Builds a minimal ICC v2.1 matrix/TRC display profile for the output space.
+pub(crate) fn icc_profile(space: OutputColorSpace) -> Vec<u8> {
+    let pcs = rgb_to_pcs_matrix(space);
+    let curve = curve_tag(transfer_curve(space));
+
+    // Shared TRC data is referenced by all three channel tags.
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", text_description_tag(profile_description(space))),
+        (b"cprt", text_tag("No copyright, use freely")),
+        (b"wtpt", xyz_tag(PCS_D50_XYZ)),
+        (b"rXYZ", xyz_tag([pcs[0][0], pcs[1][0], pcs[2][0]])),
+        (b"gXYZ", xyz_tag([pcs[0][1], pcs[1][1], pcs[2][1]])),
+        (b"bXYZ", xyz_tag([pcs[0][2], pcs[1][2], pcs[2][2]])),
+        (b"rTRC", curve),
+    ];
+
+    let tag_count = tags.len() as u32 + 2;
+    let table_len = 4 + 12 * tag_count as usize;
+    let mut offset = 128 + table_len;
+    let mut table = Vec::with_capacity(table_len);
+    let mut data = Vec::new();
+    table.extend_from_slice(&tag_count.to_be_bytes());
+    let mut trc_entry = (0u32, 0u32);
+    for (sig, bytes) in &tags {
+        table.extend_from_slice(*sig);
+        table.extend_from_slice(&(offset as u32).to_be_bytes());
+        table.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        if *sig == b"rTRC" {
+            trc_entry = (offset as u32, bytes.len() as u32);
+        }
+        data.extend_from_slice(bytes);
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        offset = 128 + table_len + data.len();
+    }
+    for sig in [b"gTRC", b"bTRC"] {
+        table.extend_from_slice(sig);
+        table.extend_from_slice(&trc_entry.0.to_be_bytes());
+        table.extend_from_slice(&trc_entry.1.to_be_bytes());
+    }
+
+    let total = 128 + table.len() + data.len();
+    let mut profile = Vec::with_capacity(total);
+    profile.extend_from_slice(&(total as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // preferred CMM
+    profile.extend_from_slice(&0x0210_0000u32.to_be_bytes());
+    profile.extend_from_slice(b"mntr");
+    profile.extend_from_slice(b"RGB ");
+    profile.extend_from_slice(b"XYZ ");
+    for v in [2024u16, 1, 1, 0, 0, 0] {
+        profile.extend_from_slice(&v.to_be_bytes());
+    }
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 4]); // platform
+    profile.extend_from_slice(&[0; 4]); // flags
+    profile.extend_from_slice(&[0; 4]); // manufacturer
+    profile.extend_from_slice(&[0; 4]); // model
+    profile.extend_from_slice(&[0; 8]); // attributes
+    profile.extend_from_slice(&0u32.to_be_bytes()); // perceptual intent
+    for v in PCS_D50_XYZ {
+        profile.extend_from_slice(&s15_fixed16(v));
+    }
+    profile.extend_from_slice(&[0; 4]); // creator
+    profile.resize(128, 0);
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
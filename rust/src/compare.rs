@@ -0,0 +1,102 @@
+// Before/after comparison previews. The current adjustments are rendered in full, so histograms
+// and analysis stay those of the edit; the baseline only renders the pixels it shows, and both go
+// out as a single JPEG. The baseline is developed from the same cached source with its own
+// retouching and eye corrections, but framed with the current geometry so the halves line up.
+
+use log::error;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::model::{AdjustmentsPayload, CompareMode, ComparePayload, PreviewPayload};
+
+// Adjustments of a comparison baseline; defaults when absent or invalid.
+pub(crate) fn parse_baseline(value: Option<&Value>) -> AdjustmentsPayload {
+    match value {
+        Some(value) => AdjustmentsPayload::deserialize(value).unwrap_or_else(|err| {
+            error!("Failed to parse comparison baseline: {}", err);
+            AdjustmentsPayload::default()
+        }),
+        None => AdjustmentsPayload::default(),
+    }
+}
+
+pub(crate) struct Comparison {
+    mode: CompareMode,
+    position: f32,
+    horizontal: bool,
+    pub(crate) baseline: AdjustmentsPayload,
+}
+
+impl Comparison {
+    // `baseline` is the parsed `compare.baseline`, which the session keeps between renders.
+    pub(crate) fn new(current: &AdjustmentsPayload, baseline: &AdjustmentsPayload) -> Option<Self> {
+        let compare = &current.preview.compare;
+        if compare.mode == CompareMode::None {
+            return None;
+        }
+        let mut baseline = AdjustmentsPayload {
+            rotation: current.rotation,
+            flip_horizontal: current.flip_horizontal,
+            flip_vertical: current.flip_vertical,
+            orientation_steps: current.orientation_steps,
+            crop: current.crop.clone(),
+            perspective: current.perspective.clone(),
+            boundary_fill: current.boundary_fill,
+            ..baseline.clone()
+        };
+        // Analysis overlays apply to both sides alike.
+        baseline.preview = PreviewPayload { compare: ComparePayload::default(), ..current.preview.clone() };
+        Some(Self {
+            mode: compare.mode,
+            position: if compare.position.is_finite() { compare.position.clamp(0.0, 1.0) } else { 0.5 },
+            horizontal: compare.horizontal,
+            baseline,
+        })
+    }
+
+    fn split(&self, size: u32) -> u32 {
+        ((self.position * size as f32).round() as u32).min(size)
+    }
+
+    // Part (x, y, width, height) of a `width` x `height` render that the baseline has to cover.
+    pub(crate) fn baseline_window(&self, width: u32, height: u32) -> [u32; 4] {
+        match (self.mode, self.horizontal) {
+            (CompareMode::Split, false) => [0, 0, self.split(width), height],
+            (CompareMode::Split, true) => [0, 0, width, self.split(height)],
+            _ => [0, 0, width, height],
+        }
+    }
+
+    // Combines the baseline and current renders, both `width` x `height` interleaved RGB, into the
+    // comparison image and its size.
+    pub(crate) fn compose(&self, before: Vec<u8>, mut after: Vec<u8>, width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+        let stride = width as usize * 3;
+        match (self.mode, self.horizontal) {
+            (CompareMode::Split, false) => {
+                let n = self.split(width) as usize * 3;
+                for (dst, src) in after.chunks_exact_mut(stride).zip(before.chunks_exact(stride)) {
+                    dst[..n].copy_from_slice(&src[..n]);
+                }
+                (after, width, height)
+            }
+            (CompareMode::Split, true) => {
+                let n = self.split(height) as usize * stride;
+                after[..n].copy_from_slice(&before[..n]);
+                (after, width, height)
+            }
+            (CompareMode::SideBySide, true) => {
+                let mut out = before;
+                out.extend_from_slice(&after);
+                (out, width, height * 2)
+            }
+            _ => {
+                let mut out = Vec::with_capacity(before.len() * 2);
+                for (left, right) in before.chunks_exact(stride).zip(after.chunks_exact(stride)) {
+                    out.extend_from_slice(left);
+                    out.extend_from_slice(right);
+                }
+                (out, width * 2, height)
+            }
+        }
+    }
+}
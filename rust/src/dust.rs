@@ -0,0 +1,298 @@
+// Sensor dust detection: small, soft, roughly circular darkenings on otherwise smooth areas.
+// Frames from the same camera are compared in sensor coordinates, where dust stays put while the
+// scene moves, so a batch only keeps spots that recur.
+
+use rawler::decoders::Orientation;
+
+use crate::{box_blur_f32, get_luma, LinearImage};
+
+// Box radius of the local background estimate, in analysis pixels.
+const BACKGROUND_RADIUS: usize = 20;
+// Darkening limits in log luminance: about 1.2% to 40% darker than the surroundings.
+const MIN_DEPTH: f32 = 0.012;
+const MAX_DEPTH: f32 = 0.5;
+// Half-depth radius limits in analysis pixels.
+const MIN_RADIUS: f32 = 0.8;
+const MAX_RADIUS: f32 = 14.0;
+// Depth relative to the texture around the spot; dust only shows on smooth areas.
+const MIN_CONTRAST: f32 = 4.0;
+// Longest over shortest axis of the spot.
+const MAX_ELONGATION: f32 = 2.0;
+const MAX_CANDIDATES: usize = 64;
+
+pub(crate) struct DustCandidate {
+    // Centre and healing radius in pixels of the analysed image.
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) radius: f32,
+    // Clean patch of similar brightness to heal from.
+    pub(crate) source_x: f32,
+    pub(crate) source_y: f32,
+    pub(crate) confidence: f32,
+}
+
+struct Maps {
+    width: usize,
+    height: usize,
+    // Log luminance minus its local background, lightly smoothed.
+    detail: Vec<f32>,
+    background: Vec<f32>,
+}
+
+impl Maps {
+    fn at(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    // Standard deviation of `detail` in the ring between `inner` and `outer` around (cx, cy).
+    fn ring_std(&self, cx: f32, cy: f32, inner: f32, outer: f32) -> Option<f32> {
+        let (x0, x1) = ((cx - outer).floor().max(0.0) as usize, ((cx + outer).ceil() as usize).min(self.width - 1));
+        let (y0, y1) = ((cy - outer).floor().max(0.0) as usize, ((cy + outer).ceil() as usize).min(self.height - 1));
+        let (mut sum, mut sum_sq, mut count) = (0f32, 0f32, 0f32);
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let d = (x as f32 - cx).hypot(y as f32 - cy);
+                if d >= inner && d <= outer {
+                    let v = self.detail[self.at(x, y)];
+                    sum += v;
+                    sum_sq += v * v;
+                    count += 1.0;
+                }
+            }
+        }
+        if count < 8.0 {
+            return None;
+        }
+        let mean = sum / count;
+        Some((sum_sq / count - mean * mean).max(0.0).sqrt())
+    }
+}
+
+fn build_maps(image: &LinearImage) -> Maps {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let log_luma: Vec<f32> = image.pixels().map(|p| get_luma(p.0).max(1.0e-4).ln()).collect();
+    // Two box passes are smooth enough and barely see a spot a fraction of the window's size.
+    let background = box_blur_f32(&box_blur_f32(&log_luma, width, height, BACKGROUND_RADIUS), width, height, BACKGROUND_RADIUS);
+    let detail: Vec<f32> = log_luma.iter().zip(&background).map(|(l, b)| l - b).collect();
+    let detail = box_blur_f32(&detail, width, height, 1);
+    Maps { width, height, detail, background }
+}
+
+// Grows the spot around a local minimum down to half its depth. `None` when it is too large,
+// too small or too elongated to be dust.
+fn measure(maps: &Maps, seed: usize, claimed: &mut [bool]) -> Option<(f32, f32, f32)> {
+    let (w, h) = (maps.width, maps.height);
+    let threshold = maps.detail[seed] * 0.5;
+    let limit = (MAX_RADIUS * 2.0) as usize;
+    let (seed_x, seed_y) = (seed % w, seed / w);
+
+    let mut stack = vec![seed];
+    let mut region = Vec::new();
+    claimed[seed] = true;
+    while let Some(i) = stack.pop() {
+        region.push(i);
+        let (x, y) = (i % w, i / w);
+        if x.abs_diff(seed_x) > limit || y.abs_diff(seed_y) > limit || region.len() > limit * limit {
+            return None;
+        }
+        let neighbours = [
+            (x > 0).then(|| i - 1),
+            (x + 1 < w).then(|| i + 1),
+            (y > 0).then(|| i - w),
+            (y + 1 < h).then(|| i + w),
+        ];
+        for n in neighbours.into_iter().flatten() {
+            if !claimed[n] && maps.detail[n] < threshold {
+                claimed[n] = true;
+                stack.push(n);
+            }
+        }
+    }
+
+    let area = region.len() as f32;
+    let (mut mx, mut my) = (0f32, 0f32);
+    for &i in &region {
+        mx += (i % w) as f32;
+        my += (i / w) as f32;
+    }
+    let (mx, my) = (mx / area, my / area);
+    let (mut sxx, mut syy, mut sxy, mut far) = (0f32, 0f32, 0f32, 0f32);
+    for &i in &region {
+        let (dx, dy) = ((i % w) as f32 - mx, (i / w) as f32 - my);
+        sxx += dx * dx;
+        syy += dy * dy;
+        sxy += dx * dy;
+        far = far.max(dx.hypot(dy));
+    }
+    let (sxx, syy, sxy) = (sxx / area, syy / area, sxy / area);
+    let spread = ((sxx - syy) * (sxx - syy) / 4.0 + sxy * sxy).sqrt();
+    let (major, minor) = ((sxx + syy) / 2.0 + spread, ((sxx + syy) / 2.0 - spread).max(1.0e-3));
+    let elongation = (major / minor).sqrt();
+
+    let radius = (area / std::f32::consts::PI).sqrt();
+    // A round blob fills most of the disc through its farthest pixel.
+    let fill = area / (std::f32::consts::PI * (far + 0.5) * (far + 0.5));
+    if !(MIN_RADIUS..=MAX_RADIUS).contains(&radius) || elongation > MAX_ELONGATION || fill < 0.4 {
+        return None;
+    }
+    Some((mx, my, radius))
+}
+
+// Nearby patch of matching brightness and little texture, avoiding other darkenings.
+fn pick_source(maps: &Maps, x: f32, y: f32, radius: f32) -> (f32, f32) {
+    let target = maps.background[maps.at(x as usize, y as usize)];
+    let distance = radius * 2.5;
+    let mut best = (x + distance, y, f32::INFINITY);
+    for step in 0..8 {
+        let angle = step as f32 * std::f32::consts::FRAC_PI_4;
+        let (sx, sy) = (x + angle.cos() * distance, y + angle.sin() * distance);
+        if sx < radius || sy < radius || sx > (maps.width - 1) as f32 - radius || sy > (maps.height - 1) as f32 - radius {
+            continue;
+        }
+        let i = maps.at(sx as usize, sy as usize);
+        let Some(texture) = maps.ring_std(sx, sy, 0.0, radius) else {
+            continue;
+        };
+        let mut cost = (maps.background[i] - target).abs() * 4.0 + texture;
+        if maps.detail[i] < -MIN_DEPTH {
+            cost += 1.0;
+        }
+        if cost < best.2 {
+            best = (sx, sy, cost);
+        }
+    }
+    (best.0, best.1)
+}
+
+pub(crate) fn detect(image: &LinearImage) -> Vec<DustCandidate> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    if width < 4 * BACKGROUND_RADIUS || height < 4 * BACKGROUND_RADIUS {
+        return Vec::new();
+    }
+    let maps = build_maps(image);
+    let margin = BACKGROUND_RADIUS / 2;
+
+    // Local minima deep enough to matter, deepest first.
+    let mut seeds = Vec::new();
+    for y in margin..height - margin {
+        for x in margin..width - margin {
+            let i = maps.at(x, y);
+            let d = maps.detail[i];
+            if !(-MAX_DEPTH..=-MIN_DEPTH).contains(&d) {
+                continue;
+            }
+            // Black and near-clipped areas have no usable contrast.
+            let level = maps.background[i].exp();
+            if !(0.01..=0.9).contains(&level) {
+                continue;
+            }
+            let minimum = (y - 1..=y + 1)
+                .flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+                .all(|(nx, ny)| maps.detail[maps.at(nx, ny)] >= d);
+            if minimum {
+                seeds.push(i);
+            }
+        }
+    }
+    seeds.sort_unstable_by(|a, b| maps.detail[*a].total_cmp(&maps.detail[*b]));
+
+    let mut claimed = vec![false; width * height];
+    let mut found = Vec::new();
+    for seed in seeds {
+        if claimed[seed] {
+            continue;
+        }
+        let depth = -maps.detail[seed];
+        let Some((x, y, radius)) = measure(&maps, seed, &mut claimed) else {
+            continue;
+        };
+        let Some(texture) = maps.ring_std(x, y, radius * 2.0 + 2.0, radius * 4.0 + 6.0) else {
+            continue;
+        };
+        let contrast = depth / texture.max(1.0e-4);
+        if contrast < MIN_CONTRAST {
+            continue;
+        }
+        // The half-depth disc undersizes a soft spot; cover its tails too.
+        let heal_radius = radius * 2.0 + 1.0;
+        let (source_x, source_y) = pick_source(&maps, x, y, heal_radius);
+        found.push(DustCandidate {
+            x,
+            y,
+            radius: heal_radius,
+            source_x,
+            source_y,
+            confidence: ((contrast - MIN_CONTRAST) / (3.0 * MIN_CONTRAST)).clamp(0.0, 1.0) * 0.7
+                + (depth / 0.1).min(1.0) * 0.3,
+        });
+    }
+    found.sort_unstable_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    found.truncate(MAX_CANDIDATES);
+    found
+}
+
+// Position in the image as shot (normalized) -> normalized sensor position, undoing the
+// orientation the preview decoder applies.
+fn to_sensor(orientation: Orientation, x: f32, y: f32) -> (f32, f32) {
+    match orientation {
+        Orientation::Normal | Orientation::Unknown => (x, y),
+        Orientation::HorizontalFlip => (1.0 - x, y),
+        Orientation::Rotate180 => (1.0 - x, 1.0 - y),
+        Orientation::VerticalFlip => (x, 1.0 - y),
+        Orientation::Rotate90 => (y, 1.0 - x),
+        Orientation::Rotate270 => (1.0 - y, x),
+        Orientation::Transpose => (1.0 - y, 1.0 - x),
+        Orientation::Transverse => (y, x),
+    }
+}
+
+pub(crate) struct Frame {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) orientation: Orientation,
+    pub(crate) candidates: Vec<DustCandidate>,
+}
+
+// For every frame, how many frames (itself included) have a candidate at the same sensor position.
+pub(crate) fn recurrence(frames: &[Frame]) -> Vec<Vec<usize>> {
+    // Sensor positions scaled by the shorter side so distances are in analysis pixels.
+    let positions: Vec<Vec<(f32, f32, f32)>> = frames
+        .iter()
+        .map(|frame| {
+            let (w, h) = ((frame.width - 1).max(1) as f32, (frame.height - 1).max(1) as f32);
+            let swapped = matches!(
+                frame.orientation,
+                Orientation::Rotate90 | Orientation::Rotate270 | Orientation::Transpose | Orientation::Transverse
+            );
+            let (sensor_w, sensor_h) = if swapped { (h, w) } else { (w, h) };
+            let unit = sensor_w.min(sensor_h);
+            frame
+                .candidates
+                .iter()
+                .map(|c| {
+                    let (u, v) = to_sensor(frame.orientation, c.x / w, c.y / h);
+                    (u * sensor_w / unit, v * sensor_h / unit, c.radius / unit)
+                })
+                .collect()
+        })
+        .collect();
+
+    positions
+        .iter()
+        .enumerate()
+        .map(|(i, own)| {
+            own.iter()
+                .map(|&(u, v, r)| {
+                    let others = positions.iter().enumerate().filter(|(j, other)| {
+                        *j != i
+                            && other.iter().any(|&(ou, ov, or)| {
+                                // Dust blurs with aperture, so allow for the larger of the two.
+                                (u - ou).hypot(v - ov) <= r.max(or).max(0.002)
+                            })
+                    });
+                    1 + others.count()
+                })
+                .collect()
+        })
+        .collect()
+}
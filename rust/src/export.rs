@@ -0,0 +1,765 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::{Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use anyhow::{anyhow, Context, Result};
+use jpeg_encoder::{ColorType, PixelDensity, SamplingFactor};
+
+use crate::color_space;
+use crate::jxl::JxlEncoder;
+use crate::metadata::{ExportMetadata, XMP_NAMESPACE};
+use crate::sizing;
+use crate::model::{ChromaSubsampling, ExportFormat, ExportPayload, OutputColorSpace, TiffCompression};
+use crate::{clamp_to_u8, try_alloc_vec};
+
+const TIFF_ROWS_PER_STRIP: u32 = 32;
+const WEBP_MAX_DIMENSION: u32 = 16383;
+const DEFAULT_WEBP_QUALITY: u8 = 90;
+
+// Receives the rendered image as bands of full-width rows (RGB f32, display-encoded, 0..1).
+// PNG, TIFF and JPEG XL are encoded as the bands arrive; JPEG and WebP need the whole 8-bit frame.
+// PNG and TIFF stream straight into `sink`; the other formats are written to it once encoded.
+pub(crate) enum ExportEncoder<W: Write + Seek + 'static> {
+    Jpeg {
+        sink: W,
+        rgb: Vec<u8>,
+        width: u32,
+        height: u32,
+        quality: u8,
+        sampling: SamplingFactor,
+        color_space: OutputColorSpace,
+        dpi: u16,
+        exif: Option<Vec<u8>>,
+        xmp: Option<String>,
+    },
+    Webp {
+        sink: W,
+        rgb: Vec<u8>,
+        width: u32,
+        height: u32,
+        // `None` encodes losslessly.
+        quality: Option<u8>,
+        color_space: OutputColorSpace,
+        exif: Option<Vec<u8>>,
+        xmp: Option<String>,
+    },
+    Png {
+        sink: SharedSink<W>,
+        writer: Box<png::StreamWriter<'static, SharedSink<W>>>,
+        sixteen_bit: bool,
+        row: Vec<u8>,
+    },
+    Tiff(Box<TiffStripWriter<W>>),
+    JpegXl {
+        sink: W,
+        encoder: Box<JxlEncoder>,
+        exif: Option<Vec<u8>>,
+        xmp: Option<String>,
+    },
+}
+
+fn alloc_rgb8(width: u32, height: u32) -> Result<Vec<u8>> {
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|v| v.checked_mul(3))
+        .context("RGB buffer size overflow")?;
+    try_alloc_vec(len, 0u8)
+}
+
+impl<W: Write + Seek + 'static> ExportEncoder<W> {
+    pub(crate) fn new(
+        export: &ExportPayload,
+        width: u32,
+        height: u32,
+        fast: bool,
+        metadata: Option<&ExportMetadata>,
+        sink: W,
+    ) -> Result<Self> {
+        let quality = export.quality.map(|q| q.clamp(1, 100));
+        let dpi = sizing::density(&export.size);
+        let metadata = metadata.map(|m| {
+            m.for_output(width, height, export.color_space, &export.metadata)
+                .with_density(dpi)
+        });
+        let exif = metadata.as_ref().map(ExportMetadata::exif_block).transpose()?;
+        let xmp = metadata.as_ref().map(ExportMetadata::xmp_packet);
+        // ProPhoto's primaries are so far apart that 8 bits band visibly.
+        let prophoto = export.color_space == OutputColorSpace::ProPhoto;
+        let bit_depth = if prophoto { export.bit_depth.max(16) } else { export.bit_depth };
+        if prophoto && matches!(export.format, ExportFormat::Jpeg | ExportFormat::UltraHdr | ExportFormat::Webp) {
+            return Err(anyhow!("ProPhoto RGB needs a 16-bit format (PNG, TIFF or JPEG XL)"));
+        }
+        match export.format {
+            ExportFormat::Jpeg | ExportFormat::UltraHdr => {
+                if width > u16::MAX as u32 || height > u16::MAX as u32 {
+                    return Err(anyhow!("JPEG export is limited to {} px per side", u16::MAX));
+                }
+                Ok(ExportEncoder::Jpeg {
+                    sink,
+                    rgb: alloc_rgb8(width, height)?,
+                    width,
+                    height,
+                    quality: quality.unwrap_or(if fast { 88 } else { 96 }),
+                    sampling: match export.chroma_subsampling {
+                        ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+                        ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+                        ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+                    },
+                    color_space: export.color_space,
+                    dpi,
+                    exif,
+                    // Ultra HDR merges the description into its own XMP packet.
+                    xmp: if export.format == ExportFormat::UltraHdr { None } else { xmp },
+                })
+            }
+            ExportFormat::Webp => {
+                if width > WEBP_MAX_DIMENSION || height > WEBP_MAX_DIMENSION {
+                    return Err(anyhow!("WebP export is limited to {WEBP_MAX_DIMENSION} px per side"));
+                }
+                Ok(ExportEncoder::Webp {
+                    sink,
+                    rgb: alloc_rgb8(width, height)?,
+                    width,
+                    height,
+                    quality: if export.lossless { None } else { Some(quality.unwrap_or(DEFAULT_WEBP_QUALITY)) },
+                    color_space: export.color_space,
+                    exif,
+                    xmp,
+                })
+            }
+            ExportFormat::Png => {
+                // PNG has no float variant, so 32 falls back to 16.
+                let sixteen_bit = bit_depth > 8;
+                let mut info = png::Info::with_size(width, height);
+                info.color_type = png::ColorType::Rgb;
+                info.bit_depth = if sixteen_bit { png::BitDepth::Sixteen } else { png::BitDepth::Eight };
+                info.icc_profile = Some(Cow::Owned(color_space::icc_profile(export.color_space)));
+                info.exif_metadata = exif.map(Cow::Owned);
+                let ppm = (dpi as f64 / 0.0254).round() as u32;
+                info.pixel_dims = Some(png::PixelDimensions { xppu: ppm, yppu: ppm, unit: png::Unit::Meter });
+                let sink = SharedSink(Rc::new(RefCell::new(sink)));
+                let mut encoder = png::Encoder::with_info(sink.clone(), info)?;
+                encoder.set_compression(if fast { png::Compression::Fast } else { png::Compression::Balanced });
+                if let Some(xmp) = xmp {
+                    encoder.add_itxt_chunk("XML:com.adobe.xmp".to_string(), xmp)?;
+                }
+                let writer = Box::new(encoder.write_header()?.into_stream_writer()?);
+                let bytes_per_sample = if sixteen_bit { 2 } else { 1 };
+                Ok(ExportEncoder::Png {
+                    sink,
+                    writer,
+                    sixteen_bit,
+                    row: Vec::with_capacity(width as usize * 3 * bytes_per_sample),
+                })
+            }
+            ExportFormat::Tiff => Ok(ExportEncoder::Tiff(Box::new(TiffStripWriter::new(
+                sink, export, bit_depth, width, height, dpi, metadata,
+            )?))),
+            // Lossless only; 32-bit requests are stored as 16-bit integers.
+            ExportFormat::JpegXl => Ok(ExportEncoder::JpegXl {
+                sink,
+                encoder: Box::new(JxlEncoder::new(width, height, bit_depth > 8, export.color_space)?),
+                exif,
+                xmp,
+            }),
+        }
+    }
+
+    // `band` holds `rows` complete rows starting at `start_y`.
+    pub(crate) fn write_rows(&mut self, band: &[f32], start_y: u32, rows: u32) -> Result<()> {
+        match self {
+            ExportEncoder::Jpeg { rgb, width, .. } | ExportEncoder::Webp { rgb, width, .. } => {
+                let offset = start_y as usize * *width as usize * 3;
+                let len = rows as usize * *width as usize * 3;
+                for (dst, &v) in rgb[offset..offset + len].iter_mut().zip(band) {
+                    *dst = clamp_to_u8(v * 255.0);
+                }
+                Ok(())
+            }
+            ExportEncoder::Png { writer, sixteen_bit, row, .. } => {
+                let row_samples = band.len() / rows.max(1) as usize;
+                for samples in band.chunks_exact(row_samples) {
+                    row.clear();
+                    if *sixteen_bit {
+                        for &v in samples {
+                            row.extend_from_slice(&quantize_u16(v).to_be_bytes());
+                        }
+                    } else {
+                        row.extend(samples.iter().map(|&v| clamp_to_u8(v * 255.0)));
+                    }
+                    writer.write_all(row)?;
+                }
+                Ok(())
+            }
+            ExportEncoder::Tiff(writer) => writer.write_rows(band),
+            ExportEncoder::JpegXl { encoder, .. } => encoder.write_rows(band),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Result<W> {
+        self.finish_with(Ok)
+    }
+
+    // `wrap` gets the encoded bytes of the formats built in memory before they reach the sink,
+    // which is how Ultra HDR attaches its gain map to the JPEG primary.
+    pub(crate) fn finish_with(self, wrap: impl FnOnce(Vec<u8>) -> Result<Vec<u8>>) -> Result<W> {
+        let (mut sink, encoded) = match self {
+            ExportEncoder::Jpeg { sink, rgb, width, height, quality, sampling, color_space, dpi, exif, xmp } => {
+                let mut encoded = Vec::new();
+                let mut encoder = jpeg_encoder::Encoder::new(&mut encoded, quality);
+                encoder.set_sampling_factor(sampling);
+                encoder.set_density(PixelDensity::dpi(dpi));
+                if let Some(exif) = exif {
+                    encoder.add_exif_metadata(&exif)?;
+                }
+                if let Some(xmp) = xmp {
+                    encoder.add_app_segment(1, [XMP_NAMESPACE, xmp.as_bytes()].concat())?;
+                }
+                encoder.add_icc_profile(&color_space::icc_profile(color_space))?;
+                encoder.encode(&rgb, width as u16, height as u16, ColorType::Rgb)?;
+                (sink, encoded)
+            }
+            ExportEncoder::Webp { sink, rgb, width, height, quality, color_space, exif, xmp } => {
+                let encoder = webp::Encoder::from_rgb(&rgb, width, height);
+                let encoded = encoder
+                    .encode_simple(quality.is_none(), quality.unwrap_or(100) as f32)
+                    .map_err(|e| anyhow!("WebP encoding failed: {e:?}"))?;
+                let container = webp_container(
+                    &encoded,
+                    width,
+                    height,
+                    &color_space::icc_profile(color_space),
+                    exif.as_deref(),
+                    xmp.as_deref(),
+                )?;
+                (sink, container)
+            }
+            ExportEncoder::Png { sink, writer, .. } => {
+                writer.finish()?;
+                let sink = Rc::try_unwrap(sink.0).ok().context("PNG writer still holds the sink")?;
+                return Ok(sink.into_inner());
+            }
+            ExportEncoder::Tiff(writer) => return writer.finish(),
+            ExportEncoder::JpegXl { sink, encoder, exif, xmp } => {
+                let codestream = encoder.finish()?;
+                if exif.is_none() && xmp.is_none() {
+                    (sink, codestream)
+                } else {
+                    (sink, jxl_container(&codestream, exif.as_deref(), xmp.as_deref())?)
+                }
+            }
+        };
+        sink.write_all(&wrap(encoded)?)?;
+        Ok(sink)
+    }
+}
+
+fn riff_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+// libwebp's simple API writes a plain VP8/VP8L file; ICC, EXIF and XMP need the extended (VP8X) layout.
+fn webp_container(
+    encoded: &[u8],
+    width: u32,
+    height: u32,
+    icc: &[u8],
+    exif: Option<&[u8]>,
+    xmp: Option<&str>,
+) -> Result<Vec<u8>> {
+    if encoded.len() < 12 || &encoded[0..4] != b"RIFF" || &encoded[8..12] != b"WEBP" {
+        return Err(anyhow!("WebP encoder returned an invalid container"));
+    }
+    let mut flags = 0x20;
+    if exif.is_some() {
+        flags |= 0x08;
+    }
+    if xmp.is_some() {
+        flags |= 0x04;
+    }
+    let mut chunks = Vec::with_capacity(encoded.len() + icc.len() + 32);
+    chunks.extend_from_slice(b"VP8X");
+    chunks.extend_from_slice(&10u32.to_le_bytes());
+    chunks.extend_from_slice(&[flags, 0, 0, 0]);
+    chunks.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    chunks.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+    riff_chunk(&mut chunks, b"ICCP", icc);
+
+    let mut pos = 12;
+    while pos + 8 <= encoded.len() {
+        let size = u32::from_le_bytes(encoded[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let end = (pos + 8 + size + (size & 1)).min(encoded.len());
+        if &encoded[pos..pos + 4] != b"VP8X" {
+            chunks.extend_from_slice(&encoded[pos..end]);
+        }
+        pos = end;
+    }
+    // Metadata chunks follow the image data.
+    if let Some(exif) = exif {
+        riff_chunk(&mut chunks, b"EXIF", exif);
+    }
+    if let Some(xmp) = xmp {
+        riff_chunk(&mut chunks, b"XMP ", xmp.as_bytes());
+    }
+
+    let mut out = Vec::with_capacity(chunks.len() + 12);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+    out.extend_from_slice(b"WEBP");
+    out.extend_from_slice(&chunks);
+    Ok(out)
+}
+
+fn isobmff_box(out: &mut Vec<u8>, kind: &[u8; 4], data: &[&[u8]]) -> Result<()> {
+    let len = data.iter().map(|d| d.len()).sum::<usize>() + 8;
+    out.extend_from_slice(&u32::try_from(len).context("JPEG XL box exceeds 4 GiB")?.to_be_bytes());
+    out.extend_from_slice(kind);
+    for part in data {
+        out.extend_from_slice(part);
+    }
+    Ok(())
+}
+
+// A bare JPEG XL codestream cannot carry metadata; wrap it in the ISOBMFF-style container.
+fn jxl_container(codestream: &[u8], exif: Option<&[u8]>, xmp: Option<&str>) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(codestream.len() + 4096);
+    isobmff_box(&mut out, b"JXL ", &[&[0x0D, 0x0A, 0x87, 0x0A]])?;
+    isobmff_box(&mut out, b"ftyp", &[b"jxl ", &[0, 0, 0, 0], b"jxl "])?;
+    if let Some(exif) = exif {
+        // Offset of the TIFF header within the box payload.
+        isobmff_box(&mut out, b"Exif", &[&[0, 0, 0, 0], exif])?;
+    }
+    if let Some(xmp) = xmp {
+        isobmff_box(&mut out, b"xml ", &[xmp.as_bytes()])?;
+    }
+    isobmff_box(&mut out, b"jxlc", &[codestream])?;
+    Ok(out)
+}
+
+// The png stream writer owns its sink, so the sink is shared with it to get it back.
+pub(crate) struct SharedSink<W>(Rc<RefCell<W>>);
+
+impl<W> Clone for SharedSink<W> {
+    fn clone(&self) -> Self {
+        Self(Rc::clone(&self.0))
+    }
+}
+
+impl<W: Write> Write for SharedSink<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+#[inline(always)]
+fn quantize_u16(v: f32) -> u16 {
+    (v.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TiffSample {
+    U8,
+    U16,
+    F32,
+}
+
+impl TiffSample {
+    fn bytes(self) -> usize {
+        match self {
+            TiffSample::U8 => 1,
+            TiffSample::U16 => 2,
+            TiffSample::F32 => 4,
+        }
+    }
+}
+
+const TIFF_BYTE: u16 = 1;
+const TIFF_ASCII: u16 = 2;
+const TIFF_SHORT: u16 = 3;
+const TIFF_LONG: u16 = 4;
+const TIFF_RATIONAL: u16 = 5;
+const TIFF_UNDEFINED: u16 = 7;
+const TIFF_SRATIONAL: u16 = 10;
+
+pub(crate) struct TiffEntry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl TiffEntry {
+    pub(crate) fn bytes(tag: u16, values: &[u8]) -> Self {
+        Self { tag, field_type: TIFF_BYTE, count: values.len() as u32, data: values.to_vec() }
+    }
+
+    pub(crate) fn ascii(tag: u16, text: &str) -> Self {
+        let mut data = text.as_bytes().to_vec();
+        data.push(0);
+        Self { tag, field_type: TIFF_ASCII, count: data.len() as u32, data }
+    }
+
+    pub(crate) fn shorts(tag: u16, values: &[u16]) -> Self {
+        Self {
+            tag,
+            field_type: TIFF_SHORT,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    pub(crate) fn longs(tag: u16, values: &[u32]) -> Self {
+        Self {
+            tag,
+            field_type: TIFF_LONG,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    pub(crate) fn rational(tag: u16, numerator: u32, denominator: u32) -> Self {
+        Self::rationals(tag, &[(numerator, denominator)])
+    }
+
+    pub(crate) fn rationals(tag: u16, values: &[(u32, u32)]) -> Self {
+        Self {
+            tag,
+            field_type: TIFF_RATIONAL,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|&(n, d)| [n.to_le_bytes(), d.to_le_bytes()]).flatten().collect(),
+        }
+    }
+
+    pub(crate) fn srational(tag: u16, numerator: i32, denominator: i32) -> Self {
+        let mut data = numerator.to_le_bytes().to_vec();
+        data.extend_from_slice(&denominator.to_le_bytes());
+        Self { tag, field_type: TIFF_SRATIONAL, count: 1, data }
+    }
+
+    pub(crate) fn undefined(tag: u16, data: Vec<u8>) -> Self {
+        Self { tag, field_type: TIFF_UNDEFINED, count: data.len() as u32, data }
+    }
+}
+
+// Appends a little-endian IFD at the (word aligned) end of `out` and returns its offset.
+// Values wider than four bytes are stored right after the entry table. `out` starts `base` bytes
+// into the file.
+pub(crate) fn write_ifd(out: &mut Vec<u8>, base: u64, entries: &mut [TiffEntry]) -> Result<u32> {
+    entries.sort_by_key(|e| e.tag);
+    if (base + out.len() as u64) % 2 == 1 {
+        out.push(0);
+    }
+    let ifd_offset = base as usize + out.len();
+    let mut data_offset = ifd_offset + 2 + entries.len() * 12 + 4;
+    let mut overflow = Vec::new();
+
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for entry in entries.iter() {
+        out.extend_from_slice(&entry.tag.to_le_bytes());
+        out.extend_from_slice(&entry.field_type.to_le_bytes());
+        out.extend_from_slice(&entry.count.to_le_bytes());
+        if entry.data.len() <= 4 {
+            let mut inline = [0u8; 4];
+            inline[..entry.data.len()].copy_from_slice(&entry.data);
+            out.extend_from_slice(&inline);
+        } else {
+            let offset = u32::try_from(data_offset).context("TIFF exceeds 4 GiB")?;
+            out.extend_from_slice(&offset.to_le_bytes());
+            overflow.extend_from_slice(&entry.data);
+            if entry.data.len() % 2 == 1 {
+                overflow.push(0);
+            }
+            data_offset += entry.data.len() + entry.data.len() % 2;
+        }
+    }
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&overflow);
+    u32::try_from(base + out.len() as u64).context("TIFF exceeds 4 GiB")?;
+    Ok(ifd_offset as u32)
+}
+
+// Baseline little-endian RGB TIFF written strip by strip. The IFD goes at the end,
+// once all strip offsets are known, and the header is patched to point at it.
+pub(crate) struct TiffStripWriter<W: Write + Seek> {
+    sink: W,
+    // Bytes written to `sink` so far.
+    len: u64,
+    width: u32,
+    height: u32,
+    sample: TiffSample,
+    compression: TiffCompression,
+    icc_profile: Vec<u8>,
+    dpi: u16,
+    metadata: Option<ExportMetadata>,
+    pending: Vec<u8>,
+    strip_offsets: Vec<u32>,
+    strip_byte_counts: Vec<u32>,
+}
+
+impl<W: Write + Seek> TiffStripWriter<W> {
+    fn new(
+        mut sink: W,
+        export: &ExportPayload,
+        bit_depth: u8,
+        width: u32,
+        height: u32,
+        dpi: u16,
+        metadata: Option<ExportMetadata>,
+    ) -> Result<Self> {
+        let sample = match bit_depth {
+            32 => TiffSample::F32,
+            16 => TiffSample::U16,
+            _ => TiffSample::U8,
+        };
+        // The IFD offset is patched in by `finish`.
+        sink.write_all(b"II\x2a\0\0\0\0\0")?;
+        Ok(Self {
+            sink,
+            len: 8,
+            width,
+            height,
+            sample,
+            compression: export.tiff_compression,
+            icc_profile: color_space::icc_profile(export.color_space),
+            dpi,
+            metadata,
+            pending: Vec::new(),
+            strip_offsets: Vec::new(),
+            strip_byte_counts: Vec::new(),
+        })
+    }
+
+    fn row_bytes(&self) -> usize {
+        self.width as usize * 3 * self.sample.bytes()
+    }
+
+    // Horizontal differencing (predictor 2) makes deflate/LZW far more effective on photos.
+    fn use_predictor(&self) -> bool {
+        self.compression != TiffCompression::None && self.sample != TiffSample::F32
+    }
+
+    fn write_rows(&mut self, band: &[f32]) -> Result<()> {
+        match self.sample {
+            TiffSample::U8 => self.pending.extend(band.iter().map(|&v| clamp_to_u8(v * 255.0))),
+            TiffSample::U16 => {
+                for &v in band {
+                    self.pending.extend_from_slice(&quantize_u16(v).to_le_bytes());
+                }
+            }
+            TiffSample::F32 => {
+                for &v in band {
+                    self.pending.extend_from_slice(&v.max(0.0).to_le_bytes());
+                }
+            }
+        }
+
+        let strip_bytes = self.row_bytes() * TIFF_ROWS_PER_STRIP as usize;
+        while self.pending.len() >= strip_bytes {
+            let rest = self.pending.split_off(strip_bytes);
+            let strip = std::mem::replace(&mut self.pending, rest);
+            self.flush_strip(strip)?;
+        }
+        Ok(())
+    }
+
+    fn flush_strip(&mut self, mut strip: Vec<u8>) -> Result<()> {
+        if self.use_predictor() {
+            let row_bytes = self.row_bytes();
+            for row in strip.chunks_exact_mut(row_bytes) {
+                match self.sample {
+                    TiffSample::U8 => {
+                        for i in (3..row.len()).rev() {
+                            row[i] = row[i].wrapping_sub(row[i - 3]);
+                        }
+                    }
+                    _ => {
+                        for i in (3..row.len() / 2).rev() {
+                            let cur = u16::from_le_bytes([row[i * 2], row[i * 2 + 1]]);
+                            let prev = u16::from_le_bytes([row[(i - 3) * 2], row[(i - 3) * 2 + 1]]);
+                            row[i * 2..i * 2 + 2].copy_from_slice(&cur.wrapping_sub(prev).to_le_bytes());
+                        }
+                    }
+                }
+            }
+        }
+
+        let encoded = match self.compression {
+            TiffCompression::None => strip,
+            TiffCompression::Deflate => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&strip)?;
+                encoder.finish()?
+            }
+            TiffCompression::Lzw => weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+                .encode(&strip)
+                .map_err(|e| anyhow::anyhow!("LZW encoding failed: {}", e))?,
+        };
+
+        let offset = u32::try_from(self.len).context("TIFF exceeds 4 GiB")?;
+        self.strip_offsets.push(offset);
+        self.strip_byte_counts.push(encoded.len() as u32);
+        self.sink.write_all(&encoded)?;
+        self.len += encoded.len() as u64;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W> {
+        if !self.pending.is_empty() {
+            let strip = std::mem::take(&mut self.pending);
+            self.flush_strip(strip)?;
+        }
+
+        let bits = (self.sample.bytes() * 8) as u16;
+        let sample_format = if self.sample == TiffSample::F32 { 3 } else { 1 };
+        let compression = match self.compression {
+            TiffCompression::None => 1,
+            TiffCompression::Lzw => 5,
+            TiffCompression::Deflate => 8,
+        };
+        let predictor = if self.use_predictor() { 2 } else { 1 };
+
+        let mut entries = vec![
+            TiffEntry::longs(256, &[self.width]),
+            TiffEntry::longs(257, &[self.height]),
+            TiffEntry::shorts(258, &[bits, bits, bits]),
+            TiffEntry::shorts(259, &[compression]),
+            TiffEntry::shorts(262, &[2]),
+            TiffEntry::longs(273, &self.strip_offsets),
+            TiffEntry::shorts(277, &[3]),
+            TiffEntry::longs(278, &[TIFF_ROWS_PER_STRIP.min(self.height)]),
+            TiffEntry::longs(279, &self.strip_byte_counts),
+            TiffEntry::rational(282, self.dpi as u32, 1),
+            TiffEntry::rational(283, self.dpi as u32, 1),
+            TiffEntry::shorts(284, &[1]),
+            TiffEntry::shorts(296, &[2]),
+            TiffEntry::shorts(317, &[predictor]),
+            TiffEntry::shorts(339, &[sample_format, sample_format, sample_format]),
+            TiffEntry::undefined(34675, std::mem::take(&mut self.icc_profile)),
+        ];
+        let mut tail = Vec::new();
+        if let Some(metadata) = self.metadata.take() {
+            entries.extend(metadata.ifd0_entries(&mut tail, self.len)?);
+            entries.push(TiffEntry::bytes(700, metadata.xmp_packet().as_bytes()));
+        }
+        let ifd_offset = write_ifd(&mut tail, self.len, &mut entries)?;
+        self.sink.write_all(&tail)?;
+        self.sink.seek(SeekFrom::Start(4))?;
+        self.sink.write_all(&ifd_offset.to_le_bytes())?;
+        self.sink.seek(SeekFrom::End(0))?;
+        Ok(self.sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn test_rows(width: u32, height: u32) -> Vec<f32> {
+        (0..width * height * 3).map(|i| (i % 1021) as f32 / 1020.0).collect()
+    }
+
+    fn encode<W: Write + Seek + 'static>(export: &ExportPayload, width: u32, height: u32, sink: W) -> W {
+        let metadata = ExportMetadata::default();
+        let mut encoder = ExportEncoder::new(export, width, height, false, Some(&metadata), sink).unwrap();
+        let rows = test_rows(width, height);
+        // Bands of 37 rows, so TIFF strips and bands don't line up.
+        for (i, band) in rows.chunks(width as usize * 3 * 37).enumerate() {
+            encoder.write_rows(band, i as u32 * 37, (band.len() / (width as usize * 3)) as u32).unwrap();
+        }
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn tiff_streams_into_a_file() {
+        let (width, height) = (70, 90);
+        for (bit_depth, compression) in [(8, TiffCompression::None), (16, TiffCompression::Deflate), (32, TiffCompression::Lzw)] {
+            let export = ExportPayload {
+                format: ExportFormat::Tiff,
+                bit_depth,
+                tiff_compression: compression,
+                ..ExportPayload::default()
+            };
+            let in_memory = encode(&export, width, height, Cursor::new(Vec::new())).into_inner();
+
+            let path = std::env::temp_dir().join(format!("kawaiiraweditor-export-{}-{}.tif", std::process::id(), bit_depth));
+            let file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+            drop(encode(&export, width, height, file));
+            let written = std::fs::read(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(written, in_memory);
+
+            let mut decoder = tiff::decoder::Decoder::new(Cursor::new(&written)).unwrap();
+            assert_eq!(decoder.dimensions().unwrap(), (width, height));
+            assert_eq!(
+                decoder.get_tag_u8_vec(tiff::tags::Tag::IccProfile).unwrap(),
+                color_space::icc_profile(OutputColorSpace::Srgb)
+            );
+            let exif_offset = decoder.get_tag_u32(tiff::tags::Tag::ExifDirectory).unwrap();
+            assert!(exif_offset % 2 == 0 && (exif_offset as usize) < written.len());
+
+            let expected = test_rows(width, height);
+            match decoder.read_image().unwrap() {
+                tiff::decoder::DecodingResult::U8(got) => {
+                    assert!(got.iter().zip(&expected).all(|(&g, &e)| g == clamp_to_u8(e * 255.0)));
+                }
+                tiff::decoder::DecodingResult::U16(got) => {
+                    assert!(got.iter().zip(&expected).all(|(&g, &e)| g == quantize_u16(e)));
+                }
+                tiff::decoder::DecodingResult::F32(got) => assert_eq!(got, expected),
+                _ => panic!("unexpected TIFF sample format"),
+            }
+        }
+    }
+
+    #[test]
+    fn png_streams_into_the_sink() {
+        let (width, height) = (70, 90);
+        let export = ExportPayload { format: ExportFormat::Png, bit_depth: 16, ..ExportPayload::default() };
+        let bytes = encode(&export, width, height, Cursor::new(Vec::new())).into_inner();
+
+        let mut reader = png::Decoder::new(Cursor::new(bytes)).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height, info.bit_depth), (width, height, png::BitDepth::Sixteen));
+        let expected = test_rows(width, height);
+        for (sample, &e) in buf.chunks_exact(2).zip(&expected) {
+            assert_eq!(u16::from_be_bytes([sample[0], sample[1]]), quantize_u16(e));
+        }
+    }
+
+    #[test]
+    fn jxl_container_carries_exif_and_xmp() {
+        let (width, height) = (300, 20);
+        let pixels: Vec<f32> = (0..width * height * 3).map(|i| (i % 251) as f32 / 255.0).collect();
+        let mut encoder = JxlEncoder::new(width, height, false, OutputColorSpace::Srgb).unwrap();
+        encoder.write_rows(&pixels).unwrap();
+        let codestream = encoder.finish().unwrap();
+
+        let exif = b"II*\0\x08\0\0\0\0\0\0\0\0\0";
+        let xmp = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>";
+        let bytes = jxl_container(&codestream, Some(exif), Some(xmp)).unwrap();
+
+        let image = jxl_oxide::JxlImage::builder().read(bytes.as_slice()).unwrap();
+        let raw_exif = image.aux_boxes().first_exif().unwrap().unwrap();
+        assert_eq!(raw_exif.tiff_header_offset(), 0);
+        assert_eq!(raw_exif.payload(), exif);
+        assert_eq!(image.aux_boxes().first_xml().unwrap(), xmp.as_bytes());
+
+        let decoded = image.render_frame(0).unwrap().image_all_channels();
+        for (&got, &want) in decoded.buf().iter().zip(&pixels) {
+            assert_eq!((got * 255.0).round(), (want * 255.0).round());
+        }
+    }
+}
@@ -0,0 +1,206 @@
+// Red-eye and pet-eye correction. Each correction is a circle in the output frame. The pupil is
+// found once per frame: a flood fill from the circle centre over reflection-coloured pixels, so red
+// skin or fabric elsewhere in the circle stays untouched. Tiles then blend toward a dark neutral
+// through the resolved weights, so whole frames, ROIs and padded tiles agree. The catch-light is
+// near white and is left alone.
+
+use std::collections::VecDeque;
+
+use serde_json::json;
+
+use crate::model::{EyeCorrectionPayload, EyeKind};
+use crate::{generate_radial_mask_region, smoothstep};
+
+// How much of the darkening slider can take away on top of the neutral base.
+const MAX_DARKEN: f32 = 0.8;
+// Reflection strength a pixel needs to seed the fill, and to be grown into.
+const SEED_REFLECTION: f32 = 0.5;
+const GROW_REFLECTION: f32 = 0.1;
+// The seed is searched for within this fraction of the radius around the centre, which is
+// usually on the catch-light rather than the red.
+const SEED_SEARCH: f32 = 0.5;
+// Feather width around the filled pupil, as a fraction of the radius.
+const EDGE_FEATHER: f32 = 0.15;
+
+// How strongly a linear pixel looks like a flash reflection in the pupil.
+fn reflection(kind: EyeKind, c: [f32; 3]) -> f32 {
+    let max = c[0].max(c[1]).max(c[2]);
+    if max <= 1.0e-6 {
+        return 0.0;
+    }
+    match kind {
+        // Red well above both other channels; brown irises and skin stay below the ramp.
+        EyeKind::Red => smoothstep(0.4, 0.65, (c[0] - c[1].max(c[2])) / max),
+        // Tapetum glow comes in greens, yellows and blues; any strong colour counts.
+        EyeKind::Pet => {
+            let min = c[0].min(c[1]).min(c[2]);
+            smoothstep(0.15, 0.4, (max - min) / max)
+        }
+    }
+}
+
+// Box filter of `radius` over a `width` x `height` grid, in both directions.
+fn box_blur(values: &mut [f32], width: usize, height: usize, radius: usize) {
+    let mut line = Vec::new();
+    for pass in 0..2 {
+        let (len, count) = if pass == 0 { (width, height) } else { (height, width) };
+        let index = |a: usize, b: usize| if pass == 0 { b * width + a } else { a * width + b };
+        for b in 0..count {
+            line.clear();
+            line.extend((0..len).map(|a| values[index(a, b)]));
+            for a in 0..len {
+                let (lo, hi) = (a.saturating_sub(radius), (a + radius).min(len - 1));
+                values[index(a, b)] = line[lo..=hi].iter().sum::<f32>() / (2 * radius + 1) as f32;
+            }
+        }
+    }
+}
+
+// One correction resolved against the frame: the blend weight for each pixel of its box.
+struct Pupil {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    weights: Vec<f32>,
+    keep: f32,
+}
+
+pub(crate) struct Eyes {
+    pupils: Vec<Pupil>,
+}
+
+impl Eyes {
+    // `sample` returns the linear pixels of a region (x, y, width, height) of the `full_width` x
+    // `full_height` output frame, before any eye correction.
+    pub(crate) fn new(
+        eyes: &[EyeCorrectionPayload],
+        full_width: u32,
+        full_height: u32,
+        sample: impl Fn([u32; 4]) -> Vec<f32>,
+    ) -> Option<Self> {
+        let pupils: Vec<Pupil> = eyes
+            .iter()
+            .filter_map(|eye| Self::resolve(eye, full_width, full_height, &sample))
+            .collect();
+        (!pupils.is_empty()).then_some(Self { pupils })
+    }
+
+    fn resolve(
+        eye: &EyeCorrectionPayload,
+        full_width: u32,
+        full_height: u32,
+        sample: &impl Fn([u32; 4]) -> Vec<f32>,
+    ) -> Option<Pupil> {
+        if ![eye.center_x, eye.center_y, eye.radius, eye.feather, eye.darken].iter().all(|v| v.is_finite()) {
+            return None;
+        }
+        // Same conventions as radial masks: centre normalized to the frame, radius to the
+        // shorter side.
+        let cx = eye.center_x.clamp(0.0, 1.0) * (full_width as f32 - 1.0).max(1.0);
+        let cy = eye.center_y.clamp(0.0, 1.0) * (full_height as f32 - 1.0).max(1.0);
+        let r = (eye.radius * full_width.min(full_height) as f32).max(0.5);
+
+        let x0 = (cx - r).floor().max(0.0) as u32;
+        let y0 = (cy - r).floor().max(0.0) as u32;
+        let x1 = ((cx + r).ceil().max(0.0) as u32 + 1).min(full_width);
+        let y1 = ((cy + r).ceil().max(0.0) as u32 + 1).min(full_height);
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+        let (width, height) = (x1 - x0, y1 - y0);
+        let params = json!({
+            "centerX": eye.center_x.clamp(0.0, 1.0),
+            "centerY": eye.center_y.clamp(0.0, 1.0),
+            "radiusX": eye.radius,
+            "radiusY": eye.radius,
+            "rotation": 0.0,
+            "feather": eye.feather.clamp(0.0, 1.0),
+        });
+        let mask = generate_radial_mask_region(&params, full_width, full_height, x0, y0, width, height);
+        let rgb = sample([x0, y0, width, height]);
+        let strength: Vec<f32> = rgb
+            .chunks_exact(3)
+            .zip(&mask)
+            .map(|(c, &m)| if m > 0 { reflection(eye.kind, [c[0], c[1], c[2]]) } else { 0.0 })
+            .collect();
+
+        // Seed on the reflection pixel closest to the centre.
+        let (w, h) = (width as usize, height as usize);
+        let position = |i: usize| ((x0 + (i % w) as u32) as f32, (y0 + (i / w) as u32) as f32);
+        let seed = strength
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| **s >= SEED_REFLECTION)
+            .map(|(i, _)| {
+                let (x, y) = position(i);
+                (i, (x - cx).hypot(y - cy))
+            })
+            .filter(|(_, d)| *d <= r * SEED_SEARCH)
+            .min_by(|a, b| a.1.total_cmp(&b.1))?
+            .0;
+
+        let mut region = vec![0f32; w * h];
+        let mut queue = VecDeque::from([seed]);
+        region[seed] = 1.0;
+        while let Some(i) = queue.pop_front() {
+            let (x, y) = (i % w, i / w);
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < w).then(|| i + 1),
+                (y > 0).then(|| i - w),
+                (y + 1 < h).then(|| i + w),
+            ];
+            for n in neighbours.into_iter().flatten() {
+                if region[n] == 0.0 && strength[n] >= GROW_REFLECTION {
+                    region[n] = 1.0;
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        // Grow the pupil by the feather width, then blur it back, so the whole fill stays at
+        // full weight and only its outside edge fades.
+        let feather = ((r * EDGE_FEATHER).round() as usize).max(1);
+        box_blur(&mut region, w, h, feather);
+        region.iter_mut().for_each(|v| *v = if *v > 0.0 { 1.0 } else { 0.0 });
+        box_blur(&mut region, w, h, feather);
+
+        let weights = region.iter().zip(&mask).zip(&strength).map(|((&inside, &m), &s)| inside * m as f32 / 255.0 * s).collect();
+        Some(Pupil {
+            x: x0,
+            y: y0,
+            width,
+            height,
+            weights,
+            keep: 1.0 - eye.darken.clamp(0.0, 100.0) / 100.0 * MAX_DARKEN,
+        })
+    }
+
+    // Corrects `region` (x, y, width, height) of the output; `rgb` holds that region's linear
+    // pixels.
+    pub(crate) fn apply(&self, rgb: &mut [f32], region: [u32; 4]) {
+        let [origin_x, origin_y, width, height] = region;
+        for pupil in &self.pupils {
+            let x0 = pupil.x.max(origin_x);
+            let y0 = pupil.y.max(origin_y);
+            let x1 = (pupil.x + pupil.width).min(origin_x + width);
+            let y1 = (pupil.y + pupil.height).min(origin_y + height);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let weight = pupil.weights[((y - pupil.y) * pupil.width + (x - pupil.x)) as usize];
+                    if weight <= 0.0 {
+                        continue;
+                    }
+                    let i = (((y - origin_y) * width + (x - origin_x)) * 3) as usize;
+                    let c = [rgb[i], rgb[i + 1], rgb[i + 2]];
+                    // The weakest channel carries the least reflection and keeps the iris texture.
+                    let v = c[0].min(c[1]).min(c[2]) * pupil.keep;
+                    for (channel, value) in rgb[i..i + 3].iter_mut().enumerate() {
+                        *value = c[channel] + (v - c[channel]) * weight;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// Boundary fill for output pixels that rotation or keystone correction pull from outside the
+// source. The image is mirrored across its edge and blurred more the further out a pixel is,
+// using a box-filtered pyramid so the tiled exporter can sample it anywhere without a full pass.
+
+use rayon::prelude::*;
+
+// The first pyramid level is about this many pixels on its shorter side, whatever the source
+// resolution, so the preview and the full-size export fill alike.
+const BASE_LEVEL_SIZE: u32 = 256;
+// Pyramid levels stop once the shorter side would drop below this.
+const MIN_LEVEL_SIZE: u32 = 8;
+// Blur radius grows with this fraction of the distance past the edge.
+const BLUR_PER_DISTANCE: f32 = 0.5;
+
+struct Level {
+    width: u32,
+    height: u32,
+    // Source pixels per level pixel.
+    scale: f32,
+    rgb: Vec<f32>,
+}
+
+impl Level {
+    // Bilinear lookup in source pixel coordinates.
+    fn sample(&self, x: f32, y: f32) -> [f32; 3] {
+        let fx = ((x + 0.5) / self.scale - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let fy = ((y + 0.5) / self.scale - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (fx as u32, fy as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+        let px = |x: u32, y: u32| {
+            let i = ((y * self.width + x) * 3) as usize;
+            [self.rgb[i], self.rgb[i + 1], self.rgb[i + 2]]
+        };
+        let (a, b, c, d) = (px(x0, y0), px(x1, y0), px(x0, y1), px(x1, y1));
+        let mut out = [0f32; 3];
+        for (i, v) in out.iter_mut().enumerate() {
+            let top = a[i] + (b[i] - a[i]) * tx;
+            let bottom = c[i] + (d[i] - c[i]) * tx;
+            *v = top + (bottom - top) * ty;
+        }
+        out
+    }
+
+    fn half(&self) -> Level {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut rgb = vec![0f32; (width * height * 3) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let mut acc = [0f32; 3];
+                for (sx, sy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let i = (((2 * y + sy).min(self.height - 1) * self.width + (2 * x + sx).min(self.width - 1)) * 3) as usize;
+                    acc[0] += self.rgb[i];
+                    acc[1] += self.rgb[i + 1];
+                    acc[2] += self.rgb[i + 2];
+                }
+                let o = ((y * width + x) * 3) as usize;
+                rgb[o..o + 3].copy_from_slice(&acc.map(|v| v * 0.25));
+            }
+        }
+        Level { width, height, scale: self.scale * 2.0, rgb }
+    }
+}
+
+pub(crate) struct EdgeFill {
+    width: u32,
+    height: u32,
+    levels: Vec<Level>,
+}
+
+// Reflects a coordinate back into [0, size - 1], repeating for far-away positions.
+fn mirror(v: f32, size: u32) -> f32 {
+    let max = (size.max(2) - 1) as f32;
+    let period = 2.0 * max;
+    let m = v.rem_euclid(period);
+    if m > max {
+        period - m
+    } else {
+        m
+    }
+}
+
+impl EdgeFill {
+    pub(crate) fn new(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [f32; 3] + Sync) -> Self {
+        // Source pixels averaged per side for the first level.
+        let factor = (width.min(height) / BASE_LEVEL_SIZE).max(1);
+        let (base_w, base_h) = ((width / factor).max(1), (height / factor).max(1));
+        let mut rgb = vec![0f32; (base_w * base_h * 3) as usize];
+        rgb.par_chunks_mut(base_w as usize * 3).enumerate().for_each(|(y, row)| {
+            let y0 = y as u32 * factor;
+            for (x, out) in row.chunks_exact_mut(3).enumerate() {
+                let x0 = x as u32 * factor;
+                let mut acc = [0f32; 3];
+                let mut count = 0f32;
+                for sy in y0..(y0 + factor).min(height) {
+                    for sx in x0..(x0 + factor).min(width) {
+                        let p = pixel(sx, sy);
+                        acc[0] += p[0];
+                        acc[1] += p[1];
+                        acc[2] += p[2];
+                        count += 1.0;
+                    }
+                }
+                out.copy_from_slice(&acc.map(|v| v / count.max(1.0)));
+            }
+        });
+
+        let mut levels = vec![Level { width: base_w, height: base_h, scale: factor as f32, rgb }];
+        while let Some(last) = levels.last().filter(|l| l.width.min(l.height) / 2 >= MIN_LEVEL_SIZE) {
+            let next = last.half();
+            levels.push(next);
+        }
+        Self { width, height, levels }
+    }
+
+    // Colour for source position (x, y), which may lie outside the image. `sharp` samples the
+    // full-resolution source at an in-bounds position.
+    pub(crate) fn sample(&self, x: f32, y: f32, sharp: impl Fn(f32, f32) -> [f32; 3]) -> [f32; 3] {
+        let (max_x, max_y) = ((self.width - 1) as f32, (self.height - 1) as f32);
+        let out_x = (-x).max(x - max_x).max(0.0);
+        let out_y = (-y).max(y - max_y).max(0.0);
+        let (mx, my) = (mirror(x, self.width), mirror(y, self.height));
+
+        let radius = out_x.hypot(out_y) * BLUR_PER_DISTANCE;
+        let base = self.levels[0].scale;
+        if radius <= base {
+            // Close to the edge: fade from the mirrored pixel into the first blur level.
+            let t = radius / base;
+            let (a, b) = (sharp(mx, my), self.levels[0].sample(mx, my));
+            return [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t);
+        }
+        let lod = (radius / base).log2().min((self.levels.len() - 1) as f32);
+        let i = lod.floor() as usize;
+        let a = self.levels[i].sample(mx, my);
+        if i + 1 >= self.levels.len() {
+            return a;
+        }
+        let b = self.levels[i + 1].sample(mx, my);
+        let t = lod - i as f32;
+        [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * t)
+    }
+}
@@ -0,0 +1,142 @@
+// Histograms returned alongside preview renders, so the app doesn't have to decode the JPEG again.
+// The HDR part is gathered inside the render loop from the scene-referred composite before tone
+// mapping; everything else comes from the finished 8-bit pixels.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use crate::get_luma;
+
+// Log2 luminance range covered by the HDR histogram, relative to diffuse white at 1.0.
+const HDR_MIN_EV: f32 = -12.0;
+const HDR_MAX_EV: f32 = 4.0;
+const HDR_BINS: usize = 128;
+const WAVEFORM_COLUMNS: usize = 128;
+const WAVEFORM_LEVELS: usize = 64;
+// Render threads add to separate copies of the HDR bins so they don't contend on hot bins.
+const SHARDS: usize = 16;
+// R, G, B and luma.
+const CHANNELS: usize = 4;
+
+pub(crate) struct Histograms {
+    hdr: Vec<AtomicU32>,
+    // Values above 1.0 per colour channel, which the tone mapper has to compress.
+    over_range: Vec<AtomicU32>,
+    output: Option<Value>,
+}
+
+impl Histograms {
+    pub(crate) fn new() -> Self {
+        Self {
+            hdr: (0..SHARDS * CHANNELS * HDR_BINS).map(|_| AtomicU32::new(0)).collect(),
+            over_range: (0..SHARDS * 3).map(|_| AtomicU32::new(0)).collect(),
+            output: None,
+        }
+    }
+
+    // Records one linear pixel of the composite before tone mapping.
+    #[inline]
+    pub(crate) fn add_hdr(&self, color: [f32; 3]) {
+        let shard = rayon::current_thread_index().unwrap_or(0) % SHARDS;
+        let bins = &self.hdr[shard * CHANNELS * HDR_BINS..][..CHANNELS * HDR_BINS];
+        let luma = get_luma(color);
+        for (channel, value) in [color[0], color[1], color[2], luma].into_iter().enumerate() {
+            let ev = value.max(1.0e-6).log2();
+            let t = ((ev - HDR_MIN_EV) / (HDR_MAX_EV - HDR_MIN_EV)).clamp(0.0, 1.0);
+            let bin = ((t * HDR_BINS as f32) as usize).min(HDR_BINS - 1);
+            bins[channel * HDR_BINS + bin].fetch_add(1, Ordering::Relaxed);
+        }
+        for (channel, value) in color.into_iter().enumerate() {
+            if value > 1.0 {
+                self.over_range[shard * 3 + channel].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Bins the finished interleaved sRGB pixels: per-channel and luma histograms, a luma waveform
+    // and clipping counts.
+    pub(crate) fn set_output(&mut self, rgb: &[u8], width: u32) {
+        const BINS: usize = 256 * CHANNELS + WAVEFORM_COLUMNS * WAVEFORM_LEVELS + 6;
+        let width = width.max(1) as usize;
+        let counts = rgb
+            .par_chunks(width * 3)
+            .fold(
+                || vec![0u32; BINS],
+                |mut acc, row| {
+                    for (x, p) in row.chunks_exact(3).enumerate() {
+                        let (r, g, b) = (p[0], p[1], p[2]);
+                        // Same weighting and rounding as the app's own histogram.
+                        let luma = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round().min(255.0) as usize;
+                        for (channel, v) in [r as usize, g as usize, b as usize, luma].into_iter().enumerate() {
+                            acc[channel * 256 + v] += 1;
+                        }
+                        let column = x * WAVEFORM_COLUMNS / width;
+                        let level = luma * WAVEFORM_LEVELS / 256;
+                        acc[256 * CHANNELS + level * WAVEFORM_COLUMNS + column] += 1;
+                        let clip = 256 * CHANNELS + WAVEFORM_COLUMNS * WAVEFORM_LEVELS;
+                        for (channel, v) in [r, g, b].into_iter().enumerate() {
+                            acc[clip + channel] += (v == 0) as u32;
+                            acc[clip + 3 + channel] += (v == 255) as u32;
+                        }
+                    }
+                    acc
+                },
+            )
+            .reduce(
+                || vec![0u32; BINS],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                    a
+                },
+            );
+
+        let channel = |c: usize| &counts[c * 256..(c + 1) * 256];
+        let waveform = &counts[256 * CHANNELS..256 * CHANNELS + WAVEFORM_COLUMNS * WAVEFORM_LEVELS];
+        let clip = &counts[256 * CHANNELS + WAVEFORM_COLUMNS * WAVEFORM_LEVELS..];
+        self.output = Some(json!({
+            "pixels": rgb.len() / 3,
+            "output": {
+                "red": channel(0),
+                "green": channel(1),
+                "blue": channel(2),
+                "luma": channel(3),
+            },
+            // Row-major, `levels` rows from black to white by `columns` across the frame.
+            "waveform": {
+                "columns": WAVEFORM_COLUMNS,
+                "levels": WAVEFORM_LEVELS,
+                "luma": waveform,
+            },
+            "clipping": {
+                "shadows": { "red": clip[0], "green": clip[1], "blue": clip[2] },
+                "highlights": { "red": clip[3], "green": clip[4], "blue": clip[5] },
+            },
+        }));
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        let mut hdr = vec![0u32; CHANNELS * HDR_BINS];
+        for shard in self.hdr.chunks_exact(CHANNELS * HDR_BINS) {
+            hdr.iter_mut().zip(shard).for_each(|(a, b)| *a += b.load(Ordering::Relaxed));
+        }
+        let mut over = [0u32; 3];
+        for shard in self.over_range.chunks_exact(3) {
+            over.iter_mut().zip(shard).for_each(|(a, b)| *a += b.load(Ordering::Relaxed));
+        }
+        let channel = |c: usize| &hdr[c * HDR_BINS..(c + 1) * HDR_BINS];
+
+        let mut out = self.output.clone().unwrap_or_else(|| json!({}));
+        out["hdr"] = json!({
+            "minEv": HDR_MIN_EV,
+            "maxEv": HDR_MAX_EV,
+            "red": channel(0),
+            "green": channel(1),
+            "blue": channel(2),
+            "luma": channel(3),
+        });
+        out["clipping"]["overRange"] = json!({ "red": over[0], "green": over[1], "blue": over[2] });
+        out
+    }
+}
@@ -0,0 +1,737 @@
+// Minimal lossless JPEG XL encoder (modular mode, RCT YCoCg + gradient predictor, prefix codes).
+// Each 256x256 group carries its own MA tree and histograms, so rows can be encoded as soon as a
+// full row of groups has been rendered instead of holding the whole image.
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+
+use crate::color_space;
+use crate::model::OutputColorSpace;
+
+const GROUP_SIZE_SHIFT: u32 = 1;
+const GROUP_DIM: u32 = 128 << GROUP_SIZE_SHIFT;
+const MAX_PREFIX_BITS: u8 = 15;
+const MAX_CODE_LENGTH_BITS: u8 = 5;
+const CODE_LENGTH_ORDER: [usize; 18] = [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+// Leaf contexts of the per-group tree, indexed by channel (Y, Co, Cg).
+const CHANNEL_CONTEXT: [usize; 3] = [0, 2, 1];
+
+// LSB-first bit writer as used throughout the JPEG XL codestream.
+struct BitWriter {
+    buf: Vec<u8>,
+    acc: u64,
+    nbits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { buf: Vec::new(), acc: 0, nbits: 0 }
+    }
+
+    fn write(&mut self, nbits: u32, value: u64) {
+        if nbits == 0 {
+            return;
+        }
+        self.acc |= (value & ((1u64 << nbits) - 1)) << self.nbits;
+        self.nbits += nbits;
+        while self.nbits >= 8 {
+            self.buf.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nbits -= 8;
+        }
+    }
+
+    fn write_bool(&mut self, value: bool) {
+        self.write(1, value as u64);
+    }
+
+    // U32 field with an explicit distribution selector.
+    fn write_u32_sel(&mut self, selector: u64, nbits: u32, value: u64) {
+        self.write(2, selector);
+        self.write(nbits, value);
+    }
+
+    // Enum fields: U32(0, 1, 2 + u(4), 18 + u(6)).
+    fn write_enum(&mut self, value: u32) {
+        match value {
+            0 | 1 => self.write(2, value as u64),
+            2..=17 => self.write_u32_sel(2, 4, (value - 2) as u64),
+            _ => self.write_u32_sel(3, 6, (value - 18) as u64),
+        }
+    }
+
+    fn zero_pad(&mut self) {
+        if self.nbits > 0 {
+            self.buf.push(self.acc as u8);
+            self.acc = 0;
+            self.nbits = 0;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.zero_pad();
+        self.buf
+    }
+}
+
+fn write_size(w: &mut BitWriter, value: u32) {
+    let v = (value - 1) as u64;
+    if v < 1 << 9 {
+        w.write_u32_sel(0, 9, v);
+    } else if v < 1 << 13 {
+        w.write_u32_sel(1, 13, v);
+    } else if v < 1 << 18 {
+        w.write_u32_sel(2, 18, v);
+    } else {
+        w.write_u32_sel(3, 30, v);
+    }
+}
+
+fn write_customxy(w: &mut BitWriter, xy: (f64, f64)) {
+    for v in [xy.0, xy.1] {
+        let packed = ((v * 1e6).round() as u64) * 2;
+        if packed < 1 << 19 {
+            w.write_u32_sel(0, 19, packed);
+        } else if packed < 524288 + (1 << 19) {
+            w.write_u32_sel(1, 19, packed - 524288);
+        } else if packed < 1048576 + (1 << 20) {
+            w.write_u32_sel(2, 20, packed - 1048576);
+        } else {
+            w.write_u32_sel(3, 21, packed - 2097152);
+        }
+    }
+}
+
+// Colour encodings are signalled as enums/chromaticities, so no ICC stream is needed.
+fn write_colour_encoding(w: &mut BitWriter, space: OutputColorSpace) {
+    if space == OutputColorSpace::Srgb {
+        w.write_bool(true);
+        return;
+    }
+    let [red, green, blue, white] = color_space::chromaticities(space);
+    w.write_bool(false); // all_default
+    w.write_bool(false); // want_icc
+    w.write_enum(0); // RGB
+    match space {
+        OutputColorSpace::ProPhoto => {
+            w.write_enum(2);
+            write_customxy(w, white);
+        }
+        _ => w.write_enum(1), // D65
+    }
+    match space {
+        OutputColorSpace::DisplayP3 => w.write_enum(11),
+        _ => {
+            w.write_enum(2);
+            write_customxy(w, red);
+            write_customxy(w, green);
+            write_customxy(w, blue);
+        }
+    }
+    match space {
+        OutputColorSpace::AdobeRgb => {
+            w.write_bool(true);
+            w.write(24, (1e7 * 256.0 / 563.0_f64).round() as u64);
+        }
+        // The ROMM linear toe below 1/512 has no codestream equivalent; pure 1.8 is within rounding.
+        OutputColorSpace::ProPhoto => {
+            w.write_bool(true);
+            w.write(24, (1e7 / 1.8_f64).round() as u64);
+        }
+        _ => {
+            w.write_bool(false);
+            w.write_enum(13); // sRGB transfer
+        }
+    }
+    w.write_enum(1); // relative rendering intent
+}
+
+fn write_image_header(w: &mut BitWriter, width: u32, height: u32, sixteen_bit: bool, space: OutputColorSpace) {
+    w.write(16, 0x0AFF);
+    // SizeHeader
+    w.write_bool(false);
+    write_size(w, height);
+    w.write(3, 0);
+    write_size(w, width);
+    // ImageMetadata
+    w.write_bool(false); // all_default
+    w.write_bool(false); // extra_fields
+    w.write_bool(false); // float_sample
+    if sixteen_bit {
+        w.write_u32_sel(3, 6, 15);
+    } else {
+        w.write(2, 0);
+    }
+    w.write_bool(!sixteen_bit); // modular_16bit_buffers
+    w.write(2, 0); // no extra channels
+    w.write_bool(false); // xyb_encoded
+    write_colour_encoding(w, space);
+    w.write(2, 0); // extensions
+    w.write_bool(true); // default_m
+    w.zero_pad();
+}
+
+fn write_frame_header(w: &mut BitWriter) {
+    w.write_bool(false); // all_default
+    w.write(2, 0); // regular frame
+    w.write(1, 1); // modular
+    w.write(2, 0); // flags
+    w.write_bool(false); // do_YCbCr
+    w.write(2, 0); // upsampling 1
+    w.write(2, GROUP_SIZE_SHIFT as u64);
+    w.write(2, 0); // one pass
+    w.write_bool(false); // have_crop
+    w.write(2, 0); // blend mode replace
+    w.write_bool(true); // is_last
+    w.write(2, 0); // empty name
+    w.write_bool(false); // restoration filter: not default
+    w.write_bool(false); // no gaborish
+    w.write(2, 0); // no EPF
+    w.write(2, 0); // restoration filter extensions
+    w.write(2, 0); // frame extensions
+}
+
+// Length-limited Huffman code lengths. Small counts are raised until the tree fits.
+fn code_lengths(counts: &[u32], max_len: u8) -> Vec<u8> {
+    let mut floor = 1u32;
+    loop {
+        let mut nodes: Vec<(u64, Option<(usize, usize)>)> = Vec::new();
+        let mut leaf_of_symbol = vec![usize::MAX; counts.len()];
+        for (sym, &count) in counts.iter().enumerate() {
+            if count > 0 {
+                leaf_of_symbol[sym] = nodes.len();
+                nodes.push((count.max(floor) as u64, None));
+            }
+        }
+        let mut lengths = vec![0u8; counts.len()];
+        if nodes.len() < 2 {
+            for (sym, &leaf) in leaf_of_symbol.iter().enumerate() {
+                if leaf != usize::MAX {
+                    lengths[sym] = 1;
+                }
+            }
+            return lengths;
+        }
+
+        let mut active: Vec<usize> = (0..nodes.len()).collect();
+        while active.len() > 1 {
+            active.sort_by_key(|&i| (std::cmp::Reverse(nodes[i].0), std::cmp::Reverse(i)));
+            let a = active.pop().unwrap();
+            let b = active.pop().unwrap();
+            nodes.push((nodes[a].0 + nodes[b].0, Some((a, b))));
+            active.push(nodes.len() - 1);
+        }
+
+        let mut depth = vec![0u8; nodes.len()];
+        for i in (0..nodes.len()).rev() {
+            if let Some((a, b)) = nodes[i].1 {
+                depth[a] = depth[i] + 1;
+                depth[b] = depth[i] + 1;
+            }
+        }
+        for (sym, &leaf) in leaf_of_symbol.iter().enumerate() {
+            if leaf != usize::MAX {
+                lengths[sym] = depth[leaf];
+            }
+        }
+        if lengths.iter().all(|&l| l <= max_len) {
+            return lengths;
+        }
+        floor = floor.saturating_mul(2);
+    }
+}
+
+// Canonical codes, bit-reversed so they can be written LSB first.
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let max_len = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut bl_count = vec![0u16; max_len + 1];
+    for &l in lengths {
+        if l > 0 {
+            bl_count[l as usize] += 1;
+        }
+    }
+    let mut next_code = vec![0u16; max_len + 2];
+    let mut code = 0u16;
+    for bits in 1..=max_len {
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+    lengths
+        .iter()
+        .map(|&l| {
+            if l == 0 {
+                return 0;
+            }
+            let c = next_code[l as usize];
+            next_code[l as usize] += 1;
+            c.reverse_bits() >> (16 - l)
+        })
+        .collect()
+}
+
+struct PrefixCode {
+    lengths: Vec<u8>,
+    codes: Vec<u16>,
+}
+
+impl PrefixCode {
+    fn write_symbol(&self, w: &mut BitWriter, symbol: usize) {
+        w.write(self.lengths[symbol] as u32, self.codes[symbol] as u64);
+    }
+}
+
+fn alphabet_size(counts: &[u32]) -> usize {
+    counts.iter().rposition(|&c| c > 0).map(|i| i + 1).unwrap_or(1)
+}
+
+fn write_alphabet_size(w: &mut BitWriter, size: usize) {
+    if size == 1 {
+        w.write_bool(false);
+        return;
+    }
+    let n = 31 - ((size - 1) as u32).leading_zeros();
+    w.write_bool(true);
+    w.write(4, n as u64);
+    w.write(n, (size - 1 - (1 << n)) as u64);
+}
+
+fn write_prefix_histogram(w: &mut BitWriter, counts: &[u32]) -> PrefixCode {
+    let size = alphabet_size(counts);
+    let counts = &counts[..size];
+    if size == 1 {
+        return PrefixCode { lengths: vec![0], codes: vec![0] };
+    }
+
+    let used: Vec<usize> = (0..size).filter(|&s| counts[s] > 0).collect();
+    if used.len() == 1 {
+        let alphabet_bits = (size as u32).next_power_of_two().trailing_zeros();
+        w.write(2, 1); // simple code
+        w.write(2, 0); // one symbol
+        w.write(alphabet_bits, used[0] as u64);
+        return PrefixCode { lengths: vec![0; size], codes: vec![0; size] };
+    }
+
+    let lengths = code_lengths(counts, MAX_PREFIX_BITS);
+    let mut length_counts = [0u32; 18];
+    for &l in &lengths {
+        length_counts[l as usize] += 1;
+    }
+    let mut length_code_lengths = code_lengths(&length_counts, MAX_CODE_LENGTH_BITS);
+    let single_length_symbol = length_code_lengths.iter().filter(|&&l| l > 0).count() == 1;
+
+    w.write(2, 0); // complex code, no skipped entries
+    let mut kraft = 0u32;
+    for &idx in &CODE_LENGTH_ORDER {
+        let len = length_code_lengths[idx];
+        match len {
+            0 => w.write(2, 0),
+            1 => w.write(4, 0b0111),
+            2 => w.write(3, 0b011),
+            3 => w.write(2, 0b10),
+            4 => w.write(2, 0b01),
+            _ => w.write(4, 0b1111),
+        }
+        if len > 0 && !single_length_symbol {
+            kraft += 32 >> len;
+            if kraft == 32 {
+                break;
+            }
+        }
+    }
+    // A lone code length symbol is decoded without reading any bits.
+    if single_length_symbol {
+        length_code_lengths.iter_mut().for_each(|l| *l = 0);
+    }
+    let length_codes = canonical_codes(&length_code_lengths);
+    for &l in &lengths {
+        w.write(length_code_lengths[l as usize] as u32, length_codes[l as usize] as u64);
+    }
+
+    let codes = canonical_codes(&lengths);
+    PrefixCode { lengths, codes }
+}
+
+// HybridUint config 4/0/0: values below 16 are literal tokens, the rest store their top bit position.
+#[inline(always)]
+fn hybrid_token(value: u32) -> (usize, u32, u32) {
+    if value < 16 {
+        (value as usize, 0, 0)
+    } else {
+        let n = 31 - value.leading_zeros();
+        ((12 + n) as usize, n, value - (1 << n))
+    }
+}
+
+fn write_hybrid_config(w: &mut BitWriter) {
+    w.write(4, 4); // split_exponent
+    w.write(3, 0); // msb_in_token
+    w.write(3, 0); // lsb_in_token
+}
+
+#[inline(always)]
+fn pack_signed(v: i32) -> u32 {
+    if v >= 0 {
+        (v as u32) << 1
+    } else {
+        ((-(v as i64)) as u32) * 2 - 1
+    }
+}
+
+// Writes an MA tree. With `split_channels` the tree routes each of the three channels to its own
+// leaf (and thus its own histogram); otherwise a single leaf is used.
+fn write_ma_tree(w: &mut BitWriter, split_channels: bool) {
+    // (context, value) pairs: ctx 1 = property+1, ctx 0 = split value, ctx 2..5 = leaf params.
+    let mut tokens: Vec<(usize, u32)> = Vec::new();
+    let leaf = |tokens: &mut Vec<(usize, u32)>| {
+        tokens.extend_from_slice(&[(1, 0), (2, 5), (3, 0), (4, 0), (5, 0)]);
+    };
+    if split_channels {
+        tokens.extend_from_slice(&[(1, 1), (0, pack_signed(0)), (1, 1), (0, pack_signed(1))]);
+        leaf(&mut tokens);
+        leaf(&mut tokens);
+        leaf(&mut tokens);
+    } else {
+        leaf(&mut tokens);
+    }
+
+    w.write_bool(false); // no LZ77
+    w.write_bool(true); // simple clustering
+    w.write(2, 0); // every context maps to cluster 0
+    w.write_bool(true); // prefix codes
+    write_hybrid_config(w);
+    let mut counts = [0u32; 16];
+    for &(_, v) in &tokens {
+        counts[v as usize] += 1;
+    }
+    write_alphabet_size(w, alphabet_size(&counts));
+    let code = write_prefix_histogram(w, &counts);
+    for &(_, v) in &tokens {
+        code.write_symbol(w, v as usize);
+    }
+}
+
+// Gradient predictor residuals for one channel, already packed as unsigned values.
+fn channel_residuals(plane: &[i32], width: usize, height: usize) -> Vec<u32> {
+    let mut out = Vec::with_capacity(plane.len());
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let (w, n, nw) = if y == 0 {
+                if x == 0 {
+                    (0, 0, 0)
+                } else {
+                    let l = plane[i - 1];
+                    (l, l, l)
+                }
+            } else if x == 0 {
+                let up = plane[i - width];
+                (up, up, up)
+            } else {
+                (plane[i - 1], plane[i - width], plane[i - width - 1])
+            };
+            let prediction = (w + n - nw).clamp(w.min(n), w.max(n));
+            out.push(pack_signed(plane[i] - prediction));
+        }
+    }
+    out
+}
+
+// Local modular header + tree + histograms + channel data for Y/Co/Cg planes.
+fn write_modular_channels(w: &mut BitWriter, planes: &[Vec<i32>; 3], width: usize, height: usize) {
+    write_ma_tree(w, true);
+
+    let residuals: Vec<Vec<u32>> = planes.iter().map(|p| channel_residuals(p, width, height)).collect();
+    let mut counts = vec![vec![0u32; 48]; 3];
+    for (c, res) in residuals.iter().enumerate() {
+        let hist = &mut counts[CHANNEL_CONTEXT[c]];
+        for &v in res {
+            hist[hybrid_token(v).0] += 1;
+        }
+    }
+
+    w.write_bool(false); // no LZ77
+    w.write_bool(true); // simple clustering
+    w.write(2, 2);
+    for ctx in 0..3 {
+        w.write(2, ctx);
+    }
+    w.write_bool(true); // prefix codes
+    for _ in 0..3 {
+        write_hybrid_config(w);
+    }
+    for hist in &counts {
+        write_alphabet_size(w, alphabet_size(hist));
+    }
+    let codes: Vec<PrefixCode> = counts.iter().map(|hist| write_prefix_histogram(w, hist)).collect();
+
+    for (c, res) in residuals.iter().enumerate() {
+        let code = &codes[CHANNEL_CONTEXT[c]];
+        for &v in res {
+            let (token, nbits, bits) = hybrid_token(v);
+            code.write_symbol(w, token);
+            w.write(nbits, bits as u64);
+        }
+    }
+}
+
+fn write_group_header(w: &mut BitWriter, with_rct: bool) {
+    w.write_bool(false); // use_global_tree
+    w.write_bool(true); // default weighted predictor params
+    if with_rct {
+        w.write(2, 1); // one transform
+        w.write(2, 0); // RCT
+        w.write_u32_sel(0, 3, 0); // begin_c
+        w.write(2, 0); // type 6: YCoCg
+    } else {
+        w.write(2, 0);
+    }
+}
+
+// Forward YCoCg-R, the inverse of RCT type 6.
+#[inline(always)]
+fn forward_rct(r: i32, g: i32, b: i32) -> (i32, i32, i32) {
+    let co = r - b;
+    let tmp = b + (co >> 1);
+    let cg = g - tmp;
+    let y = tmp + (cg >> 1);
+    (y, co, cg)
+}
+
+pub(crate) struct JxlEncoder {
+    width: u32,
+    height: u32,
+    sixteen_bit: bool,
+    color_space: OutputColorSpace,
+    // One row of groups, interleaved RGB samples.
+    rows: Vec<u16>,
+    rows_filled: u32,
+    groups: Vec<Vec<u8>>,
+    single_group: Option<Vec<u8>>,
+}
+
+impl JxlEncoder {
+    pub(crate) fn new(width: u32, height: u32, sixteen_bit: bool, color_space: OutputColorSpace) -> Result<Self> {
+        let len = (width as usize)
+            .checked_mul(GROUP_DIM.min(height) as usize)
+            .and_then(|v| v.checked_mul(3))
+            .context("JPEG XL row buffer overflow")?;
+        Ok(Self {
+            width,
+            height,
+            sixteen_bit,
+            color_space,
+            rows: crate::try_alloc_vec(len, 0u16)?,
+            rows_filled: 0,
+            groups: Vec::new(),
+            single_group: None,
+        })
+    }
+
+    fn is_single_group(&self) -> bool {
+        self.width <= GROUP_DIM && self.height <= GROUP_DIM
+    }
+
+    pub(crate) fn write_rows(&mut self, band: &[f32]) -> Result<()> {
+        let max = if self.sixteen_bit { 65535.0 } else { 255.0 };
+        let row_len = self.width as usize * 3;
+        for row in band.chunks_exact(row_len) {
+            let start = self.rows_filled as usize * row_len;
+            for (dst, &v) in self.rows[start..start + row_len].iter_mut().zip(row) {
+                *dst = (v.clamp(0.0, 1.0) * max).round() as u16;
+            }
+            self.rows_filled += 1;
+            let group_row_start = self.groups.len() as u32 / self.groups_x().max(1);
+            let remaining = self.height - group_row_start * GROUP_DIM;
+            if self.rows_filled == GROUP_DIM.min(remaining) {
+                self.flush_group_row();
+            }
+        }
+        Ok(())
+    }
+
+    fn groups_x(&self) -> u32 {
+        (self.width + GROUP_DIM - 1) / GROUP_DIM
+    }
+
+    fn group_planes(&self, x0: u32, gw: u32, gh: u32) -> [Vec<i32>; 3] {
+        let mut planes = [
+            Vec::with_capacity((gw * gh) as usize),
+            Vec::with_capacity((gw * gh) as usize),
+            Vec::with_capacity((gw * gh) as usize),
+        ];
+        for y in 0..gh {
+            let row = &self.rows[(y * self.width) as usize * 3..];
+            for x in x0..x0 + gw {
+                let i = x as usize * 3;
+                let (yy, co, cg) = forward_rct(row[i] as i32, row[i + 1] as i32, row[i + 2] as i32);
+                planes[0].push(yy);
+                planes[1].push(co);
+                planes[2].push(cg);
+            }
+        }
+        planes
+    }
+
+    fn flush_group_row(&mut self) {
+        let gh = self.rows_filled;
+        if self.is_single_group() {
+            let planes = self.group_planes(0, self.width, gh);
+            let mut w = BitWriter::new();
+            w.write_bool(true); // LfChannelDequantization: default
+            w.write_bool(false); // no global tree
+            write_group_header(&mut w, true);
+            write_modular_channels(&mut w, &planes, self.width as usize, gh as usize);
+            self.single_group = Some(w.finish());
+        } else {
+            let encoded: Vec<Vec<u8>> = (0..self.groups_x())
+                .into_par_iter()
+                .map(|gx| {
+                    let x0 = gx * GROUP_DIM;
+                    let gw = GROUP_DIM.min(self.width - x0);
+                    let planes = self.group_planes(x0, gw, gh);
+                    let mut w = BitWriter::new();
+                    write_group_header(&mut w, false);
+                    write_modular_channels(&mut w, &planes, gw as usize, gh as usize);
+                    w.finish()
+                })
+                .collect();
+            self.groups.extend(encoded);
+        }
+        self.rows_filled = 0;
+    }
+
+    pub(crate) fn finish(self) -> Result<Vec<u8>> {
+        let mut w = BitWriter::new();
+        write_image_header(&mut w, self.width, self.height, self.sixteen_bit, self.color_space);
+        write_frame_header(&mut w);
+
+        let sections: Vec<Vec<u8>> = if let Some(all) = self.single_group {
+            vec![all]
+        } else {
+            // The color channels don't fit a single group, so the global stream only declares the
+            // transform and a trivial tree; all samples live in the pass groups.
+            let mut lf_global = BitWriter::new();
+            lf_global.write_bool(true);
+            lf_global.write_bool(false);
+            write_group_header(&mut lf_global, true);
+            write_ma_tree(&mut lf_global, false);
+            lf_global.write_bool(false); // no LZ77
+            lf_global.write_bool(true); // prefix codes
+            write_hybrid_config(&mut lf_global);
+            write_alphabet_size(&mut lf_global, 1);
+
+            let lf_dim = GROUP_DIM * 8;
+            let num_lf_groups = ((self.width + lf_dim - 1) / lf_dim * ((self.height + lf_dim - 1) / lf_dim)) as usize;
+            let expected = (self.groups_x() * ((self.height + GROUP_DIM - 1) / GROUP_DIM)) as usize;
+            if self.groups.len() != expected {
+                return Err(anyhow::anyhow!("JPEG XL encoder received incomplete image"));
+            }
+            let mut sections = vec![lf_global.finish()];
+            sections.extend(std::iter::repeat(Vec::new()).take(num_lf_groups + 1));
+            sections.extend(self.groups);
+            sections
+        };
+
+        w.write_bool(false); // TOC not permuted
+        w.zero_pad();
+        for section in &sections {
+            let size = section.len() as u64;
+            if size < 1024 {
+                w.write_u32_sel(0, 10, size);
+            } else if size < 17408 {
+                w.write_u32_sel(1, 14, size - 1024);
+            } else if size < 4211712 {
+                w.write_u32_sel(2, 22, size - 17408);
+            } else {
+                w.write_u32_sel(3, 30, size - 4211712);
+            }
+        }
+        w.zero_pad();
+
+        let mut out = w.finish();
+        for section in sections {
+            out.extend_from_slice(&section);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Noise with a few flat and smooth areas so every predictor branch and both short and long
+    // prefix codes get exercised.
+    fn test_image(width: u32, height: u32) -> Vec<f32> {
+        let mut state = 0x2545_f491u32;
+        let mut out = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..3 {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    let v = match (x / 16 + y / 16 + c) % 3 {
+                        0 => (state >> 8) as f32 / (1u32 << 24) as f32,
+                        1 => (x + y) as f32 / (width + height) as f32,
+                        _ => 0.5,
+                    };
+                    out.push(v);
+                }
+            }
+        }
+        out
+    }
+
+    fn encode(pixels: &[f32], width: u32, height: u32, sixteen_bit: bool, space: OutputColorSpace) -> Vec<u8> {
+        let mut encoder = JxlEncoder::new(width, height, sixteen_bit, space).unwrap();
+        // Odd band heights, so bands straddle group rows like the tiled export does.
+        for band in pixels.chunks(width as usize * 3 * 37) {
+            encoder.write_rows(band).unwrap();
+        }
+        encoder.finish().unwrap()
+    }
+
+    fn assert_round_trip(width: u32, height: u32, sixteen_bit: bool, space: OutputColorSpace) {
+        let pixels = test_image(width, height);
+        let bytes = encode(&pixels, width, height, sixteen_bit, space);
+        let image = jxl_oxide::JxlImage::builder().read(bytes.as_slice()).unwrap();
+        assert_eq!((image.width(), image.height()), (width, height));
+
+        let decoded = image.render_frame(0).unwrap().image_all_channels();
+        let max = if sixteen_bit { 65535.0 } else { 255.0 };
+        assert_eq!(decoded.buf().len(), pixels.len());
+        for (i, (&got, &want)) in decoded.buf().iter().zip(&pixels).enumerate() {
+            assert_eq!((got * max).round(), (want * max).round(), "{}x{} sample {}", width, height, i);
+        }
+    }
+
+    #[test]
+    fn round_trips_8_bit() {
+        assert_round_trip(1, 1, false, OutputColorSpace::Srgb);
+        assert_round_trip(200, 150, false, OutputColorSpace::Srgb);
+        assert_round_trip(257, 300, false, OutputColorSpace::Srgb);
+        assert_round_trip(600, 513, false, OutputColorSpace::DisplayP3);
+    }
+
+    #[test]
+    fn round_trips_16_bit() {
+        assert_round_trip(255, 256, true, OutputColorSpace::AdobeRgb);
+        assert_round_trip(300, 257, true, OutputColorSpace::ProPhoto);
+    }
+
+    // More than one LF group (2048 px) across, so the LF group sections in the TOC are counted.
+    #[test]
+    fn round_trips_wide_image() {
+        assert_round_trip(2100, 40, false, OutputColorSpace::Srgb);
+    }
+
+    #[test]
+    fn rejects_incomplete_image() {
+        let pixels = test_image(300, 300);
+        let mut encoder = JxlEncoder::new(300, 300, false, OutputColorSpace::Srgb).unwrap();
+        encoder.write_rows(&pixels[..300 * 3 * 260]).unwrap();
+        assert!(encoder.finish().is_err());
+    }
+}
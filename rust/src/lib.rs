@@ -1,20 +1,57 @@
 //Code taken from RapidRAW by CyberTimon
 //https://github.com/CyberTimon/RapidRAW
 
+mod analysis;
+mod autocrop;
+mod canvas;
+mod color_space;
+mod compare;
+mod dust;
+mod export;
+mod eye;
+mod fill;
+mod histogram;
+mod jxl;
+mod lut;
+mod metadata;
 mod model;
+mod perspective;
 mod raw_processing;
-
+mod resample;
+mod retouch;
+mod sharpen;
+mod sizing;
+mod ultrahdr;
+mod upright;
+mod watermark;
+
+use analysis::Analysis;
 use anyhow::{Context, Result};
 use base64::Engine;
+use canvas::Canvas;
+use color_space::OutputTransform;
+use compare::Comparison;
+use export::ExportEncoder;
+use eye::Eyes;
+use fill::EdgeFill;
+use histogram::Histograms;
+use metadata::ExportMetadata;
+use perspective::Homography;
+use retouch::Retouch;
+use sharpen::OutputSharpener;
+use ultrahdr::GainMapBuilder;
+use watermark::Watermark;
 use image::{
     codecs::jpeg::JpegEncoder,
+    codecs::png::PngEncoder,
     imageops::FilterType,
     ExtendedColorType,
     ImageBuffer,
+    ImageEncoder,
     DynamicImage,
 };
-use jni::objects::{JByteArray, JClass, JString};
-use jni::sys::{jbyteArray, jlong, jstring, jint, jboolean};
+use jni::objects::{JByteArray, JClass, JLongArray, JObject, JString, JValue};
+use jni::sys::{jbyteArray, jlong, jobject, jstring, jint, jboolean, jfloat, JNI_FALSE, JNI_TRUE};
 use jni::JNIEnv;
 use log::error;
 #[cfg(target_os = "android")]
@@ -27,6 +64,9 @@ use serde_json::json;
 use serde_json::from_str;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Seek, Write};
+use std::os::fd::BorrowedFd;
 use std::panic;
 use std::ptr;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -1503,6 +1543,13 @@ fn detail_blur_index(blurs: &DetailBlurLuma, full_x: u32, full_y: u32) -> Option
     Some((local_y * blurs.width + local_x) as usize)
 }
 
+// Source luminance at (x, y) of the full frame and its sharpening blur, for focus peaking.
+fn source_detail(linear: &[f32], width: u32, blurs: &DetailBlurLuma, x: u32, y: u32) -> Option<(f32, f32)> {
+    let blurred = *blurs.sharpness.as_ref()?.get(detail_blur_index(blurs, x, y)?)?;
+    let base = ((y * width + x) * 3) as usize;
+    Some((get_luma([*linear.get(base)?, *linear.get(base + 1)?, *linear.get(base + 2)?]), blurred))
+}
+
 fn sample_linear_color(linear: &[f32], width: u32, height: u32, x: u32, y: u32) -> [f32; 3] {
     if width == 0 || height == 0 {
         return [0.0, 0.0, 0.0];
@@ -1627,6 +1674,16 @@ const HSL_RANGES: [(f32, f32); 8] = [
     (330.0, 50.0), // Magentas
 ];
 
+// Gaussian influence of each HSL_RANGES band at `hue`.
+fn hue_band_influences(hue: f32) -> [f32; 8] {
+    HSL_RANGES.map(|(center, width)| {
+        let dist = (hue - center).abs();
+        let dist = dist.min(360.0 - dist);
+        let falloff = dist / (width * 0.5);
+        (-1.5 * falloff * falloff).exp()
+    })
+}
+
 fn apply_hsl_panel(color: [f32; 3], hsl_adjustments: &[HslColorValues; 8]) -> [f32; 3] {
     if hsl_adjustments.iter().all(|adj| {
         adj.hue.abs() <= 0.000001
@@ -1658,12 +1715,7 @@ fn apply_hsl_panel(color: [f32; 3], hsl_adjustments: &[HslColorValues; 8]) -> [f
     let mut total_lum_adj = 0.0;
     let mut total_weight = 0.0;
 
-    for (i, (center, width)) in HSL_RANGES.iter().enumerate() {
-        let dist = (original_hue - center).abs();
-        let dist = dist.min(360.0 - dist);
-        let falloff = dist / (width * 0.5);
-        let influence = (-1.5 * falloff * falloff).exp();
-
+    for (i, influence) in hue_band_influences(original_hue).into_iter().enumerate() {
         total_weight += influence;
 
         let adj = &hsl_adjustments[i];
@@ -1706,6 +1758,34 @@ fn apply_hsl_panel(color: [f32; 3], hsl_adjustments: &[HslColorValues; 8]) -> [f
     [rgb_shifted[0] * scale, rgb_shifted[1] * scale, rgb_shifted[2] * scale]
 }
 
+fn apply_monochrome(color: [f32; 3], monochrome: &MonochromeValues) -> [f32; 3] {
+    if !monochrome.enabled {
+        return color;
+    }
+    let [wr, wg, wb] = monochrome.weights;
+    let mut grey = (color[0] * wr + color[1] * wg + color[2] * wb).max(0.0);
+
+    if monochrome.mixer.iter().any(|v| v.abs() > 0.000001) {
+        let [hue, sat, _] = rgb_to_hsv(color);
+        let influences = hue_band_influences(hue);
+        let total_weight: f32 = influences.iter().sum();
+        if total_weight > 0.0001 {
+            let adjustment = influences.iter().zip(&monochrome.mixer).map(|(i, m)| i * m).sum::<f32>() / total_weight;
+            // Same saturation ramp as the HSL panel's luminance, so greys keep their value.
+            grey *= (1.0 + adjustment * smoothstep(0.0, 1.0, sat)).max(0.0);
+        }
+    }
+
+    let strength = monochrome.tone_saturation.clamp(0.0, 1.0);
+    if strength <= 0.000001 {
+        return [grey, grey, grey];
+    }
+    // Tint scaled to unit luminance so toning doesn't change brightness.
+    let tint = hsv_to_rgb(monochrome.tone_hue.rem_euclid(360.0), strength, 1.0);
+    let tint_luma = get_luma(tint).max(0.0001);
+    [grey * tint[0] / tint_luma, grey * tint[1] / tint_luma, grey * tint[2] / tint_luma]
+}
+
 fn apply_color_grading(
     color: [f32; 3],
     shadows: ColorGradeSettings,
@@ -2080,7 +2160,58 @@ fn apply_local_contrast_from_luma(color: [f32; 3], blurred_luma: f32, amount: f3
     ]
 }
 
-fn apply_color_adjustments(mut colors: [f32; 3], settings: &AdjustmentValues, centre_mask: f32) -> [f32; 3] {
+// Camera calibration and channel mixer. The global one runs on the linear pixel as it comes from
+// the camera, before the default curve; a mask's own calibration applies on top of the composite.
+#[derive(Clone, Copy)]
+pub(crate) struct Calibration {
+    matrix: Option<[[f32; 3]; 3]>,
+    shadow_tint: f32,
+}
+
+impl Calibration {
+    fn of(settings: &AdjustmentValues) -> Self {
+        Self { matrix: settings.color_matrix, shadow_tint: settings.calibration.shadow_tint }
+    }
+
+    // Moves the calibration out of `settings`, so the colour adjustments don't apply it again.
+    pub(crate) fn take(settings: &mut AdjustmentValues) -> Self {
+        let calibration = Self::of(settings);
+        settings.color_matrix = None;
+        settings.calibration.shadow_tint = 0.0;
+        calibration
+    }
+
+    pub(crate) fn apply(&self, mut colors: [f32; 3]) -> [f32; 3] {
+        if let Some(m) = self.matrix.as_ref() {
+            colors = [0, 1, 2].map(|r| m[r][0] * colors[0] + m[r][1] * colors[1] + m[r][2] * colors[2]);
+        }
+        let tint = self.shadow_tint;
+        if tint.abs() > 0.000001 {
+            let luma = get_luma([colors[0].max(0.0), colors[1].max(0.0), colors[2].max(0.0)]);
+            // Same green/magenta balance as the Tint slider, fading out above middle grey.
+            let t = tint * 0.25 * (1.0 - smoothstep(0.0, 0.18, luma));
+            colors = [colors[0] * (1.0 + t), colors[1] * (1.0 - t), colors[2] * (1.0 + t)];
+        }
+        colors
+    }
+}
+
+// Keep HDR headroom for tone mapping later, but clamp for safety to avoid NaNs/inf and
+// runaway values on extreme slider settings.
+const MAX_HDR: f32 = 64.0;
+
+fn apply_color_adjustments(colors: [f32; 3], settings: &AdjustmentValues, centre_mask: f32) -> [f32; 3] {
+    adjust_colors(colors, settings, centre_mask, 0.0)
+}
+
+// Wide-gamut exports keep what the edit pushes outside sRGB as negative components.
+fn apply_color_adjustments_wide(colors: [f32; 3], settings: &AdjustmentValues, centre_mask: f32) -> [f32; 3] {
+    adjust_colors(colors, settings, centre_mask, -MAX_HDR)
+}
+
+fn adjust_colors(mut colors: [f32; 3], settings: &AdjustmentValues, centre_mask: f32, floor: f32) -> [f32; 3] {
+    colors = Calibration::of(settings).apply(colors);
+
     // Exposure (linear, RapidRAW-like): color *= 2^exposure
     if settings.exposure != 0.0 {
         let factor = 2f32.powf(settings.exposure);
@@ -2121,6 +2252,9 @@ fn apply_color_adjustments(mut colors: [f32; 3], settings: &AdjustmentValues, ce
     // RapidRAW-like HSL panel (Reds/Oranges/.../Magentas)
     colors = apply_hsl_panel(colors, &settings.hsl);
 
+    // Black & white conversion; the grading wheels below can still split-tone the result.
+    colors = apply_monochrome(colors, &settings.monochrome);
+
     // RapidRAW-like color grading wheels (shadows/midtones/highlights)
     colors = apply_color_grading(
         colors,
@@ -2133,15 +2267,12 @@ fn apply_color_adjustments(mut colors: [f32; 3], settings: &AdjustmentValues, ce
 
     colors = apply_creative_color(colors, settings.saturation, settings.vibrance);
     
-    // Keep HDR headroom for tone mapping later, but clamp for safety to avoid NaNs/inf and
-    // runaway values on extreme slider settings.
-    const MAX_HDR: f32 = 64.0;
     for channel in colors.iter_mut() {
         if !channel.is_finite() {
             *channel = 0.0;
             continue;
         }
-        *channel = channel.clamp(0.0, MAX_HDR);
+        *channel = channel.clamp(floor, MAX_HDR);
     }
 
     colors
@@ -2243,6 +2374,28 @@ fn tone_map(colors: [f32; 3], mapper: ToneMapper) -> [f32; 3] {
     }
 }
 
+// Same curves as `tone_map`, for wide-gamut exports: negative components (colours outside sRGB)
+// pass through instead of being clipped.
+fn tone_map_wide(colors: [f32; 3], mapper: ToneMapper) -> [f32; 3] {
+    match mapper {
+        ToneMapper::Basic => colors.map(|c| c.signum() * tonemap_aces_fitted(c.abs())),
+        ToneMapper::Agx => {
+            let luma = get_luma(colors);
+            if !luma.is_finite() || luma <= 1.0e-6 {
+                return [0.0, 0.0, 0.0];
+            }
+            let scale = tonemap_aces_fitted(luma) / luma;
+            let mut out = colors.map(|c| c * scale);
+            let out_luma = get_luma(out);
+            let desat = smoothstep(0.75, 1.0, out_luma);
+            for c in out.iter_mut() {
+                *c = (*c + (out_luma - *c) * desat * 0.25).min(1.0);
+            }
+            out
+        }
+    }
+}
+
 fn linear_to_srgb(linear: f32) -> f32 {
     // Encode scene-linear (extended range allowed) into sRGB.
     let v = linear.max(0.0);
@@ -2335,11 +2488,12 @@ fn compact_image(input: &LinearImage) -> CompactImage {
 }
 
 // Decode RAW to compact u16 format without rotation (for export)
+// `target_size` receives the decoded size and orientation and returns the size to resample to.
 fn decode_raw_to_compact(
     raw_bytes: &[u8],
     fast_demosaic: bool,
-    max_w: Option<u32>,
-    max_h: Option<u32>
+    target_size: impl FnOnce(u32, u32, Orientation) -> (u32, u32),
+    filter: ResampleFilter,
 ) -> Result<(CompactImage, Orientation)> {
     let highlight_compression = 2.5;
 
@@ -2351,46 +2505,24 @@ fn decode_raw_to_compact(
     let src_h = dyn_img.height();
 
     // 2. Calculate target dimensions
-    let mut dst_w = src_w;
-    let mut dst_h = src_h;
-
-    if let (Some(mw), Some(mh)) = (max_w, max_h) {
-        if src_w > mw || src_h > mh {
-            let scale = (mw as f32 / src_w as f32).min(mh as f32 / src_h as f32);
-            dst_w = (src_w as f32 * scale).max(1.0) as u32;
-            dst_h = (src_h as f32 * scale).max(1.0) as u32;
-        }
-    }
-
-    // 3. Resize if needed (using nearest neighbor for speed)
-    let output_len = (dst_w as usize) * (dst_h as usize) * 3;
-    let mut raw_u16 = Vec::with_capacity(output_len);
-
-    match dyn_img {
-        DynamicImage::ImageRgb16(buf) => {
-            let s = buf.as_raw();
-            let x_ratio = src_w as f32 / dst_w as f32;
-            let y_ratio = src_h as f32 / dst_h as f32;
-
-            for y in 0..dst_h {
-                let sy = ((y as f32) * y_ratio).min((src_h - 1) as f32) as u32;
-                let y_offset = (sy * src_w) as usize * 3;
-                for x in 0..dst_w {
-                    let sx = ((x as f32) * x_ratio).min((src_w - 1) as f32) as u32;
-                    let idx = y_offset + (sx as usize * 3);
-                    raw_u16.push(s[idx]);
-                    raw_u16.push(s[idx+1]);
-                    raw_u16.push(s[idx+2]);
-                }
-            }
-        },
+    let (dst_w, dst_h) = target_size(src_w, src_h, orientation);
+
+    // 3. Resize if needed (the decoded data is linear, so this filters in linear light)
+    let buf = match dyn_img {
+        DynamicImage::ImageRgb16(buf) => buf,
         _ => return Err(anyhow::anyhow!("Unexpected image format")),
+    };
+    if dst_w == src_w && dst_h == src_h {
+        return Ok((buf, orientation));
     }
+    let raw_u16 = resample::resize_rgb16(buf.as_raw(), src_w, src_h, dst_w, dst_h, filter)?;
+    drop(buf);
 
     Ok((ImageBuffer::from_vec(dst_w, dst_h, raw_u16).unwrap(), orientation))
 }
 
 // Virtual transformation state - no physical rotation needed
+#[derive(Clone)]
 struct TransformState {
     source_w: u32,
     source_h: u32,
@@ -2401,8 +2533,13 @@ struct TransformState {
     flip_v: bool,
     rotation_rad: f32,
     crop: Option<CropPayload>,
+    perspective: Option<Homography>,
     center_x: f32,
     center_y: f32,
+    // Output pixel -> natural output position: (scale_x, scale_y, offset_x, offset_y).
+    output_map: Option<[f32; 4]>,
+    // Camera orientation alone as (steps, flip_h, flip_v).
+    base_orientation: (u8, bool, bool),
 }
 
 impl TransformState {
@@ -2450,17 +2587,68 @@ impl TransformState {
             flip_v: total_flip_v,
             rotation_rad: payload.rotation.to_radians(),
             crop: payload.crop.clone(),
-            center_x: (final_w as f32 - 1.0) / 2.0,
-            center_y: (final_h as f32 - 1.0) / 2.0,
+            perspective: Homography::keystone(&payload.perspective, rotated_w, rotated_h),
+            // Rotation pivots on the uncropped image, as in `apply_geometry`.
+            center_x: (rotated_w as f32 - 1.0) / 2.0,
+            center_y: (rotated_h as f32 - 1.0) / 2.0,
+            output_map: None,
+            base_orientation: (base_steps, base_flip_h, base_flip_v),
+        }
+    }
+
+    // Resamples the natural output to exactly `width` x `height`: stretched by the sub-pixel
+    // rounding difference when fitting, centre-cropped when filling.
+    fn with_output_size(mut self, width: u32, height: u32, fill: bool) -> Self {
+        if width == self.output_w && height == self.output_h {
+            return self;
+        }
+        let mut sx = self.output_w as f32 / width as f32;
+        let mut sy = self.output_h as f32 / height as f32;
+        if fill {
+            sx = sx.min(sy);
+            sy = sx;
+        }
+        let mut ox = (self.output_w as f32 - width as f32 * sx) / 2.0;
+        let mut oy = (self.output_h as f32 - height as f32 * sy) / 2.0;
+        // Compose with an earlier resize so the result still maps to the natural output.
+        if let Some([psx, psy, pox, poy]) = self.output_map {
+            ox = ox * psx + pox;
+            oy = oy * psy + poy;
+            sx *= psx;
+            sy *= psy;
         }
+        self.output_map = Some([sx, sy, ox, oy]);
+        self.output_w = width;
+        self.output_h = height;
+        self
     }
 
     // Maps output (x,y) to source (sx,sy) coordinates - the magic of virtual rotation
     #[inline(always)]
     fn map_coord(&self, x: u32, y: u32) -> Option<(f32, f32)> {
+        let (sx, sy) = self.map_coord_unbounded(x, y)?;
+
+        // Bounds check
+        if sx < 0.0 || sy < 0.0 || sx >= self.source_w as f32 || sy >= self.source_h as f32 {
+            return None;
+        }
+
+        Some((sx, sy))
+    }
+
+    // Like `map_coord`, but positions outside the source are returned for boundary fill. Only
+    // `None` where keystone correction has no image at all.
+    #[inline(always)]
+    fn map_coord_unbounded(&self, x: u32, y: u32) -> Option<(f32, f32)> {
         let mut fx = x as f32;
         let mut fy = y as f32;
 
+        // 0. Inverse output resize (pixel centres)
+        if let Some([sx, sy, ox, oy]) = self.output_map {
+            fx = (fx + 0.5) * sx + ox - 0.5;
+            fy = (fy + 0.5) * sy + oy - 0.5;
+        }
+
         // 1. Inverse Crop (Add offset)
         if let Some(crop) = &self.crop {
             let (oriented_w, oriented_h) = if self.orientation_steps % 2 == 1 { 
@@ -2484,71 +2672,92 @@ impl TransformState {
             fy = -dx * sin_a + dy * cos_a + self.center_y;
         }
 
-        // 3. Inverse Flips
-        let (cur_w, cur_h) = if self.orientation_steps % 2 == 1 {
+        // 3. Inverse Perspective
+        if let Some(perspective) = &self.perspective {
+            (fx, fy) = perspective.unwarp(fx, fy)?;
+        }
+
+        // 4-5. Inverse flips and orientation
+        Some(self.unorient(self.orientation_steps, self.flip_h, self.flip_v, fx, fy))
+    }
+
+    #[inline(always)]
+    fn unorient(&self, steps: u8, flip_h: bool, flip_v: bool, mut fx: f32, mut fy: f32) -> (f32, f32) {
+        // 4. Inverse Flips
+        let (cur_w, cur_h) = if steps % 2 == 1 {
             (self.source_h, self.source_w)
         } else {
             (self.source_w, self.source_h)
         };
 
-        if self.flip_h { fx = cur_w as f32 - 1.0 - fx; }
-        if self.flip_v { fy = cur_h as f32 - 1.0 - fy; }
+        if flip_h { fx = cur_w as f32 - 1.0 - fx; }
+        if flip_v { fy = cur_h as f32 - 1.0 - fy; }
 
-        // 4. Inverse Orientation (Swap/Flip coords to match source)
+        // 5. Inverse Orientation (Swap/Flip coords to match source)
         // CRITICAL FIX: Use correct dimensions after swap for each rotation
-        let (sx, sy) = match self.orientation_steps {
+        match steps {
             0 => (fx, fy),
             1 => (fy, (self.source_h as f32 - 1.0) - fx), // 90° CW: x maps to y, y maps to (H-1-x)
             2 => ((self.source_w as f32 - 1.0) - fx, (self.source_h as f32 - 1.0) - fy), // 180°
             3 => ((self.source_w as f32 - 1.0) - fy, fx), // 270° CW: x maps to (W-1-y), y maps to x
             _ => (fx, fy),
-        };
-
-        // Bounds check
-        if sx < 0.0 || sy < 0.0 || sx >= self.source_w as f32 || sy >= self.source_h as f32 {
-            return None;
         }
+    }
 
-        Some((sx, sy))
+    // Normalized position in the image as shot (camera orientation applied, no edits) -> source
+    // pixel. Retouch spots are stored in that frame.
+    fn shot_to_source(&self, x: f32, y: f32) -> (f32, f32) {
+        let (steps, flip_h, flip_v) = self.base_orientation;
+        let (w, h) = if steps % 2 == 1 { (self.source_h, self.source_w) } else { (self.source_w, self.source_h) };
+        let fx = x * (w as f32 - 1.0).max(1.0);
+        let fy = y * (h as f32 - 1.0).max(1.0);
+        self.unorient(steps, flip_h, flip_v, fx, fy)
     }
 }
 
-// Sample from u16 image using bilinear interpolation, return f32
-fn sample_virtual(img: &CompactImage, x: f32, y: f32) -> [f32; 3] {
-    let w = img.width();
-    let h = img.height();
-    
-    let x0 = x.floor() as u32;
-    let y0 = y.floor() as u32;
-    let x1 = (x0 + 1).min(w - 1);
-    let y1 = (y0 + 1).min(h - 1);
-    
-    let wx = x - x0 as f32;
-    let wy = y - y0 as f32;
-    
-    let get_p = |xx, yy| {
+// Source-space edits the virtual sampler applies while reading the compact image.
+struct SourceLayers {
+    retouch: Option<Retouch>,
+    edge_fill: Option<EdgeFill>,
+}
+
+impl SourceLayers {
+    fn new(source: &CompactImage, transform: &TransformState, payload: &AdjustmentsPayload) -> Self {
+        let raw_pixel = |x: u32, y: u32| source.get_pixel(x, y).0.map(|v| v as f32 / 65535.0);
+        let retouch = Retouch::new(
+            &payload.retouch,
+            source.width(),
+            source.height(),
+            |x, y| transform.shot_to_source(x, y),
+            raw_pixel,
+        );
+        // Only rotation and keystone correction can reach outside the source.
+        let geometry_active = transform.rotation_rad.abs() > 0.0001 || transform.perspective.is_some();
+        let edge_fill = (payload.boundary_fill == BoundaryFill::Extend && geometry_active).then(|| {
+            EdgeFill::new(source.width(), source.height(), |x, y| {
+                retouch.as_ref().and_then(|r| r.get(x, y)).unwrap_or_else(|| raw_pixel(x, y))
+            })
+        });
+        Self { retouch, edge_fill }
+    }
+}
+
+// Sample from u16 image using bicubic interpolation, return f32
+fn sample_virtual(img: &CompactImage, retouch: Option<&Retouch>, x: f32, y: f32) -> [f32; 3] {
+    resample::sample_bicubic(img.width(), img.height(), x, y, |xx, yy| {
+        if let Some(p) = retouch.and_then(|r| r.get(xx, yy)) {
+            return p;
+        }
         let p = img.get_pixel(xx, yy);
         [p[0] as f32 / 65535.0, p[1] as f32 / 65535.0, p[2] as f32 / 65535.0]
-    };
-    
-    let p00 = get_p(x0, y0);
-    let p10 = get_p(x1, y0);
-    let p01 = get_p(x0, y1);
-    let p11 = get_p(x1, y1);
-    
-    let mut out = [0.0; 3];
-    for i in 0..3 {
-        let top = p00[i] * (1.0 - wx) + p10[i] * wx;
-        let bot = p01[i] * (1.0 - wx) + p11[i] * wx;
-        out[i] = top * (1.0 - wy) + bot * wy;
-    }
-    out
+    })
 }
 
 // Helper to extract a small f32 tile for detail blur calculations
 fn extract_tile_f32(
     source: &CompactImage, 
     transform: &TransformState, 
+    layers: &SourceLayers,
     tx: u32, 
     ty: u32, 
     tw: u32, 
@@ -2557,9 +2766,14 @@ fn extract_tile_f32(
     let mut tile = Vec::with_capacity((tw * th * 3) as usize);
     for y in 0..th {
         for x in 0..tw {
+            let retouch = layers.retouch.as_ref();
             if let Some((sx, sy)) = transform.map_coord(tx + x, ty + y) {
-                let c = sample_virtual(source, sx, sy);
+                let c = sample_virtual(source, retouch, sx, sy);
                 tile.extend_from_slice(&c);
+            } else if let Some((fill, (sx, sy))) =
+                layers.edge_fill.as_ref().zip(transform.map_coord_unbounded(tx + x, ty + y))
+            {
+                tile.extend_from_slice(&fill.sample(sx, sy, |sx, sy| sample_virtual(source, retouch, sx, sy)));
             } else {
                 tile.extend_from_slice(&[0.0, 0.0, 0.0]);
             }
@@ -2604,8 +2818,18 @@ fn develop_preview_linear(
             let scale = (max_w as f32 / w).min(max_h as f32 / h);
             let target_w = ((w * scale).max(1.0)) as u32;
             let target_h = ((h * scale).max(1.0)) as u32;
-            // Use a faster filter for preview downscale (Triangle is a good quality/speed tradeoff).
-            dynamic_image = dynamic_image.resize(target_w, target_h, FilterType::Triangle);
+            // Mitchell has the shorter kernel of the two and doesn't ring around specular highlights.
+            dynamic_image = match dynamic_image {
+                DynamicImage::ImageRgb16(buf) => {
+                    let (w, h) = buf.dimensions();
+                    let resized =
+                        resample::resize_rgb16(buf.as_raw(), w, h, target_w, target_h, ResampleFilter::Mitchell)?;
+                    DynamicImage::ImageRgb16(
+                        ImageBuffer::from_vec(target_w, target_h, resized).context("Resized buffer size mismatch")?,
+                    )
+                }
+                other => other.resize(target_w, target_h, FilterType::Triangle),
+            };
         }
     }
 
@@ -2618,7 +2842,7 @@ fn develop_preview_linear(
     Ok(rotated.to_rgb32f())
 }
 
-fn rotate_about_center_rgb32f(image: &LinearImage, rotation_degrees: f32) -> LinearImage {
+fn rotate_about_center_rgb32f(image: &LinearImage, rotation_degrees: f32, fill: BoundaryFill) -> LinearImage {
     let angle = rotation_degrees % 360.0;
     if !angle.is_finite() || angle.abs() <= 0.0001 {
         return image.clone();
@@ -2660,7 +2884,7 @@ fn rotate_about_center_rgb32f(image: &LinearImage, rotation_degrees: f32) -> Lin
         [src[idx], src[idx + 1], src[idx + 2]]
     }
 
-    fn bilinear(src: &[f32], width: u32, height: u32, x: f32, y: f32) -> [f32; 3] {
+    fn bicubic(src: &[f32], width: u32, height: u32, x: f32, y: f32) -> [f32; 3] {
         if !x.is_finite() || !y.is_finite() {
             return [0.0, 0.0, 0.0];
         }
@@ -2669,29 +2893,13 @@ fn rotate_about_center_rgb32f(image: &LinearImage, rotation_degrees: f32) -> Lin
         if x < 0.0 || y < 0.0 || x > max_x || y > max_y {
             return [0.0, 0.0, 0.0];
         }
-
-        let x0 = x.floor() as u32;
-        let y0 = y.floor() as u32;
-        let x1 = (x0 + 1).min(width.saturating_sub(1));
-        let y1 = (y0 + 1).min(height.saturating_sub(1));
-
-        let tx = (x - x0 as f32).clamp(0.0, 1.0);
-        let ty = (y - y0 as f32).clamp(0.0, 1.0);
-
-        let p00 = get_pixel(src, width, x0, y0);
-        let p10 = get_pixel(src, width, x1, y0);
-        let p01 = get_pixel(src, width, x0, y1);
-        let p11 = get_pixel(src, width, x1, y1);
-
-        let mut out = [0.0f32; 3];
-        for c in 0..3 {
-            let a = p00[c] + (p10[c] - p00[c]) * tx;
-            let b = p01[c] + (p11[c] - p01[c]) * tx;
-            out[c] = a + (b - a) * ty;
-        }
-        out
+        resample::sample_bicubic(width, height, x, y, |px, py| get_pixel(src, width, px, py))
     }
 
+    let edge_fill = (fill == BoundaryFill::Extend).then(|| EdgeFill::new(width, height, |x, y| get_pixel(src, width, x, y)));
+    let max_x = width.saturating_sub(1) as f32;
+    let max_y = height.saturating_sub(1) as f32;
+
     for y in 0..height {
         for x in 0..width {
             let dx = x as f32 - cx;
@@ -2700,7 +2908,13 @@ fn rotate_about_center_rgb32f(image: &LinearImage, rotation_degrees: f32) -> Lin
             let src_x = cos_a * dx + sin_a * dy + cx;
             let src_y = -sin_a * dx + cos_a * dy + cy;
 
-            let rgb = bilinear(src, width, height, src_x, src_y);
+            let outside = src_x < 0.0 || src_y < 0.0 || src_x > max_x || src_y > max_y;
+            let rgb = match edge_fill.as_ref() {
+                Some(edge_fill) if outside => {
+                    edge_fill.sample(src_x, src_y, |sx, sy| bicubic(src, width, height, sx, sy))
+                }
+                _ => bicubic(src, width, height, src_x, src_y),
+            };
             let idx = ((y as usize) * (width as usize) + (x as usize)) * 3;
             out[idx..idx + 3].copy_from_slice(&rgb);
         }
@@ -2709,6 +2923,52 @@ fn rotate_about_center_rgb32f(image: &LinearImage, rotation_degrees: f32) -> Lin
     ImageBuffer::from_vec(width, height, out).unwrap_or_else(|| image.clone())
 }
 
+// Resamples `image` through a keystone correction; areas with no source become black unless
+// `fill` extends the image into them.
+fn warp_perspective_rgb32f(image: &LinearImage, perspective: &Homography, fill: BoundaryFill) -> LinearImage {
+    let (width, height) = image.dimensions();
+    let src = image.as_raw();
+    let len = match (width as usize).checked_mul(height as usize).and_then(|v| v.checked_mul(3)) {
+        Some(v) => v,
+        None => {
+            error!("Perspective buffer size overflow for {}x{}", width, height);
+            return image.clone();
+        }
+    };
+    let mut out = match try_alloc_vec(len, 0.0f32) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("OOM during perspective correction: {}", err);
+            return image.clone();
+        }
+    };
+
+    let get_pixel = |x: u32, y: u32| {
+        let idx = (y as usize * width as usize + x as usize) * 3;
+        [src[idx], src[idx + 1], src[idx + 2]]
+    };
+    let edge_fill = (fill == BoundaryFill::Extend).then(|| EdgeFill::new(width, height, get_pixel));
+    let max_x = width.saturating_sub(1) as f32;
+    let max_y = height.saturating_sub(1) as f32;
+    out.par_chunks_mut(width as usize * 3).enumerate().for_each(|(y, row)| {
+        for (x, px) in row.chunks_exact_mut(3).enumerate() {
+            let Some((sx, sy)) = perspective.unwarp(x as f32, y as f32) else {
+                continue;
+            };
+            let sharp = |sx, sy| resample::sample_bicubic(width, height, sx, sy, get_pixel);
+            if sx < 0.0 || sy < 0.0 || sx > max_x || sy > max_y {
+                if let Some(edge_fill) = edge_fill.as_ref() {
+                    px.copy_from_slice(&edge_fill.sample(sx, sy, sharp));
+                }
+                continue;
+            }
+            px.copy_from_slice(&sharp(sx, sy));
+        }
+    });
+
+    ImageBuffer::from_vec(width, height, out).unwrap_or_else(|| image.clone())
+}
+
 fn apply_crop_linear(image: &LinearImage, crop: &CropPayload) -> LinearImage {
     let (img_w, img_h) = image.dimensions();
     if img_w == 0 || img_h == 0 {
@@ -2786,13 +3046,43 @@ fn crop_rect_pixels(img_w: u32, img_h: u32, crop: &CropPayload) -> (u32, u32, u3
 }
 
 fn apply_transformations(mut linear: LinearImage, payload: &AdjustmentsPayload) -> LinearImage {
+    // Retouch spots live in the frame the preview is decoded in, before any edits.
+    let (width, height) = linear.dimensions();
+    let retouch = Retouch::new(
+        &payload.retouch,
+        width,
+        height,
+        |x, y| (x * (width as f32 - 1.0).max(1.0), y * (height as f32 - 1.0).max(1.0)),
+        |x, y| linear.get_pixel(x, y).0,
+    );
+    if let Some(retouch) = retouch {
+        retouch.apply(&mut linear, width);
+    }
+
+    linear = apply_geometry(linear, payload);
+
+    // Eye corrections are placed on the finished frame, like masks.
+    let (width, height) = linear.dimensions();
+    let frame = &linear;
+    let eyes = Eyes::new(&payload.eyes, width, height, |[x, y, w, h]| {
+        (y..y + h).flat_map(|y| (x..x + w).flat_map(move |x| frame.get_pixel(x, y).0)).collect()
+    });
+    if let Some(eyes) = eyes {
+        eyes.apply(&mut linear, [0, 0, width, height]);
+    }
+    linear
+}
+
+fn apply_geometry(mut linear: LinearImage, payload: &AdjustmentsPayload) -> LinearImage {
     let steps = payload.orientation_steps % 4;
     let flip_h = payload.flip_horizontal;
     let flip_v = payload.flip_vertical;
     let rotation = payload.rotation;
     let has_crop = payload.crop.is_some();
+    let has_perspective = payload.perspective != PerspectivePayload::default();
 
-    let has_transform = steps != 0 || flip_h || flip_v || rotation.abs() > 0.0001 || has_crop;
+    let has_transform =
+        steps != 0 || flip_h || flip_v || rotation.abs() > 0.0001 || has_crop || has_perspective;
     if !has_transform {
         return linear;
     }
@@ -2811,8 +3101,12 @@ fn apply_transformations(mut linear: LinearImage, payload: &AdjustmentsPayload)
         linear = image::imageops::flip_vertical(&linear);
     }
 
+    if let Some(perspective) = Homography::keystone(&payload.perspective, linear.width(), linear.height()) {
+        linear = warp_perspective_rgb32f(&linear, &perspective, payload.boundary_fill);
+    }
+
     if rotation.abs() > 0.0001 {
-        linear = rotate_about_center_rgb32f(&linear, rotation);
+        linear = rotate_about_center_rgb32f(&linear, rotation, payload.boundary_fill);
     }
 
     if let Some(crop) = payload.crop.as_ref() {
@@ -2827,8 +3121,36 @@ fn render_linear_with_payload(
     payload: &AdjustmentsPayload,
     mask_runtimes: &[MaskRuntime],
     fast_demosaic: bool,
+    histograms: Option<&mut Histograms>,
+) -> Result<Vec<u8>> {
+    let rgb = render_rgb_with_payload(linear_buffer, payload, mask_runtimes, histograms, None)?;
+    encode_preview_jpeg(&rgb, linear_buffer.width(), linear_buffer.height(), fast_demosaic)
+}
+
+fn encode_preview_jpeg(rgb: &[u8], width: u32, height: u32, fast_demosaic: bool) -> Result<Vec<u8>> {
+    let mut encoded = Vec::new();
+    let quality = if fast_demosaic { 88 } else { 96 };
+    let mut encoder = JpegEncoder::new_with_quality(&mut encoded, quality);
+    encoder.encode(rgb, width, height, ExtendedColorType::Rgb8)?;
+    Ok(encoded)
+}
+
+// Whether (x, y) lies outside `window` (x, y, width, height). Renders restricted to a window skip
+// those pixels and leave them black.
+fn outside_window(window: Option<[u32; 4]>, x: u32, y: u32) -> bool {
+    window.is_some_and(|[wx, wy, ww, wh]| x < wx || y < wy || x >= wx + ww || y >= wy + wh)
+}
+
+// Interleaved 8-bit sRGB render of the whole frame.
+fn render_rgb_with_payload(
+    linear_buffer: &LinearImage,
+    payload: &AdjustmentsPayload,
+    mask_runtimes: &[MaskRuntime],
+    histograms: Option<&mut Histograms>,
+    window: Option<[u32; 4]>,
 ) -> Result<Vec<u8>> {
-    let adjustment_values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    let mut adjustment_values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    let calibration = Calibration::take(&mut adjustment_values);
     let use_basic_tone_mapper = matches!(adjustment_values.tone_mapper, ToneMapper::Basic);
     let global_curves = CurvesRuntime::from_payload(&payload.curves);
     let curves_are_active = !global_curves.is_default() || mask_runtimes.iter().any(|m| m.curves_are_active);
@@ -2839,9 +3161,11 @@ fn render_linear_with_payload(
     let linear = linear_buffer.as_raw();
     debug_assert_eq!(linear.len(), (width as usize) * (height as usize) * 3);
 
+    let analysis = Analysis::new(&payload.preview.analysis);
     let need_sharpness =
         adjustment_values.sharpness.abs() > 0.00001 ||
-            mask_runtimes.iter().any(|m| m.adjustments.sharpness.abs() > 0.00001);
+            mask_runtimes.iter().any(|m| m.adjustments.sharpness.abs() > 0.00001) ||
+            analysis.as_ref().is_some_and(Analysis::needs_detail);
     let need_clarity =
         adjustment_values.clarity.abs() > 0.00001 ||
             adjustment_values.centre.abs() > 0.00001 ||
@@ -2867,6 +3191,8 @@ fn render_linear_with_payload(
         .context("RGB buffer size overflow")?;
     let mut rgb = try_alloc_vec(len, 0u8)?;
 
+    let hdr_histograms = histograms.as_deref();
+
     // --- PARALLEL PROCESSING START ---
     // Use Rayon to split the image into chunks and process on all cores
     rgb.par_chunks_exact_mut(3)
@@ -2875,6 +3201,9 @@ fn render_linear_with_payload(
             // Re-calculate x, y from linear index
             let x = (idx as u32) % width;
             let y = (idx as u32) / width;
+            if outside_window(window, x, y) {
+                return;
+            }
             let base = idx * 3;
 
             // 1. Fetch Linear Pixel
@@ -2885,7 +3214,7 @@ fn render_linear_with_payload(
             };
 
             // 2. Default Processing
-            colors = apply_default_raw_processing(colors, use_basic_tone_mapper);
+            colors = apply_default_raw_processing(calibration.apply(colors), use_basic_tone_mapper);
 
             // 3. Detail & Local Contrast
             let centre_mask = if need_centre_mask { compute_centre_mask(x, y, width, height) } else { 0.0 };
@@ -2917,6 +3246,10 @@ fn render_linear_with_payload(
                 }
             }
 
+            if let Some(histograms) = hdr_histograms {
+                histograms.add_hdr(composite);
+            }
+
             // 6. Tone Mapping
             composite = tone_map(composite, adjustment_values.tone_mapper);
 
@@ -2990,21 +3323,28 @@ fn render_linear_with_payload(
         });
     // --- PARALLEL PROCESSING END ---
 
-    let mut encoded = Vec::new();
-    let quality = if fast_demosaic { 88 } else { 96 };
-    let mut encoder = JpegEncoder::new_with_quality(&mut encoded, quality);
-    encoder.encode(&rgb, width, height, ExtendedColorType::Rgb8)?;
-    Ok(encoded)
+    if let Some(histograms) = histograms {
+        histograms.set_output(&rgb, width);
+    }
+
+    if let Some(analysis) = &analysis {
+        analysis.overlay(&mut rgb, width, (0, 0), |x, y| source_detail(linear, width, detail_blurs.as_ref()?, x, y));
+    }
+
+    Ok(rgb)
 }
 
-fn render_linear_roi_with_payload(
+// Interleaved 8-bit sRGB render of `roi`; `window` is in full-frame coordinates.
+fn render_rgb_roi_with_payload(
     linear_buffer: &LinearImage,
     payload: &AdjustmentsPayload,
     mask_runtimes: &[MaskRuntime],
-    fast_demosaic: bool,
     roi: &CropPayload,
+    histograms: Option<&mut Histograms>,
+    window: Option<[u32; 4]>,
 ) -> Result<Vec<u8>> {
-    let adjustment_values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    let mut adjustment_values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    let calibration = Calibration::take(&mut adjustment_values);
     let use_basic_tone_mapper = matches!(adjustment_values.tone_mapper, ToneMapper::Basic);
     let global_curves = CurvesRuntime::from_payload(&payload.curves);
     let curves_are_active = !global_curves.is_default() || mask_runtimes.iter().any(|m| m.curves_are_active);
@@ -3023,9 +3363,11 @@ fn render_linear_roi_with_payload(
     let linear = linear_buffer.as_raw();
     debug_assert_eq!(linear.len(), (width as usize) * (height as usize) * 3);
 
+    let analysis = Analysis::new(&payload.preview.analysis);
     let need_sharpness =
         adjustment_values.sharpness.abs() > 0.00001 ||
-            mask_runtimes.iter().any(|m| m.adjustments.sharpness.abs() > 0.00001);
+            mask_runtimes.iter().any(|m| m.adjustments.sharpness.abs() > 0.00001) ||
+            analysis.as_ref().is_some_and(Analysis::needs_detail);
     let need_clarity =
         adjustment_values.clarity.abs() > 0.00001 ||
             adjustment_values.centre.abs() > 0.00001 ||
@@ -3055,6 +3397,9 @@ fn render_linear_roi_with_payload(
         let full_y = roi_y + y;
         for x in 0..roi_w {
             let full_x = roi_x + x;
+            if outside_window(window, full_x, full_y) {
+                continue;
+            }
             let idx_full = ((full_y * width + full_x) as usize).min((width as usize * height as usize).saturating_sub(1));
             let base = idx_full * 3;
 
@@ -3067,7 +3412,7 @@ fn render_linear_roi_with_payload(
                 };
 
             // Apply default RAW processing (brightness + contrast boost for Basic tone mapper)
-            colors = apply_default_raw_processing(colors, use_basic_tone_mapper);
+            colors = apply_default_raw_processing(calibration.apply(colors), use_basic_tone_mapper);
 
             let centre_mask =
                 if need_centre_mask { compute_centre_mask(full_x, full_y, width, height) } else { 0.0 };
@@ -3100,6 +3445,10 @@ fn render_linear_roi_with_payload(
                 ];
             }
 
+            if let Some(histograms) = histograms.as_deref() {
+                histograms.add_hdr(composite);
+            }
+
             composite = tone_map(composite, adjustment_values.tone_mapper);
 
             let mut srgb = [
@@ -3180,11 +3529,15 @@ fn render_linear_roi_with_payload(
         }
     }
 
-    let mut encoded = Vec::new();
-    let quality = if fast_demosaic { 88 } else { 96 };
-    let mut encoder = JpegEncoder::new_with_quality(&mut encoded, quality);
-    encoder.encode(&rgb, roi_w, roi_h, ExtendedColorType::Rgb8)?;
-    Ok(encoded)
+    if let Some(histograms) = histograms {
+        histograms.set_output(&rgb, roi_w);
+    }
+
+    if let Some(analysis) = &analysis {
+        analysis.overlay(&mut rgb, roi_w, (roi_x, roi_y), |x, y| source_detail(linear, width, detail_blurs.as_ref()?, x, y));
+    }
+
+    Ok(rgb)
 }
 
 fn render_linear_with_payload_tiled(
@@ -3194,7 +3547,8 @@ fn render_linear_with_payload_tiled(
     fast_demosaic: bool,
     tile_size: u32,
 ) -> Result<Vec<u8>> {
-    let adjustment_values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    let mut adjustment_values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    let calibration = Calibration::take(&mut adjustment_values);
     let use_basic_tone_mapper = matches!(adjustment_values.tone_mapper, ToneMapper::Basic);
     let global_curves = CurvesRuntime::from_payload(&payload.curves);
     let curves_are_active = !global_curves.is_default() || mask_defs.iter().any(|m| m.curves_are_active);
@@ -3278,7 +3632,7 @@ fn render_linear_with_payload_tiled(
                             [linear[base], linear[base + 1], linear[base + 2]]
                         };
 
-                    colors = apply_default_raw_processing(colors, use_basic_tone_mapper);
+                    colors = apply_default_raw_processing(calibration.apply(colors), use_basic_tone_mapper);
 
                     let centre_mask =
                         if need_centre_mask { compute_centre_mask(full_x, full_y, width, height) } else { 0.0 };
@@ -3414,22 +3768,73 @@ fn render_raw(
     let width = linear_buffer.width();
     let height = linear_buffer.height();
     let mask_runtimes = parse_masks(payload.masks.clone(), width, height);
-    render_linear_with_payload(&linear_buffer, &payload, &mask_runtimes, fast_demosaic)
+    let encoded = render_linear_with_payload(&linear_buffer, &payload, &mask_runtimes, fast_demosaic, None)?;
+    // Full-resolution renders are saved as files; previews don't need the metadata.
+    if max_width.is_none() && max_height.is_none() {
+        let metadata = ExportMetadata::from_raw(raw_bytes).unwrap_or_default();
+        return metadata
+            .for_output(width, height, OutputColorSpace::Srgb, &payload.export.metadata)
+            .embed_in_jpeg(encoded);
+    }
+    Ok(encoded)
+}
+
+// Small display-encoded rendering of the photo, centre-cropped to fill a `canvas_w` x `canvas_h`
+// canvas, for the blurred canvas background. Only global adjustments; the blur hides the rest.
+fn render_canvas_backdrop(
+    source: &CompactImage,
+    transform: &TransformState,
+    layers: &SourceLayers,
+    payload: &AdjustmentsPayload,
+    canvas_w: u32,
+    canvas_h: u32,
+) -> (u32, u32, Vec<f32>) {
+    const BACKDROP_SIZE: f32 = 96.0;
+    let scale = BACKDROP_SIZE / canvas_w.max(canvas_h).max(1) as f32;
+    let w = (canvas_w as f32 * scale).round().max(1.0) as u32;
+    let h = (canvas_h as f32 * scale).round().max(1.0) as u32;
+    let small = transform.clone().with_output_size(w, h, true);
+
+    let mut adjustment_values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    let calibration = Calibration::take(&mut adjustment_values);
+    let use_basic_tone_mapper = matches!(adjustment_values.tone_mapper, ToneMapper::Basic);
+    let curves = CurvesRuntime::from_payload(&payload.curves);
+    let linear = extract_tile_f32(source, &small, layers, 0, 0, w, h);
+    let rgb = linear
+        .chunks_exact(3)
+        .flat_map(|p| {
+            let colors = apply_default_raw_processing(calibration.apply([p[0], p[1], p[2]]), use_basic_tone_mapper);
+            let colors = tone_map(apply_color_adjustments(colors, &adjustment_values, 0.0), adjustment_values.tone_mapper);
+            let srgb = curves.apply_all(colors.map(linear_to_srgb));
+            srgb.map(|v| v.clamp(0.0, 1.0))
+        })
+        .collect();
+    (w, h, rgb)
 }
 
-// Compact u16 tiled renderer with virtual transformations (no physical rotation)
-fn render_compact_tiled(
+// Compact u16 tiled renderer with virtual transformations (no physical rotation).
+// Rows of tiles are streamed into the encoder selected by `payload.export`, which writes to `out`.
+fn render_compact_tiled<W: Write + Seek + 'static>(
     source: &CompactImage,
     transform: &TransformState,
     payload: &AdjustmentsPayload,
     mask_defs: &[MaskRuntimeDef],
-    fast_demosaic: bool,
-    tile_size: u32,
-) -> Result<Vec<u8>> {
-    let adjustment_values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    low_ram_mode: bool,
+    metadata: Option<&ExportMetadata>,
+    out: W,
+) -> Result<W> {
+    let fast_demosaic = low_ram_mode;
+    let tile_size = if low_ram_mode { 128 } else { 256 };
+    let mut adjustment_values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    let calibration = Calibration::take(&mut adjustment_values);
     let use_basic_tone_mapper = matches!(adjustment_values.tone_mapper, ToneMapper::Basic);
     let global_curves = CurvesRuntime::from_payload(&payload.curves);
     let curves_are_active = !global_curves.is_default() || mask_defs.iter().any(|m| m.curves_are_active);
+    let output_transform = OutputTransform::new(payload.export.color_space);
+    // Wide output spaces keep colours outside sRGB until the output transform clips them.
+    let wide_gamut = output_transform.wide();
+    let adjust = if wide_gamut { apply_color_adjustments_wide } else { apply_color_adjustments };
+    let tone = if wide_gamut { tone_map_wide } else { tone_map };
 
     // Use OUTPUT dimensions (post-rotation/crop)
     let width = transform.output_w;
@@ -3467,14 +3872,63 @@ fn render_compact_tiled(
     // Add safety margin - 50px is typically safe for all RapidRAW-style effects
     let padding = if max_radius > 0 { max_radius + 10 } else { 0 };
 
-    // Prepare Output Buffer (RGB u8)
-    let len = (width as usize)
-        .checked_mul(height as usize)
-        .and_then(|v| v.checked_mul(3))
-        .context("RGB buffer size overflow")?;
-    let mut rgb = try_alloc_vec(len, 0u8)?;
+    let layers = SourceLayers::new(source, transform, payload);
+    let eyes = Eyes::new(&payload.eyes, width, height, |[x, y, w, h]| extract_tile_f32(source, transform, &layers, x, y, w, h));
+
+    let canvas = match payload.export.canvas.as_ref() {
+        Some(canvas) => {
+            let backdrop = (canvas.background == CanvasBackground::Blur)
+                .then(|| {
+                    let lines = canvas::caption_lines(canvas.caption.as_ref(), metadata).len();
+                    let layout = canvas::layout(canvas, width, height, lines);
+                    render_canvas_backdrop(source, transform, &layers, payload, layout.width, layout.height)
+                });
+            Some(Canvas::new(canvas, width, height, metadata, backdrop)?)
+        }
+        None => None,
+    };
+    // The encoder and gain map see the whole canvas; everything else works on the photo.
+    let (out_w, out_h, photo_x, photo_y) = match canvas.as_ref().map(Canvas::layout) {
+        Some(layout) => (layout.width, layout.height, layout.photo_x, layout.photo_y),
+        None => (width, height, 0, 0),
+    };
+
+    let encoder = ExportEncoder::new(&payload.export, out_w, out_h, fast_demosaic, metadata, out)?;
+    let gain_map = if payload.export.format == ExportFormat::UltraHdr {
+        Some(GainMapBuilder::new(out_w, out_h)?)
+    } else {
+        None
+    };
+    let want_hdr = gain_map.is_some();
+    let watermark = match payload.export.watermark.as_ref() {
+        Some(watermark) => Watermark::new(watermark, width, height)?,
+        None => None,
+    };
+    let mut sink = PhotoSink {
+        encoder,
+        canvas,
+        gain_map,
+        watermark,
+        output_transform: &output_transform,
+        width,
+        out_w,
+        photo_x,
+        photo_y,
+        hdr_ratios: Vec::new(),
+    };
+    // Runs on the finished display rows, so it sees the final output size.
+    let mut sharpener = OutputSharpener::new(payload.export.sharpening, width);
 
     let tile = tile_size.max(64).min(width.max(height));
+
+    // One row of tiles is rendered into this band and handed to the encoder before moving on
+    let band_len = (width as usize)
+        .checked_mul(tile as usize)
+        .and_then(|v| v.checked_mul(3))
+        .context("RGB buffer size overflow")?;
+    let mut band = try_alloc_vec(band_len, 0f32)?;
+    // HDR/SDR luminance ratio per band pixel, for the gain map.
+    let mut ratio_band = if want_hdr { try_alloc_vec(band_len / 3, 0f32)? } else { Vec::new() };
     let mut tile_y = 0;
     
     while tile_y < height {
@@ -3504,7 +3958,10 @@ fn render_compact_tiled(
             };
             
             // Extract PADDED f32 tile for detail calculations
-            let padded_linear_tile = extract_tile_f32(source, transform, fetch_x, fetch_y, fetch_w, fetch_h);
+            let mut padded_linear_tile = extract_tile_f32(source, transform, &layers, fetch_x, fetch_y, fetch_w, fetch_h);
+            if let Some(eyes) = eyes.as_ref() {
+                eyes.apply(&mut padded_linear_tile, [fetch_x, fetch_y, fetch_w, fetch_h]);
+            }
             
             // Build detail blurs on the PADDED tile
             // The blur edges will be messy, but clean in the center where our actual tile is
@@ -3547,7 +4004,7 @@ fn render_compact_tiled(
                         [0.0, 0.0, 0.0]
                     };
 
-                    colors = apply_default_raw_processing(colors, use_basic_tone_mapper);
+                    colors = apply_default_raw_processing(calibration.apply(colors), use_basic_tone_mapper);
 
                     let centre_mask =
                         if need_centre_mask { compute_centre_mask(full_x, full_y, width, height) } else { 0.0 };
@@ -3558,7 +4015,7 @@ fn render_compact_tiled(
                         colors = apply_local_contrast_stack(colors, local_x, local_y, centre_mask, &adjustment_values, blurs);
                     }
 
-                    let mut composite = apply_color_adjustments(colors, &adjustment_values, centre_mask);
+                    let mut composite = adjust(colors, &adjustment_values, centre_mask);
                     
                     for mask in &mask_runtimes {
                         let mut selection = mask_selection_at(mask, full_x, full_y);
@@ -3574,7 +4031,7 @@ fn render_compact_tiled(
                         if let Some(blurs) = detail_blurs.as_ref() {
                             mask_base = apply_local_contrast_stack(mask_base, local_x, local_y, centre_mask, &mask.adjustments, blurs);
                         }
-                        let mask_adjusted = apply_color_adjustments(mask_base, &mask.adjustments, centre_mask);
+                        let mask_adjusted = adjust(mask_base, &mask.adjustments, centre_mask);
                         composite = [
                             composite[0] + (mask_adjusted[0] - composite[0]) * influence,
                             composite[1] + (mask_adjusted[1] - composite[1]) * influence,
@@ -3582,16 +4039,18 @@ fn render_compact_tiled(
                         ];
                     }
 
-                    composite = tone_map(composite, adjustment_values.tone_mapper);
+                    // Luminance the tone mapper compresses away; it becomes the gain map.
+                    let hdr_luma = want_hdr.then(|| get_luma(composite));
+                    composite = tone(composite, adjustment_values.tone_mapper);
+                    let hdr_ratio = hdr_luma.map(|luma| luma / get_luma(composite).max(1.0e-6));
 
-                    let mut srgb = [
-                        linear_to_srgb(composite[0]),
-                        linear_to_srgb(composite[1]),
-                        linear_to_srgb(composite[2]),
-                    ];
+                    let mut srgb = output_transform.encode(composite);
 
                     if curves_are_active {
-                        srgb = global_curves.apply_all(srgb);
+                        // Curves only cover display values; what a wide-gamut colour has beyond
+                        // them is carried over.
+                        let excess = srgb.map(|v| v - v.clamp(0.0, 1.0));
+                        srgb = global_curves.apply_all(srgb.map(|v| v.clamp(0.0, 1.0)));
 
                         for mask in &mask_runtimes {
                             if !mask.curves_are_active {
@@ -3614,6 +4073,7 @@ fn render_compact_tiled(
                                 srgb[2] + (mask_curved[2] - srgb[2]) * influence,
                             ];
                         }
+                        srgb = [srgb[0] + excess[0], srgb[1] + excess[1], srgb[2] + excess[2]];
                     }
 
                     if adjustment_values.vignette_amount.abs() > 0.00001 {
@@ -3648,39 +4108,122 @@ fn render_compact_tiled(
                                 srgb[2] + (1.0 - srgb[2]) * t,
                             ];
                         }
-                        srgb = [
-                            srgb[0].clamp(0.0, 1.0),
-                            srgb[1].clamp(0.0, 1.0),
-                            srgb[2].clamp(0.0, 1.0),
-                        ];
+                        srgb = srgb.map(|v| v.min(1.0));
                     }
 
-                    let out_base = ((full_y * width + full_x) as usize) * 3;
-                    rgb[out_base] = clamp_to_u8(srgb[0] * 255.0);
-                    rgb[out_base + 1] = clamp_to_u8(srgb[1] * 255.0);
-                    rgb[out_base + 2] = clamp_to_u8(srgb[2] * 255.0);
+                    let out_pixel = (y * width + full_x) as usize;
+                    if let Some(ratio) = hdr_ratio {
+                        ratio_band[out_pixel] = ratio;
+                    }
+                    band[out_pixel * 3..out_pixel * 3 + 3].copy_from_slice(&srgb);
                 }
             }
 
             tile_x += tile;
         }
+
+        let band_pixels = (tile_h * width) as usize;
+        if want_hdr {
+            sink.hdr_ratios.extend_from_slice(&ratio_band[..band_pixels]);
+        }
+        match sharpener.as_mut() {
+            Some(sharpener) => {
+                let (rows, start_y) = sharpener.push(&band[..band_pixels * 3]);
+                sink.write(rows, start_y)?;
+            }
+            None => sink.write(band[..band_pixels * 3].to_vec(), tile_y)?,
+        }
         tile_y += tile;
     }
+    if let Some(sharpener) = sharpener.as_mut() {
+        let (rows, start_y) = sharpener.finish();
+        sink.write(rows, start_y)?;
+    }
 
-    let mut encoded = Vec::new();
-    let quality = if fast_demosaic { 88 } else { 96 };
-    let mut encoder = JpegEncoder::new_with_quality(&mut encoded, quality);
-    encoder.encode(&rgb, width, height, ExtendedColorType::Rgb8)?;
-    Ok(encoded)
+    let description = metadata.map(|m| {
+        m.for_output(out_w, out_h, payload.export.color_space, &payload.export.metadata)
+            .xmp_description()
+    });
+    sink.finish(description)
 }
 
-// Helper to handle the specific export logic with u16 compact storage
-fn render_export_from_session(
-    handle: jlong,
-    adjustments_json: Option<&str>,
-    max_dimension: u32,
-    low_ram_mode: bool,
-) -> Result<Vec<u8>> {
+// Takes finished display rows of the photo, already sharpened, through the watermark, the gain
+// map and the output transform to the encoder, surrounded by the canvas when there is one.
+struct PhotoSink<'a, W: Write + Seek + 'static> {
+    encoder: ExportEncoder<W>,
+    canvas: Option<Canvas>,
+    gain_map: Option<GainMapBuilder>,
+    watermark: Option<Watermark>,
+    output_transform: &'a OutputTransform,
+    width: u32,
+    // Encoded width and the photo's position in it.
+    out_w: u32,
+    photo_x: u32,
+    photo_y: u32,
+    // HDR/SDR luminance ratios of the rows not written yet, oldest first.
+    hdr_ratios: Vec<f32>,
+}
+
+impl<W: Write + Seek + 'static> PhotoSink<'_, W> {
+    fn write(&mut self, mut rows: Vec<f32>, start_y: u32) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let width = self.width as usize;
+        let ratios: Vec<f32> = match self.gain_map {
+            Some(_) => self.hdr_ratios.drain(..rows.len() / 3).collect(),
+            None => Vec::new(),
+        };
+        for (i, pixel) in rows.chunks_exact_mut(3).enumerate() {
+            let x = (i % width) as u32;
+            let y = start_y + (i / width) as u32;
+            let mut srgb = [pixel[0], pixel[1], pixel[2]];
+            let mut coverage = 0.0;
+            if let Some(watermark) = self.watermark.as_ref() {
+                (srgb, coverage) = watermark.apply(x, y, srgb);
+            }
+            if let Some(gain_map) = self.gain_map.as_mut() {
+                // Keep the watermark at SDR brightness in the HDR rendition.
+                let ratio = 1.0 + (ratios[i] - 1.0) * (1.0 - coverage);
+                gain_map.add(self.photo_x + x, self.photo_y + y, srgb, ratio);
+            }
+            pixel.copy_from_slice(&self.output_transform.apply(srgb));
+        }
+        let (rows, start_y) = match self.canvas.as_mut() {
+            Some(canvas) => canvas.push(&rows, start_y, self.output_transform),
+            None => (rows, start_y),
+        };
+        self.write_encoded(&rows, start_y)
+    }
+
+    fn write_encoded(&mut self, rows: &[f32], start_y: u32) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.encoder.write_rows(rows, start_y, (rows.len() / (self.out_w as usize * 3)) as u32)
+    }
+
+    // `description` goes into the Ultra HDR primary's XMP.
+    fn finish(mut self, description: Option<String>) -> Result<W> {
+        if let Some(mut canvas) = self.canvas.take() {
+            let (rows, start_y) = canvas.finish(self.output_transform);
+            self.write_encoded(&rows, start_y)?;
+        }
+        match self.gain_map {
+            Some(gain_map) => self.encoder.finish_with(|primary| gain_map.finish(primary, description)),
+            None => self.encoder.finish(),
+        }
+    }
+}
+
+// Helper to handle the specific export logic with u16 compact storage
+fn render_export_from_session<W: Write + Seek + 'static>(
+    handle: jlong,
+    adjustments_json: Option<&str>,
+    max_dimension: u32,
+    low_ram_mode: bool,
+    out: W,
+) -> Result<W> {
     let session = get_session(handle).context("Invalid session handle")?;
     
     // 1. Get raw bytes and clear session cache to free RAM
@@ -3705,32 +4248,68 @@ fn render_export_from_session(
 
     // 2. Decode directly to u16 compact format (no rotation, returns orientation)
     let fast_demosaic = low_ram_mode;
-    let (req_w, req_h) = if max_dimension > 0 { 
-        (Some(max_dimension), Some(max_dimension)) 
-    } else { 
-        (None, None) 
+    // Missing or unreadable metadata shouldn't fail the export; injected fields still apply.
+    let metadata = ExportMetadata::from_raw(&raw_bytes).unwrap_or_default();
+    let mut output_size = None;
+    let target_size = |src_w: u32, src_h: u32, orientation: Orientation| {
+        if payload.export.size.mode == ResizeMode::None {
+            // Legacy sizing: fit the uncropped source into a max_dimension box.
+            if max_dimension > 0 && (src_w > max_dimension || src_h > max_dimension) {
+                let scale = (max_dimension as f32 / src_w as f32).min(max_dimension as f32 / src_h as f32);
+                return ((src_w as f32 * scale).max(1.0) as u32, (src_h as f32 * scale).max(1.0) as u32);
+            }
+            return (src_w, src_h);
+        }
+        // Sizes refer to the cropped, rotated result; only ever resample the source down and
+        // leave enlargement to the output interpolation.
+        let natural = TransformState::new(src_w, src_h, &payload, orientation);
+        let size = match payload.export.canvas.as_ref() {
+            // The requested size is the whole canvas and the photo is scaled to match, never
+            // cropped. Pixel borders don't scale, so only percent borders land exactly on it.
+            Some(canvas) => {
+                let lines = canvas::caption_lines(canvas.caption.as_ref(), Some(&metadata)).len();
+                let layout = canvas::layout(canvas, natural.output_w, natural.output_h, lines);
+                let scale = sizing::resolve(&payload.export.size, layout.width, layout.height).scale;
+                sizing::OutputSize {
+                    scale,
+                    width: (natural.output_w as f32 * scale).round().max(1.0) as u32,
+                    height: (natural.output_h as f32 * scale).round().max(1.0) as u32,
+                    fill: false,
+                }
+            }
+            None => sizing::resolve(&payload.export.size, natural.output_w, natural.output_h),
+        };
+        let scale = size.scale.min(1.0);
+        output_size = Some(size);
+        (
+            (src_w as f32 * scale).round().max(1.0) as u32,
+            (src_h as f32 * scale).round().max(1.0) as u32,
+        )
     };
-    
-    let (compact_source, base_orientation) = decode_raw_to_compact(&raw_bytes, fast_demosaic, req_w, req_h)?;
+
+    let (compact_source, base_orientation) =
+        decode_raw_to_compact(&raw_bytes, fast_demosaic, target_size, payload.export.resample_filter)?;
 
     // 3. Setup Virtual Transform with base orientation (handles rotation virtually)
-    let transform = TransformState::new(
+    let mut transform = TransformState::new(
         compact_source.width(), 
         compact_source.height(), 
         &payload,
         base_orientation
     );
+    if let Some(size) = output_size {
+        transform = transform.with_output_size(size.width, size.height, size.fill);
+    }
 
     // 4. Render Tiled using Virtual Coordinates
-    let tile_size = if low_ram_mode { 128 } else { 256 };
-    
     render_compact_tiled(
         &compact_source, 
         &transform, 
         &payload, 
         &mask_defs, 
-        fast_demosaic, 
-        tile_size
+        low_ram_mode,
+        Some(&metadata),
+        out,
     )
 }
 
@@ -3749,8 +4328,9 @@ pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecode
         let max_dim = if max_dimension < 0 { 0 } else { max_dimension as u32 };
         let is_low_ram = low_ram_mode != 0;
 
-        match render_export_from_session(handle, adjustments.as_deref(), max_dim, is_low_ram) {
-            Ok(payload) => make_byte_array(&env, &payload),
+        let out = Cursor::new(Vec::new());
+        match render_export_from_session(handle, adjustments.as_deref(), max_dim, is_low_ram, out) {
+            Ok(out) => make_byte_array(&env, out.get_ref()),
             Err(err) => {
                 error!("Failed to export session: {}", err);
                 if err.to_string().contains("Out of memory") {
@@ -3777,6 +4357,68 @@ pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecode
     }
 }
 
+// Writes the export straight to `fd`, which must be an empty, seekable file opened for writing.
+// The descriptor is duplicated, so the caller keeps ownership of it.
+fn export_session_to_fd(
+    handle: jlong,
+    adjustments_json: Option<&str>,
+    max_dimension: u32,
+    low_ram_mode: bool,
+    fd: i32,
+) -> Result<()> {
+    if fd < 0 {
+        return Err(anyhow::anyhow!("Invalid file descriptor"));
+    }
+    let file = File::from(unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?);
+    let out = render_export_from_session(handle, adjustments_json, max_dimension, low_ram_mode, BufWriter::new(file))?;
+    out.into_inner().map_err(|e| e.into_error())?;
+    Ok(())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecoder_exportFromSessionToFd(
+    mut env: JNIEnv,
+    _: JClass,
+    handle: jlong,
+    adjustments_json: JString,
+    max_dimension: jint,
+    low_ram_mode: jboolean,
+    fd: jint,
+) -> jboolean {
+    ensure_logger();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let adjustments = read_adjustments_json(&mut env, adjustments_json);
+        let max_dim = if max_dimension < 0 { 0 } else { max_dimension as u32 };
+        let is_low_ram = low_ram_mode != 0;
+
+        match export_session_to_fd(handle, adjustments.as_deref(), max_dim, is_low_ram, fd) {
+            Ok(()) => JNI_TRUE,
+            Err(err) => {
+                error!("Failed to export session: {}", err);
+                if err.to_string().contains("Out of memory") {
+                    let _ = env.throw_new(
+                        "java/lang/OutOfMemoryError",
+                        "Native heap limit exceeded during export",
+                    );
+                }
+                JNI_FALSE
+            }
+        }
+    }));
+
+    match result {
+        Ok(written) => written,
+        Err(_) => {
+            error!("Native panic in exportFromSessionToFd");
+            let _ = env.throw_new(
+                "java/lang/OutOfMemoryError",
+                "Native heap limit exceeded during export",
+            );
+            JNI_FALSE
+        }
+    }
+}
+
 static LOGGER_INIT: Once = Once::new();
 
 fn ensure_logger() {
@@ -3872,6 +4514,9 @@ struct Session {
     masks_low: Option<MasksCache>,
     masks_preview: Option<MasksCache>,
     masks_zoom: Option<MasksCache>,
+
+    // Comparison baseline JSON and its parsed payload.
+    compare_baseline: Option<(Option<Value>, AdjustmentsPayload)>,
 }
 
 impl Session {
@@ -3888,6 +4533,7 @@ impl Session {
             masks_low: None,
             masks_preview: None,
             masks_zoom: None,
+            compare_baseline: None,
         }
     }
 
@@ -3908,6 +4554,14 @@ impl Session {
         Ok(shared)
     }
 
+    // The parsed comparison baseline; parsed again only when its JSON changes.
+    fn compare_baseline(&mut self, value: Option<&Value>) -> &AdjustmentsPayload {
+        if !matches!(&self.compare_baseline, Some((cached, _)) if cached.as_ref() == value) {
+            self.compare_baseline = None;
+        }
+        &self.compare_baseline.get_or_insert_with(|| (value.cloned(), compare::parse_baseline(value))).1
+    }
+
     fn linear_for(&mut self, kind: PreviewKind) -> Result<Arc<LinearImage>> {
         if let PreviewKind::Zoom = kind {
             let (max_w, max_h) = kind.max_dims();
@@ -4000,6 +4654,27 @@ fn make_byte_array(env: &JNIEnv, bytes: &[u8]) -> jbyteArray {
     }
 }
 
+// A `NativeRender` holding the image bytes and, if any, the histogram JSON.
+fn make_render_result(env: &mut JNIEnv, image: &[u8], histogram: Option<&str>) -> jobject {
+    let result = (|| -> jni::errors::Result<jobject> {
+        let image = env.byte_array_from_slice(image)?;
+        let histogram = match histogram {
+            Some(histogram) => JObject::from(env.new_string(histogram)?),
+            None => JObject::null(),
+        };
+        let render = env.new_object(
+            "com/dueckis/kawaiiraweditor/data/native/NativeRender",
+            "([BLjava/lang/String;)V",
+            &[JValue::Object(&image), JValue::Object(&histogram)],
+        )?;
+        Ok(render.into_raw())
+    })();
+    result.unwrap_or_else(|err| {
+        error!("Failed to build render result: {}", err);
+        ptr::null_mut()
+    })
+}
+
 fn read_adjustments_json(env: &mut JNIEnv, adjustments: JString) -> Option<String> {
     match env.get_string(&adjustments) {
         Ok(js) => {
@@ -4076,15 +4751,272 @@ pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecode
     }
 }
 
-fn render_from_session(
-    handle: jlong,
-    adjustments_json: Option<&str>,
-    kind: PreviewKind,
-) -> Result<Vec<u8>> {
+// `width` x `height` is the frame crops are expressed in, after orientation steps. A non-positive
+// `aspect_ratio` keeps the frame's aspect.
+#[no_mangle]
+pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecoder_computeAutoCrop(
+    mut env: JNIEnv,
+    _: JClass,
+    adjustments_json: JString,
+    width: jint,
+    height: jint,
+    aspect_ratio: jfloat,
+) -> jstring {
+    ensure_logger();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let adjustments = read_adjustments_json(&mut env, adjustments_json);
+        let payload = parse_adjustments_payload(adjustments.as_deref());
+        let aspect = (aspect_ratio > 0.0).then_some(aspect_ratio);
+        let crop = autocrop::max_inscribed_crop(
+            width.max(0) as u32,
+            height.max(0) as u32,
+            payload.rotation,
+            &payload.perspective,
+            aspect,
+        );
+        let Some(crop) = crop else {
+            error!("Failed to compute auto crop for {}x{}", width, height);
+            return ptr::null_mut();
+        };
+        let result = json!({
+            "x": crop.x,
+            "y": crop.y,
+            "width": crop.width,
+            "height": crop.height,
+        });
+        match env.new_string(result.to_string()) {
+            Ok(s) => s.into_raw(),
+            Err(_) => ptr::null_mut(),
+        }
+    }));
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            error!("Native panic in computeAutoCrop");
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecoder_bakeCubeLut(
+    mut env: JNIEnv,
+    _: JClass,
+    adjustments_json: JString,
+    size: jint,
+) -> jstring {
+    ensure_logger();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let adjustments = read_adjustments_json(&mut env, adjustments_json);
+        let payload = parse_adjustments_payload(adjustments.as_deref());
+        let size = if size <= 0 { lut::LUT_SIZE_SMALL } else { size as u32 };
+        let baked = match lut::bake_cube_lut(&payload, size) {
+            Ok(baked) => baked,
+            Err(err) => {
+                error!("Failed to bake LUT: {}", err);
+                return ptr::null_mut();
+            }
+        };
+        let result = json!({
+            "size": baked.size,
+            "cube": baked.cube,
+            "excludedStages": baked.excluded_stages,
+        });
+        match env.new_string(result.to_string()) {
+            Ok(s) => s.into_raw(),
+            Err(_) => ptr::null_mut(),
+        }
+    }));
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            error!("Native panic in bakeCubeLut");
+            ptr::null_mut()
+        }
+    }
+}
+
+// Suggested rotation and keystone values for the session's preview, as JSON.
+fn analyze_upright_from_session(handle: jlong, adjustments_json: Option<&str>) -> Result<String> {
     let session = get_session(handle).context("Invalid session handle")?;
-    let mut session = session.lock().map_err(|_| anyhow::anyhow!("Session lock poisoned"))?;
+    let linear = {
+        let mut session = session.lock().map_err(|_| anyhow::anyhow!("Session lock poisoned"))?;
+        session.linear_for(PreviewKind::Preview)?
+    };
 
-    let payload = parse_adjustments_payload(adjustments_json);
+    // Analyse in the frame perspective and rotation apply to: oriented and flipped only.
+    let mut payload = parse_adjustments_payload(adjustments_json);
+    payload.rotation = 0.0;
+    payload.crop = None;
+    payload.perspective = PerspectivePayload::default();
+    let oriented = apply_transformations((*linear).clone(), &payload);
+    let analysis = upright::analyze(&oriented);
+
+    let mode = |s: &upright::UprightSuggestion| {
+        json!({
+            "rotation": s.rotation,
+            "perspective": {
+                "vertical": s.perspective.vertical,
+                "horizontal": s.perspective.horizontal,
+                "aspect": s.perspective.aspect,
+                "scale": s.perspective.scale,
+            },
+            "lineCount": s.lines,
+        })
+    };
+    Ok(json!({
+        "level": mode(&analysis.level),
+        "vertical": mode(&analysis.vertical),
+        "full": mode(&analysis.full),
+        "lineCount": analysis.detected,
+    })
+    .to_string())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecoder_analyzeUprightFromSession(
+    mut env: JNIEnv,
+    _: JClass,
+    handle: jlong,
+    adjustments_json: JString,
+) -> jstring {
+    ensure_logger();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let adjustments = read_adjustments_json(&mut env, adjustments_json);
+        let result = match analyze_upright_from_session(handle, adjustments.as_deref()) {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Failed to analyze upright: {}", err);
+                return ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(_) => ptr::null_mut(),
+        }
+    }));
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            error!("Native panic in analyzeUprightFromSession");
+            ptr::null_mut()
+        }
+    }
+}
+
+// Camera orientation without decoding the image.
+fn raw_orientation(raw_bytes: &[u8]) -> Orientation {
+    let source = RawSource::new_from_slice(raw_bytes);
+    rawler::get_decoder(&source)
+        .ok()
+        .and_then(|decoder| decoder.raw_metadata(&source, &RawDecodeParams::default()).ok())
+        .and_then(|metadata| metadata.exif.orientation)
+        .map(Orientation::from_u16)
+        .unwrap_or(Orientation::Normal)
+}
+
+// Dust spots for each session, as heal spots in that session's retouch frame. With several
+// sessions only spots that recur at the same sensor position in at least two frames are kept.
+fn detect_dust_from_sessions(handles: &[jlong]) -> Result<String> {
+    let mut frames = Vec::with_capacity(handles.len());
+    for &handle in handles {
+        let session = get_session(handle).context("Invalid session handle")?;
+        let (linear, orientation) = {
+            let mut session = session.lock().map_err(|_| anyhow::anyhow!("Session lock poisoned"))?;
+            // Dust is only a few pixels across even at this size; smaller previews lose it.
+            (session.linear_for(PreviewKind::Zoom)?, raw_orientation(&session.raw_bytes))
+        };
+        frames.push(dust::Frame {
+            width: linear.width(),
+            height: linear.height(),
+            orientation,
+            candidates: dust::detect(&linear),
+        });
+    }
+
+    let recurrence = dust::recurrence(&frames);
+    let required = if frames.len() > 1 { 2 } else { 1 };
+    let sessions: Vec<Value> = handles
+        .iter()
+        .zip(&frames)
+        .zip(&recurrence)
+        .map(|((handle, frame), counts)| {
+            let (w, h) = ((frame.width - 1).max(1) as f32, (frame.height - 1).max(1) as f32);
+            let base = frame.width.min(frame.height).max(1) as f32;
+            let spots: Vec<Value> = frame
+                .candidates
+                .iter()
+                .zip(counts)
+                .filter(|(_, count)| **count >= required)
+                .map(|(c, count)| {
+                    json!({
+                        "mode": "heal",
+                        "x": c.x / w,
+                        "y": c.y / h,
+                        "sourceX": c.source_x / w,
+                        "sourceY": c.source_y / h,
+                        "radius": c.radius / base,
+                        "feather": 0.5,
+                        "opacity": 100.0,
+                        "confidence": c.confidence,
+                        "frames": count,
+                    })
+                })
+                .collect();
+            json!({ "handle": handle, "spots": spots })
+        })
+        .collect();
+    Ok(json!({ "sessions": sessions }).to_string())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecoder_detectDustSpots(
+    env: JNIEnv,
+    _: JClass,
+    handles: JLongArray,
+) -> jstring {
+    ensure_logger();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let len = match env.get_array_length(&handles) {
+            Ok(len) => len as usize,
+            Err(err) => {
+                error!("Failed to read session handles: {}", err);
+                return ptr::null_mut();
+            }
+        };
+        let mut buffer = vec![0 as jlong; len];
+        if let Err(err) = env.get_long_array_region(&handles, 0, &mut buffer) {
+            error!("Failed to read session handles: {}", err);
+            return ptr::null_mut();
+        }
+        let result = match detect_dust_from_sessions(&buffer) {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Failed to detect dust: {}", err);
+                return ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(_) => ptr::null_mut(),
+        }
+    }));
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            error!("Native panic in detectDustSpots");
+            ptr::null_mut()
+        }
+    }
+}
+
+// The session's untransformed linear preview for `kind`, and the preview kind it was actually
+// taken from.
+fn session_preview_source(
+    session: &mut Session,
+    payload: &AdjustmentsPayload,
+    kind: PreviewKind,
+) -> Result<(Arc<LinearImage>, PreviewKind)> {
     let effective_kind;
     let linear = if payload.preview.use_zoom {
         let requested = payload.preview.max_dimension;
@@ -4104,31 +5036,188 @@ fn render_from_session(
         effective_kind = kind;
         session.linear_for(kind)?
     };
-    // Try to consume the Arc, or clone if it's still shared
-    let linear_owned = Arc::try_unwrap(linear).unwrap_or_else(|arc| (*arc).clone());
-    let transformed = apply_transformations(linear_owned, &payload);
+    Ok((linear, effective_kind))
+}
+
+// The preview JPEG, and its histograms as JSON when the payload set `preview.histogram`.
+fn render_from_session(
+    handle: jlong,
+    adjustments_json: Option<&str>,
+    kind: PreviewKind,
+) -> Result<(Vec<u8>, Option<String>)> {
+    let session = get_session(handle).context("Invalid session handle")?;
+    let mut session = session.lock().map_err(|_| anyhow::anyhow!("Session lock poisoned"))?;
+
+    let payload = parse_adjustments_payload(adjustments_json);
+    let comparison = Comparison::new(&payload, session.compare_baseline(payload.preview.compare.baseline.as_ref()));
+    let (source, effective_kind) = session_preview_source(&mut session, &payload, kind)?;
+    // The baseline is developed from the untouched source with its own retouching and eye
+    // corrections, so only the current edit needs the cached buffer to itself.
+    let baseline_source = comparison.as_ref().map(|_| Arc::clone(&source));
+    let source = Arc::try_unwrap(source).unwrap_or_else(|arc| (*arc).clone());
+    let transformed = apply_transformations(source, &payload);
     let width = transformed.width();
     let height = transformed.height();
     let masks = session.masks_for(effective_kind, &payload.masks, width, height);
 
-    if let Some(roi) = payload.preview.roi.as_ref() {
-        render_linear_roi_with_payload(&transformed, &payload, masks, true, roi)
-    } else {
-        render_linear_with_payload(&transformed, &payload, masks, true)
+    let roi = payload.preview.roi.as_ref();
+    let render = |linear: &LinearImage, payload: &AdjustmentsPayload, masks: &[MaskRuntime], histograms, window| match roi {
+        Some(roi) => render_rgb_roi_with_payload(linear, payload, masks, roi, histograms, window),
+        None => render_rgb_with_payload(linear, payload, masks, histograms, window),
+    };
+    let (origin_x, origin_y, mut out_w, mut out_h) = match roi {
+        Some(roi) => crop_rect_pixels(width, height, roi),
+        None => (0, 0, width, height),
+    };
+
+    let mut histograms = payload.preview.histogram.then(Histograms::new);
+    let mut rgb = render(&transformed, &payload, masks, histograms.as_mut(), None)?;
+    if let (Some(comparison), Some(baseline_source)) = (comparison, baseline_source) {
+        let baseline_source = Arc::try_unwrap(baseline_source).unwrap_or_else(|arc| (*arc).clone());
+        let baseline_linear = apply_transformations(baseline_source, &comparison.baseline);
+        let baseline_masks = parse_masks(comparison.baseline.masks.clone(), width, height);
+        let [x, y, w, h] = comparison.baseline_window(out_w, out_h);
+        let window = Some([origin_x + x, origin_y + y, w, h]);
+        let before = render(&baseline_linear, &comparison.baseline, &baseline_masks, None, window)?;
+        (rgb, out_w, out_h) = comparison.compose(before, rgb, out_w, out_h);
+    }
+    let histogram = histograms.map(|histograms| histograms.to_json().to_string());
+    Ok((encode_preview_jpeg(&rgb, out_w, out_h, true)?, histogram))
+}
+
+// What a mask selects in the session's preview frame (cropped to the preview ROI, if any), as a
+// PNG: grey levels, or white with the selection as alpha. Without `sub_mask_id` this is the whole
+// mask including invert and opacity, exactly as rendering blends it; with it, that one sub-mask
+// on its own.
+fn render_mask_from_session(
+    handle: jlong,
+    adjustments_json: Option<&str>,
+    mask_id: &str,
+    sub_mask_id: Option<&str>,
+    alpha: bool,
+) -> Result<Vec<u8>> {
+    let session = get_session(handle).context("Invalid session handle")?;
+    let mut session = session.lock().map_err(|_| anyhow::anyhow!("Session lock poisoned"))?;
+
+    let payload = parse_adjustments_payload(adjustments_json);
+    // The transformed frame's size follows from the geometry alone; no need to render it.
+    let (source, _) = session_preview_source(&mut session, &payload, PreviewKind::Preview)?;
+    let transform = TransformState::new(source.width(), source.height(), &payload, Orientation::Normal);
+    let (width, height) = (transform.output_w, transform.output_h);
+    drop(session);
+
+    let value = payload
+        .masks
+        .iter()
+        .find(|mask| mask.get("id").and_then(Value::as_str) == Some(mask_id))
+        .context("Unknown mask id")?;
+    let selection: Box<dyn Fn(u32, u32) -> f32> = match sub_mask_id {
+        None => match parse_masks(vec![value.clone()], width, height).pop() {
+            Some(mask) => Box::new(move |x, y| {
+                let mut selection = mask_selection_at(&mask, x, y);
+                if mask.invert {
+                    selection = 1.0 - selection;
+                }
+                (selection * mask.opacity_factor).clamp(0.0, 1.0)
+            }),
+            // Hidden masks select nothing.
+            None => Box::new(|_, _| 0.0),
+        },
+        Some(sub_mask_id) => {
+            let def: MaskDefinitionPayload = serde_json::from_value(value.clone())?;
+            let mut sub_mask = def
+                .sub_masks
+                .into_iter()
+                .find(|sub_mask| sub_mask.id == sub_mask_id)
+                .context("Unknown sub-mask id")?;
+            // On its own a subtractive sub-mask shows the area it takes away.
+            sub_mask.mode = SubMaskMode::Additive;
+            let bitmap = generate_mask_bitmap(&[sub_mask], width, height);
+            Box::new(move |x, y| bitmap[(y * width + x) as usize] as f32 / 255.0)
+        }
+    };
+
+    let (roi_x, roi_y, roi_w, roi_h) = match payload.preview.roi.as_ref() {
+        Some(roi) => crop_rect_pixels(width, height, roi),
+        None => (0, 0, width, height),
+    };
+    let channels = if alpha { 2 } else { 1 };
+    let mut pixels = try_alloc_vec((roi_w as usize) * (roi_h as usize) * channels, 0u8)?;
+    for (i, out) in pixels.chunks_exact_mut(channels).enumerate() {
+        let (x, y) = (roi_x + i as u32 % roi_w, roi_y + i as u32 / roi_w);
+        let value = (selection(x, y) * 255.0).round() as u8;
+        if alpha {
+            out.copy_from_slice(&[255, value]);
+        } else {
+            out[0] = value;
+        }
     }
+
+    let mut encoded = Vec::new();
+    let color = if alpha { ExtendedColorType::La8 } else { ExtendedColorType::L8 };
+    PngEncoder::new(&mut encoded).write_image(&pixels, roi_w, roi_h, color)?;
+    Ok(encoded)
 }
 
 #[no_mangle]
-pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecoder_lowlowdecodeFromSession(
+pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecoder_renderMaskFromSession(
     mut env: JNIEnv,
     _: JClass,
     handle: jlong,
     adjustments_json: JString,
+    mask_id: JString,
+    sub_mask_id: JString,
+    alpha: jboolean,
 ) -> jbyteArray {
+    ensure_logger();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let adjustments = read_adjustments_json(&mut env, adjustments_json);
+        let mask_id: String = match env.get_string(&mask_id) {
+            Ok(s) => s.into(),
+            Err(err) => {
+                error!("Failed to read mask id: {}", err);
+                return ptr::null_mut();
+            }
+        };
+        let sub_mask_id: Option<String> = if sub_mask_id.is_null() {
+            None
+        } else {
+            match env.get_string(&sub_mask_id) {
+                Ok(s) => Some(s.into()),
+                Err(err) => {
+                    error!("Failed to read sub-mask id: {}", err);
+                    return ptr::null_mut();
+                }
+            }
+        };
+        match render_mask_from_session(handle, adjustments.as_deref(), &mask_id, sub_mask_id.as_deref(), alpha != 0) {
+            Ok(png) => make_byte_array(&env, &png),
+            Err(err) => {
+                error!("Failed to render mask: {}", err);
+                ptr::null_mut()
+            }
+        }
+    }));
+    match result {
+        Ok(value) => value,
+        Err(_) => {
+            error!("Native panic in renderMaskFromSession");
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecoder_lowlowdecodeFromSession(
+    mut env: JNIEnv,
+    _: JClass,
+    handle: jlong,
+    adjustments_json: JString,
+) -> jobject {
     ensure_logger();
     let adjustments = read_adjustments_json(&mut env, adjustments_json);
     match render_from_session(handle, adjustments.as_deref(), PreviewKind::SuperLow) {
-        Ok(payload) => make_byte_array(&env, &payload),
+        Ok((image, histogram)) => make_render_result(&mut env, &image, histogram.as_deref()),
         Err(err) => {
             error!("Failed to render session preview: {}", err);
             ptr::null_mut()
@@ -4142,11 +5231,11 @@ pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecode
     _: JClass,
     handle: jlong,
     adjustments_json: JString,
-) -> jbyteArray {
+) -> jobject {
     ensure_logger();
     let adjustments = read_adjustments_json(&mut env, adjustments_json);
     match render_from_session(handle, adjustments.as_deref(), PreviewKind::Low) {
-        Ok(payload) => make_byte_array(&env, &payload),
+        Ok((image, histogram)) => make_render_result(&mut env, &image, histogram.as_deref()),
         Err(err) => {
             error!("Failed to render session preview: {}", err);
             ptr::null_mut()
@@ -4160,11 +5249,11 @@ pub extern "system" fn Java_com_dueckis_kawaiiraweditor_data_native_LibRawDecode
     _: JClass,
     handle: jlong,
     adjustments_json: JString,
-) -> jbyteArray {
+) -> jobject {
     ensure_logger();
     let adjustments = read_adjustments_json(&mut env, adjustments_json);
     match render_from_session(handle, adjustments.as_deref(), PreviewKind::Preview) {
-        Ok(payload) => make_byte_array(&env, &payload),
+        Ok((image, histogram)) => make_render_result(&mut env, &image, histogram.as_deref()),
         Err(err) => {
             error!("Failed to render session preview: {}", err);
             ptr::null_mut()
@@ -0,0 +1,140 @@
+use std::fmt::Write as _;
+
+use anyhow::Result;
+use rayon::prelude::*;
+
+use crate::model::{AdjustmentValues, AdjustmentsPayload, CurvesRuntime, ToneMapper, ADJUSTMENT_SCALES};
+use crate::{apply_color_adjustments, apply_default_raw_processing, linear_to_srgb, srgb_to_linear, tone_map, Calibration};
+
+pub(crate) const LUT_SIZE_SMALL: u32 = 33;
+pub(crate) const LUT_SIZE_LARGE: u32 = 65;
+
+pub(crate) struct BakedLut {
+    pub size: u32,
+    pub cube: String,
+    pub excluded_stages: Vec<&'static str>,
+}
+
+// Stages that depend on pixel position or neighbourhood can't be expressed as a 3D LUT.
+fn excluded_stages(payload: &AdjustmentsPayload, values: &AdjustmentValues) -> Vec<&'static str> {
+    let mut excluded = Vec::new();
+    let active = |v: f32| v.abs() > 0.00001;
+    if active(values.sharpness) {
+        excluded.push("sharpness");
+    }
+    if active(values.clarity) {
+        excluded.push("clarity");
+    }
+    if active(values.structure) {
+        excluded.push("structure");
+    }
+    if active(values.centre) {
+        excluded.push("centre");
+    }
+    if active(values.vignette_amount) {
+        excluded.push("vignette");
+    }
+    if active(values.chromatic_aberration_red_cyan) || active(values.chromatic_aberration_blue_yellow) {
+        excluded.push("chromaticAberration");
+    }
+    if active(values.luma_noise_reduction) || active(values.color_noise_reduction) {
+        excluded.push("noiseReduction");
+    }
+    if !payload.masks.is_empty() {
+        excluded.push("masks");
+    }
+    excluded
+}
+
+fn global_only(values: AdjustmentValues) -> AdjustmentValues {
+    AdjustmentValues {
+        sharpness: 0.0,
+        clarity: 0.0,
+        structure: 0.0,
+        centre: 0.0,
+        vignette_amount: 0.0,
+        chromatic_aberration_red_cyan: 0.0,
+        chromatic_aberration_blue_yellow: 0.0,
+        luma_noise_reduction: 0.0,
+        color_noise_reduction: 0.0,
+        ..values
+    }
+}
+
+// Input and output are both sRGB-encoded, so the LUT drops straight onto display-referred footage.
+fn eval_global_pipeline(
+    srgb_in: [f32; 3],
+    values: &AdjustmentValues,
+    calibration: &Calibration,
+    curves: &CurvesRuntime,
+    curves_are_active: bool,
+) -> [f32; 3] {
+    let use_basic_tone_mapper = matches!(values.tone_mapper, ToneMapper::Basic);
+    let mut colors = [
+        srgb_to_linear(srgb_in[0]),
+        srgb_to_linear(srgb_in[1]),
+        srgb_to_linear(srgb_in[2]),
+    ];
+    colors = apply_default_raw_processing(calibration.apply(colors), use_basic_tone_mapper);
+    colors = apply_color_adjustments(colors, values, 0.0);
+    colors = tone_map(colors, values.tone_mapper);
+    let mut srgb = [
+        linear_to_srgb(colors[0]),
+        linear_to_srgb(colors[1]),
+        linear_to_srgb(colors[2]),
+    ];
+    if curves_are_active {
+        srgb = curves.apply_all(srgb);
+    }
+    [srgb[0].clamp(0.0, 1.0), srgb[1].clamp(0.0, 1.0), srgb[2].clamp(0.0, 1.0)]
+}
+
+pub(crate) fn bake_cube_lut(payload: &AdjustmentsPayload, size: u32) -> Result<BakedLut> {
+    if size != LUT_SIZE_SMALL && size != LUT_SIZE_LARGE {
+        return Err(anyhow::anyhow!(
+            "Unsupported LUT size {} (expected {} or {})",
+            size,
+            LUT_SIZE_SMALL,
+            LUT_SIZE_LARGE
+        ));
+    }
+
+    let values = payload.to_values().normalized(&ADJUSTMENT_SCALES);
+    let excluded = excluded_stages(payload, &values);
+    let mut values = global_only(values);
+    let calibration = Calibration::take(&mut values);
+    let curves = CurvesRuntime::from_payload(&payload.curves);
+    let curves_are_active = !curves.is_default();
+
+    let n = size as usize;
+    let scale = 1.0 / (size - 1) as f32;
+    // .cube ordering: red varies fastest, then green, then blue.
+    let entries: Vec<[f32; 3]> = (0..n * n * n)
+        .into_par_iter()
+        .map(|i| {
+            let r = (i % n) as f32 * scale;
+            let g = ((i / n) % n) as f32 * scale;
+            let b = (i / (n * n)) as f32 * scale;
+            eval_global_pipeline([r, g, b], &values, &calibration, &curves, curves_are_active)
+        })
+        .collect();
+
+    let mut cube = String::with_capacity(entries.len() * 28 + 256);
+    cube.push_str("TITLE \"IRIDIS\"\n");
+    cube.push_str("# Input: sRGB encoded, Output: sRGB encoded\n");
+    if !excluded.is_empty() {
+        let _ = writeln!(cube, "# Excluded spatial stages: {}", excluded.join(", "));
+    }
+    let _ = writeln!(cube, "LUT_3D_SIZE {}", size);
+    cube.push_str("DOMAIN_MIN 0.0 0.0 0.0\n");
+    cube.push_str("DOMAIN_MAX 1.0 1.0 1.0\n");
+    for rgb in &entries {
+        let _ = writeln!(cube, "{:.6} {:.6} {:.6}", rgb[0], rgb[1], rgb[2]);
+    }
+
+    Ok(BakedLut {
+        size,
+        cube,
+        excluded_stages: excluded,
+    })
+}
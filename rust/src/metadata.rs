@@ -0,0 +1,482 @@
+// Capture metadata carried from the RAW into exported files as EXIF (TIFF structure) and XMP.
+// Orientation is always written as 1 because the renderer bakes the RAW orientation into the pixels.
+
+use anyhow::{Context, Result};
+use rawler::decoders::RawDecodeParams;
+use rawler::exif::Exif;
+use rawler::formats::tiff::{Rational, SRational};
+use rawler::rawsource::RawSource;
+use rawler::tags::{ExifGpsTag, ExifTag};
+
+use crate::export::{write_ifd, TiffEntry};
+use crate::model::{MetadataPayload, OutputColorSpace};
+
+const SOFTWARE: &str = "IRIDIS";
+const DEFAULT_DPI: u16 = 72;
+pub(crate) const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+#[derive(Clone, Default)]
+pub(crate) struct ExportMetadata {
+    make: String,
+    model: String,
+    lens: Option<String>,
+    rating: Option<u32>,
+    exif: Exif,
+    description: Option<String>,
+    width: u32,
+    height: u32,
+    srgb: bool,
+    dpi: u16,
+}
+
+fn rational(v: Rational) -> (u32, u32) {
+    (v.n, v.d)
+}
+
+fn gps_rationals(v: &[Rational; 3]) -> [(u32, u32); 3] {
+    [rational(v[0]), rational(v[1]), rational(v[2])]
+}
+
+fn push_ascii(entries: &mut Vec<TiffEntry>, tag: u16, value: &Option<String>) {
+    if let Some(text) = value.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        entries.push(TiffEntry::ascii(tag, text));
+    }
+}
+
+fn push_short(entries: &mut Vec<TiffEntry>, tag: u16, value: Option<u16>) {
+    if let Some(v) = value {
+        entries.push(TiffEntry::shorts(tag, &[v]));
+    }
+}
+
+fn push_long(entries: &mut Vec<TiffEntry>, tag: u16, value: Option<u32>) {
+    if let Some(v) = value {
+        entries.push(TiffEntry::longs(tag, &[v]));
+    }
+}
+
+fn push_rational(entries: &mut Vec<TiffEntry>, tag: u16, value: Option<Rational>) {
+    if let Some(v) = value {
+        entries.push(TiffEntry::rational(tag, v.n, v.d));
+    }
+}
+
+fn push_srational(entries: &mut Vec<TiffEntry>, tag: u16, value: Option<SRational>) {
+    if let Some(v) = value {
+        entries.push(TiffEntry::srational(tag, v.n, v.d));
+    }
+}
+
+// EXIF UserComment carries an 8-byte character code in front of the text.
+fn user_comment(text: &str) -> Vec<u8> {
+    if text.is_ascii() {
+        [b"ASCII\0\0\0".as_slice(), text.as_bytes()].concat()
+    } else {
+        let mut out = b"UNICODE\0".to_vec();
+        out.extend(text.encode_utf16().flat_map(|c| c.to_le_bytes()));
+        out
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c if (c as u32) < 0x20 && c != '\t' && c != '\n' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// "YYYY:MM:DD HH:MM:SS" plus an optional "+HH:MM" offset to ISO 8601.
+fn xmp_date(date: &str, offset: Option<&str>) -> Option<String> {
+    let date = date.trim();
+    let b = date.as_bytes();
+    if b.len() < 19 || b[4] != b':' || b[7] != b':' || b[10] != b' ' {
+        return None;
+    }
+    let mut out = format!("{}-{}-{}T{}", &date[0..4], &date[5..7], &date[8..10], &date[11..19]);
+    if let Some(offset) = offset.map(str::trim).filter(|o| o.len() == 6) {
+        out.push_str(offset);
+    }
+    Some(out)
+}
+
+// XMP GPS coordinates are "DDD,MM.mmmmmmR".
+fn xmp_coordinate(value: &[Rational; 3], reference: Option<&str>) -> Option<String> {
+    let part = |r: Rational| if r.d == 0 { 0.0 } else { r.n as f64 / r.d as f64 };
+    let reference = reference?.trim().chars().next()?;
+    let minutes = part(value[1]) + part(value[2]) / 60.0;
+    Some(format!("{},{:.6}{}", part(value[0]) as u32, minutes, reference))
+}
+
+impl ExportMetadata {
+    pub(crate) fn from_raw(raw_bytes: &[u8]) -> Result<Self> {
+        let source = RawSource::new_from_slice(raw_bytes);
+        let decoder = rawler::get_decoder(&source).context("No decoder for RAW")?;
+        let metadata = decoder
+            .raw_metadata(&source, &RawDecodeParams::default())
+            .context("Failed to read RAW metadata")?;
+        Ok(Self {
+            lens: metadata
+                .lens
+                .as_ref()
+                .map(|l| l.lens_name.clone())
+                .or_else(|| metadata.exif.lens_model.clone()),
+            make: metadata.make,
+            model: metadata.model,
+            rating: metadata.rating,
+            exif: metadata.exif,
+            description: None,
+            width: 0,
+            height: 0,
+            srgb: true,
+            dpi: DEFAULT_DPI,
+        })
+    }
+
+    // Copy describing the rendered output rather than the sensor, filtered by the export's
+    // privacy settings and with the injected fields applied.
+    pub(crate) fn for_output(
+        &self,
+        width: u32,
+        height: u32,
+        color_space: OutputColorSpace,
+        policy: &MetadataPayload,
+    ) -> Self {
+        let mut out = if policy.copyright_only {
+            Self {
+                make: String::new(),
+                model: String::new(),
+                lens: None,
+                rating: None,
+                exif: Exif {
+                    copyright: self.exif.copyright.clone(),
+                    artist: self.exif.artist.clone(),
+                    ..Exif::default()
+                },
+                description: self.description.clone(),
+                width,
+                height,
+                srgb: true,
+                dpi: DEFAULT_DPI,
+            }
+        } else {
+            Self { width, height, ..self.clone() }
+        };
+        out.srgb = color_space == OutputColorSpace::Srgb;
+        out.dpi = DEFAULT_DPI;
+        if policy.strip_location {
+            out.exif.gps = None;
+        }
+        if policy.strip_identifiers {
+            out.exif.serial_number = None;
+            out.exif.lens_serial_number = None;
+            out.exif.owner_name = None;
+        }
+
+        let injected = |v: &Option<String>| v.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(String::from);
+        if let Some(copyright) = injected(&policy.copyright) {
+            out.exif.copyright = Some(copyright);
+        }
+        if let Some(creator) = injected(&policy.creator) {
+            out.exif.artist = Some(creator);
+        }
+        if let Some(description) = injected(&policy.description) {
+            out.description = Some(description);
+        }
+        out
+    }
+
+    pub(crate) fn with_density(mut self, dpi: u16) -> Self {
+        self.dpi = dpi;
+        self
+    }
+
+    // One-line shooting summary for captions, e.g. "X-T5 · XF33mm · 1/250 s · f/2.8 · ISO 200 · 33 mm".
+    pub(crate) fn caption_line(&self) -> String {
+        let value = |r: Option<Rational>| r.filter(|r| r.d != 0 && r.n != 0).map(|r| r.n as f32 / r.d as f32);
+        let mut parts: Vec<String> = Vec::new();
+        parts.extend(Some(self.model.trim()).filter(|m| !m.is_empty()).map(String::from));
+        parts.extend(self.lens.as_deref().map(str::trim).filter(|l| !l.is_empty()).map(String::from));
+        if let Some(t) = value(self.exif.exposure_time) {
+            parts.push(if t < 1.0 { format!("1/{} s", (1.0 / t).round()) } else { format!("{} s", t) });
+        }
+        if let Some(f) = value(self.exif.fnumber) {
+            parts.push(format!("f/{}", (f * 10.0).round() / 10.0));
+        }
+        if let Some(iso) = self.exif.iso_speed_ratings.map(u32::from).or(self.exif.iso_speed) {
+            parts.push(format!("ISO {}", iso));
+        }
+        if let Some(mm) = value(self.exif.focal_length) {
+            parts.push(format!("{} mm", mm.round()));
+        }
+        parts.join(" · ")
+    }
+
+    fn exif_ifd_entries(&self) -> Vec<TiffEntry> {
+        let e = &self.exif;
+        let mut entries = vec![
+            TiffEntry::undefined(ExifTag::ExifVersion as u16, b"0232".to_vec()),
+            TiffEntry::shorts(ExifTag::ColorSpace as u16, &[if self.srgb { 1 } else { 0xFFFF }]),
+            TiffEntry::longs(ExifTag::ExifImageWidth as u16, &[self.width]),
+            TiffEntry::longs(ExifTag::ExifImageHeight as u16, &[self.height]),
+        ];
+        push_rational(&mut entries, ExifTag::ExposureTime as u16, e.exposure_time);
+        push_rational(&mut entries, ExifTag::FNumber as u16, e.fnumber);
+        push_short(&mut entries, ExifTag::ExposureProgram as u16, e.exposure_program);
+        push_short(&mut entries, ExifTag::ISOSpeedRatings as u16, e.iso_speed_ratings);
+        push_short(&mut entries, ExifTag::SensitivityType as u16, e.sensitivity_type);
+        push_long(&mut entries, ExifTag::RecommendedExposureIndex as u16, e.recommended_exposure_index);
+        push_long(&mut entries, ExifTag::ISOSpeed as u16, e.iso_speed);
+        push_ascii(&mut entries, ExifTag::DateTimeOriginal as u16, &e.date_time_original);
+        push_ascii(&mut entries, ExifTag::CreateDate as u16, &e.create_date);
+        push_ascii(&mut entries, ExifTag::OffsetTime as u16, &e.offset_time);
+        push_ascii(&mut entries, ExifTag::OffsetTimeOriginal as u16, &e.offset_time_original);
+        push_ascii(&mut entries, ExifTag::OffsetTimeDigitized as u16, &e.offset_time_digitized);
+        push_srational(&mut entries, ExifTag::ShutterSpeedValue as u16, e.shutter_speed_value);
+        push_rational(&mut entries, ExifTag::ApertureValue as u16, e.aperture_value);
+        push_srational(&mut entries, ExifTag::BrightnessValue as u16, e.brightness_value);
+        push_srational(&mut entries, ExifTag::ExposureBiasValue as u16, e.exposure_bias);
+        push_rational(&mut entries, ExifTag::MaxApertureValue as u16, e.max_aperture_value);
+        push_rational(&mut entries, ExifTag::SubjectDistance as u16, e.subject_distance);
+        push_short(&mut entries, ExifTag::MeteringMode as u16, e.metering_mode);
+        push_short(&mut entries, ExifTag::LightSource as u16, e.light_source);
+        push_short(&mut entries, ExifTag::Flash as u16, e.flash);
+        push_rational(&mut entries, ExifTag::FocalLength as u16, e.focal_length);
+        push_long(&mut entries, ExifTag::ImageNumber as u16, e.image_number);
+        if let Some(comment) = e.user_comment.as_deref().filter(|c| !c.trim().is_empty()) {
+            entries.push(TiffEntry::undefined(ExifTag::UserComment as u16, user_comment(comment)));
+        }
+        push_ascii(&mut entries, ExifTag::SubSecTime as u16, &e.sub_sec_time);
+        push_ascii(&mut entries, ExifTag::SubSecTimeOriginal as u16, &e.sub_sec_time_original);
+        push_ascii(&mut entries, ExifTag::SubSecTimeDigitized as u16, &e.sub_sec_time_digitized);
+        push_rational(&mut entries, ExifTag::FlashEnergy2 as u16, e.flash_energy);
+        push_short(&mut entries, ExifTag::ExposureMode as u16, e.exposure_mode);
+        push_short(&mut entries, ExifTag::WhiteBalance as u16, e.white_balance);
+        push_short(&mut entries, ExifTag::SceneCaptureType as u16, e.scene_capture_type);
+        push_short(&mut entries, ExifTag::SubjectDistanceRange as u16, e.subject_distance_range);
+        push_ascii(&mut entries, ExifTag::OwnerName as u16, &e.owner_name);
+        push_ascii(&mut entries, ExifTag::SerialNumber as u16, &e.serial_number);
+        if let Some(spec) = e.lens_spec {
+            entries.push(TiffEntry::rationals(ExifTag::LensSpecification as u16, &spec.map(rational)));
+        }
+        push_ascii(&mut entries, ExifTag::LensMake as u16, &e.lens_make);
+        push_ascii(&mut entries, ExifTag::LensModel as u16, &e.lens_model);
+        push_ascii(&mut entries, ExifTag::LensSerialNumber as u16, &e.lens_serial_number);
+        entries
+    }
+
+    fn gps_ifd_entries(&self) -> Vec<TiffEntry> {
+        let Some(g) = self.exif.gps.as_ref() else {
+            return Vec::new();
+        };
+        let mut entries = Vec::new();
+        if let Some(version) = g.gps_version_id {
+            entries.push(TiffEntry::bytes(ExifGpsTag::GPSVersionID as u16, &version));
+        }
+        push_ascii(&mut entries, ExifGpsTag::GPSLatitudeRef as u16, &g.gps_latitude_ref);
+        if let Some(v) = g.gps_latitude.as_ref() {
+            entries.push(TiffEntry::rationals(ExifGpsTag::GPSLatitude as u16, &gps_rationals(v)));
+        }
+        push_ascii(&mut entries, ExifGpsTag::GPSLongitudeRef as u16, &g.gps_longitude_ref);
+        if let Some(v) = g.gps_longitude.as_ref() {
+            entries.push(TiffEntry::rationals(ExifGpsTag::GPSLongitude as u16, &gps_rationals(v)));
+        }
+        if let Some(v) = g.gps_altitude_ref {
+            entries.push(TiffEntry::bytes(ExifGpsTag::GPSAltitudeRef as u16, &[v]));
+        }
+        push_rational(&mut entries, ExifGpsTag::GPSAltitude as u16, g.gps_altitude);
+        if let Some(v) = g.gps_timestamp.as_ref() {
+            entries.push(TiffEntry::rationals(ExifGpsTag::GPSTimeStamp as u16, &gps_rationals(v)));
+        }
+        push_ascii(&mut entries, ExifGpsTag::GPSSatellites as u16, &g.gps_satellites);
+        push_ascii(&mut entries, ExifGpsTag::GPSStatus as u16, &g.gps_status);
+        push_ascii(&mut entries, ExifGpsTag::GPSMeasureMode as u16, &g.gps_measure_mode);
+        push_rational(&mut entries, ExifGpsTag::GPSDOP as u16, g.gps_dop);
+        push_ascii(&mut entries, ExifGpsTag::GPSSpeedRef as u16, &g.gps_speed_ref);
+        push_rational(&mut entries, ExifGpsTag::GPSSpeed as u16, g.gps_speed);
+        push_ascii(&mut entries, ExifGpsTag::GPSTrackRef as u16, &g.gps_track_ref);
+        push_rational(&mut entries, ExifGpsTag::GPSTrack as u16, g.gps_track);
+        push_ascii(&mut entries, ExifGpsTag::GPSImgDirectionRef as u16, &g.gps_img_direction_ref);
+        push_rational(&mut entries, ExifGpsTag::GPSImgDirection as u16, g.gps_img_direction);
+        push_ascii(&mut entries, ExifGpsTag::GPSMapDatum as u16, &g.gps_map_datum);
+        push_ascii(&mut entries, ExifGpsTag::GPSDestLatitudeRef as u16, &g.gps_dest_latitude_ref);
+        if let Some(v) = g.gps_dest_latitude.as_ref() {
+            entries.push(TiffEntry::rationals(ExifGpsTag::GPSDestLatitude as u16, &gps_rationals(v)));
+        }
+        push_ascii(&mut entries, ExifGpsTag::GPSDestLongitudeRef as u16, &g.gps_dest_longitude_ref);
+        if let Some(v) = g.gps_dest_longitude.as_ref() {
+            entries.push(TiffEntry::rationals(ExifGpsTag::GPSDestLongitude as u16, &gps_rationals(v)));
+        }
+        push_ascii(&mut entries, ExifGpsTag::GPSDestBearingRef as u16, &g.gps_dest_bearing_ref);
+        push_rational(&mut entries, ExifGpsTag::GPSDestBearing as u16, g.gps_dest_bearing);
+        push_ascii(&mut entries, ExifGpsTag::GPSDestDistanceRef as u16, &g.gps_dest_distance_ref);
+        push_rational(&mut entries, ExifGpsTag::GPSDestDistance as u16, g.gps_dest_distance);
+        if let Some(v) = g.gps_processing_method.as_ref() {
+            entries.push(TiffEntry::undefined(ExifGpsTag::GPSProcessingMethod as u16, v.clone()));
+        }
+        if let Some(v) = g.gps_area_information.as_ref() {
+            entries.push(TiffEntry::undefined(ExifGpsTag::GPSAreaInformation as u16, v.clone()));
+        }
+        push_ascii(&mut entries, ExifGpsTag::GPSDateStamp as u16, &g.gps_date_stamp);
+        push_short(&mut entries, ExifGpsTag::GPSDifferential as u16, g.gps_differential);
+        push_rational(&mut entries, ExifGpsTag::GPSHPositioningError as u16, g.gps_h_positioning_error);
+        entries
+    }
+
+    // Writes the Exif and GPS sub-IFDs at the end of `out` and returns the IFD0 entries,
+    // including the pointers to them. Used directly by TIFF output.
+    // Sub-IFDs are appended to `out`, which starts `base` bytes into the file.
+    pub(crate) fn ifd0_entries(&self, out: &mut Vec<u8>, base: u64) -> Result<Vec<TiffEntry>> {
+        let e = &self.exif;
+        let mut entries = Vec::new();
+        push_ascii(&mut entries, ExifTag::ImageDescription as u16, &self.description);
+        push_ascii(&mut entries, ExifTag::Make as u16, &Some(self.make.clone()));
+        push_ascii(&mut entries, ExifTag::Model as u16, &Some(self.model.clone()));
+        entries.push(TiffEntry::shorts(ExifTag::Orientation as u16, &[1]));
+        entries.push(TiffEntry::ascii(ExifTag::Software as u16, SOFTWARE));
+        push_ascii(&mut entries, ExifTag::ModifyDate as u16, &e.modify_date.clone().or(e.date_time_original.clone()));
+        push_ascii(&mut entries, ExifTag::Artist as u16, &e.artist);
+        push_ascii(&mut entries, ExifTag::Copyright as u16, &e.copyright);
+
+        let exif_offset = write_ifd(out, base, &mut self.exif_ifd_entries())?;
+        entries.push(TiffEntry::longs(ExifTag::ExifOffset as u16, &[exif_offset]));
+        let mut gps = self.gps_ifd_entries();
+        if !gps.is_empty() {
+            let gps_offset = write_ifd(out, base, &mut gps)?;
+            entries.push(TiffEntry::longs(ExifTag::GPSInfo as u16, &[gps_offset]));
+        }
+        Ok(entries)
+    }
+
+    // Standalone little-endian TIFF structure, as embedded by JPEG APP1, PNG eXIf and WebP EXIF.
+    pub(crate) fn exif_block(&self) -> Result<Vec<u8>> {
+        let mut out = b"II*\0\0\0\0\0".to_vec();
+        let mut entries = self.ifd0_entries(&mut out, 0)?;
+        // TIFF output writes these itself as part of the image IFD.
+        entries.push(TiffEntry::rational(ExifTag::XResolution as u16, self.dpi as u32, 1));
+        entries.push(TiffEntry::rational(ExifTag::YResolution as u16, self.dpi as u32, 1));
+        entries.push(TiffEntry::shorts(ExifTag::ResolutionUnit as u16, &[2]));
+        let ifd0 = write_ifd(&mut out, 0, &mut entries)?;
+        out[4..8].copy_from_slice(&ifd0.to_le_bytes());
+        Ok(out)
+    }
+
+    // The rdf:Description element on its own, so it can share a packet with other XMP.
+    pub(crate) fn xmp_description(&self) -> String {
+        let e = &self.exif;
+        let mut attrs = vec![("xmp:CreatorTool", SOFTWARE.to_string()), ("tiff:Orientation", "1".to_string())];
+        if !self.make.trim().is_empty() {
+            attrs.push(("tiff:Make", self.make.trim().to_string()));
+        }
+        if !self.model.trim().is_empty() {
+            attrs.push(("tiff:Model", self.model.trim().to_string()));
+        }
+        let original = e.date_time_original.as_deref().or(e.create_date.as_deref());
+        if let Some(date) = original.and_then(|d| xmp_date(d, e.offset_time_original.as_deref())) {
+            attrs.push(("exif:DateTimeOriginal", date.clone()));
+            attrs.push(("xmp:CreateDate", date));
+        }
+        if let Some(rating) = self.rating {
+            attrs.push(("xmp:Rating", rating.to_string()));
+        }
+        if let Some(lens) = self.lens.as_ref().filter(|l| !l.trim().is_empty()) {
+            attrs.push(("aux:Lens", lens.trim().to_string()));
+        }
+        if let Some(serial) = e.serial_number.as_ref().filter(|s| !s.trim().is_empty()) {
+            attrs.push(("aux:SerialNumber", serial.trim().to_string()));
+        }
+        if let Some(serial) = e.lens_serial_number.as_ref().filter(|s| !s.trim().is_empty()) {
+            attrs.push(("aux:LensSerialNumber", serial.trim().to_string()));
+        }
+        if let Some(gps) = e.gps.as_ref() {
+            if let Some(v) = gps.gps_latitude.as_ref().and_then(|v| xmp_coordinate(v, gps.gps_latitude_ref.as_deref())) {
+                attrs.push(("exif:GPSLatitude", v));
+            }
+            if let Some(v) = gps.gps_longitude.as_ref().and_then(|v| xmp_coordinate(v, gps.gps_longitude_ref.as_deref())) {
+                attrs.push(("exif:GPSLongitude", v));
+            }
+            if let Some(v) = gps.gps_altitude {
+                attrs.push(("exif:GPSAltitude", format!("{}/{}", v.n, v.d)));
+                attrs.push(("exif:GPSAltitudeRef", gps.gps_altitude_ref.unwrap_or(0).to_string()));
+            }
+        }
+
+        let mut out = String::from(concat!(
+            "<rdf:Description rdf:about=\"\"",
+            " xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"",
+            " xmlns:tiff=\"http://ns.adobe.com/tiff/1.0/\"",
+            " xmlns:exif=\"http://ns.adobe.com/exif/1.0/\"",
+            " xmlns:aux=\"http://ns.adobe.com/exif/1.0/aux/\"",
+            " xmlns:dc=\"http://purl.org/dc/elements/1.1/\""
+        ));
+        for (name, value) in attrs {
+            out.push_str(&format!(" {}=\"{}\"", name, xml_escape(&value)));
+        }
+        out.push('>');
+        if let Some(artist) = e.artist.as_ref().filter(|a| !a.trim().is_empty()) {
+            out.push_str(&format!(
+                "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+                xml_escape(artist.trim())
+            ));
+        }
+        if let Some(description) = self.description.as_ref() {
+            out.push_str(&format!(
+                "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>",
+                xml_escape(description)
+            ));
+        }
+        if let Some(copyright) = e.copyright.as_ref().filter(|c| !c.trim().is_empty()) {
+            out.push_str(&format!(
+                "<dc:rights><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:rights>",
+                xml_escape(copyright.trim())
+            ));
+        }
+        out.push_str("</rdf:Description>");
+        out
+    }
+
+    pub(crate) fn xmp_packet(&self) -> String {
+        format!(
+            concat!(
+                "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>",
+                "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\" x:xmptk=\"{}\">",
+                "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">{}</rdf:RDF>",
+                "</x:xmpmeta><?xpacket end=\"w\"?>"
+            ),
+            SOFTWARE,
+            self.xmp_description()
+        )
+    }
+
+    // Inserts the APP1 segments into an already encoded JPEG, right after SOI/JFIF.
+    pub(crate) fn embed_in_jpeg(&self, jpeg: Vec<u8>) -> Result<Vec<u8>> {
+        if jpeg.len() < 4 || jpeg[0] != 0xFF || jpeg[1] != 0xD8 {
+            return Err(anyhow::anyhow!("Not a JPEG"));
+        }
+        let mut at = 2;
+        if jpeg[2] == 0xFF && jpeg[3] == 0xE0 && jpeg.len() >= 6 {
+            at += 2 + u16::from_be_bytes([jpeg[4], jpeg[5]]) as usize;
+        }
+        let mut out = Vec::with_capacity(jpeg.len() + 4096);
+        out.extend_from_slice(&jpeg[..at]);
+        let segments = [
+            [b"Exif\0\0".as_slice(), &self.exif_block()?].concat(),
+            [XMP_NAMESPACE, self.xmp_packet().as_bytes()].concat(),
+        ];
+        for segment in segments {
+            let len = u16::try_from(segment.len() + 2).context("Metadata segment too large")?;
+            out.extend_from_slice(&[0xFF, 0xE1]);
+            out.extend_from_slice(&len.to_be_bytes());
+            out.extend_from_slice(&segment);
+        }
+        out.extend_from_slice(&jpeg[at..]);
+        Ok(out)
+    }
+}
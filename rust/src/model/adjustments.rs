@@ -1,6 +1,8 @@
 use serde::Deserialize;
 use std::ops::AddAssign;
 
+use super::curves::get_luma;
+
 #[derive(Clone, Copy, Default)]
 pub struct AdjustmentValues {
     pub exposure: f32,
@@ -30,6 +32,11 @@ pub struct AdjustmentValues {
     pub tone_mapper: ToneMapper,
     pub color_grading: ColorGradingValues,
     pub hsl: [HslColorValues; 8],
+    pub monochrome: MonochromeValues,
+    pub calibration: CalibrationValues,
+    pub channel_mixer: ChannelMixerValues,
+    // Calibration followed by the channel mixer, set by `normalized` when either does anything.
+    pub color_matrix: Option<[[f32; 3]; 3]>,
 }
 
 #[derive(Clone, Copy, Default)]
@@ -39,6 +46,117 @@ pub struct HslColorValues {
     pub luminance: f32,
 }
 
+#[derive(Clone, Copy, Default)]
+pub struct MonochromeValues {
+    pub enabled: bool,
+    // Brightness change per HSL band, in HSL_RANGES order.
+    pub mixer: [f32; 8],
+    // R, G and B contributions to the grey value.
+    pub weights: [f32; 3],
+    pub tone_hue: f32,
+    pub tone_saturation: f32,
+}
+
+impl MonochromeValues {
+    pub fn normalized(self) -> Self {
+        Self {
+            enabled: self.enabled,
+            mixer: self.mixer.map(|v| v / 100.0),
+            weights: self.weights.map(|v| v / 100.0),
+            tone_hue: self.tone_hue,
+            tone_saturation: self.tone_saturation / 100.0,
+        }
+    }
+}
+
+// Largest rotation of a calibration primary, in degrees, at a hue of ±100.
+const CALIBRATION_MAX_HUE: f32 = 30.0;
+
+#[derive(Clone, Copy, Default)]
+pub struct CalibrationValues {
+    // Per camera primary, in R, G, B order.
+    pub hue: [f32; 3],
+    pub saturation: [f32; 3],
+    pub shadow_tint: f32,
+}
+
+impl CalibrationValues {
+    pub fn normalized(self) -> Self {
+        Self {
+            hue: self.hue.map(|v| v / 100.0),
+            saturation: self.saturation.map(|v| v / 100.0),
+            shadow_tint: self.shadow_tint / 100.0,
+        }
+    }
+
+    fn is_identity(&self) -> bool {
+        self.hue.iter().chain(&self.saturation).all(|v| v.abs() <= 0.000001)
+    }
+
+    // Each primary rotated around the grey axis and pushed away from or towards grey at its own
+    // luminance, then rows rescaled so white stays white.
+    fn matrix(&self) -> [[f32; 3]; 3] {
+        let k = 1.0 / 3f32.sqrt();
+        let mut matrix = [[0f32; 3]; 3];
+        for primary in 0..3 {
+            let mut p = [0f32; 3];
+            p[primary] = 1.0;
+            let (sin, cos) = (self.hue[primary] * CALIBRATION_MAX_HUE).to_radians().sin_cos();
+            // Rodrigues rotation about (1, 1, 1) / sqrt(3).
+            let along = k * (p[0] + p[1] + p[2]) * (1.0 - cos);
+            let cross = [k * (p[2] - p[1]), k * (p[0] - p[2]), k * (p[1] - p[0])];
+            let rotated: [f32; 3] = std::array::from_fn(|c| p[c] * cos + cross[c] * sin + k * along);
+
+            let grey = get_luma(rotated);
+            let scale = (1.0 + self.saturation[primary]).max(0.0);
+            let pushed = rotated.map(|v| grey + (v - grey) * scale);
+            let keep = get_luma(p) / get_luma(pushed).max(0.000001);
+            for (row, v) in matrix.iter_mut().zip(pushed) {
+                row[primary] = v * keep;
+            }
+        }
+        for row in matrix.iter_mut() {
+            let sum: f32 = row.iter().sum();
+            if sum.abs() > 0.000001 {
+                *row = row.map(|v| v / sum);
+            }
+        }
+        matrix
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ChannelMixerValues {
+    // Rows are output channels, columns the R, G, B inputs, as fractions of the input.
+    pub matrix: [[f32; 3]; 3],
+}
+
+impl Default for ChannelMixerValues {
+    fn default() -> Self {
+        Self {
+            matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+}
+
+impl ChannelMixerValues {
+    fn is_default(&self) -> bool {
+        let identity = Self::default().matrix;
+        (0..3).all(|r| (0..3).all(|c| (self.matrix[r][c] - identity[r][c]).abs() <= 0.000001))
+    }
+}
+
+// Calibration then mixer as one matrix, or `None` when both are identities. Takes normalized values.
+fn color_matrix(calibration: &CalibrationValues, mixer: &ChannelMixerValues) -> Option<[[f32; 3]; 3]> {
+    if calibration.is_identity() && mixer.is_default() {
+        return None;
+    }
+    let calibration = calibration.matrix();
+    Some(std::array::from_fn(|r| {
+        std::array::from_fn(|c| (0..3).map(|i| mixer.matrix[r][i] * calibration[i][c]).sum())
+    }))
+}
+
 #[derive(Clone, Copy, Default)]
 pub struct ColorGradeSettings {
     pub hue: f32,
@@ -165,6 +283,9 @@ impl AdjustmentValues {
             color.luminance = scale(color.luminance, scales.hsl_luminance);
         }
 
+        let calibration = self.calibration.normalized();
+        let channel_mixer = self.channel_mixer;
+
         AdjustmentValues {
             exposure: scale(self.exposure, scales.exposure),
             brightness: scale(self.brightness, scales.brightness),
@@ -193,6 +314,10 @@ impl AdjustmentValues {
             tone_mapper: self.tone_mapper,
             color_grading: self.color_grading.normalized(),
             hsl,
+            monochrome: self.monochrome.normalized(),
+            calibration,
+            channel_mixer,
+            color_matrix: color_matrix(&calibration, &channel_mixer),
         }
     }
 }
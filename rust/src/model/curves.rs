@@ -38,7 +38,39 @@ impl CurveRuntime {
 
         pts.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
         pts.truncate(16);
+        Self::from_points(pts)
+    }
 
+    // Curve over a periodic axis such as hue, where x = 255 wraps to 0. The ends are joined by
+    // copying the last two points before 0 and the first two past 255.
+    pub fn periodic(points: &[CurvePointPayload]) -> Self {
+        let mut pts: Vec<CurvePoint> = points
+            .iter()
+            .filter(|p| p.x >= 0.0 && p.x <= 255.0)
+            .map(|p| CurvePoint { x: p.x, y: p.y })
+            .collect();
+        // Points at 0 and 255 sit on the same hue, so they become one point at their average.
+        let ends: Vec<f32> = pts.iter().filter(|p| p.x <= 0.0 || p.x >= 255.0).map(|p| p.y).collect();
+        pts.retain(|p| p.x > 0.0 && p.x < 255.0);
+        if !ends.is_empty() {
+            pts.push(CurvePoint { x: 0.0, y: ends.iter().sum::<f32>() / ends.len() as f32 });
+        }
+        pts.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
+        pts.truncate(16);
+        let n = pts.len();
+        if n < 2 {
+            let y = pts.first().map_or(128.0, |p| p.y);
+            return Self::from_points(vec![CurvePoint { x: 0.0, y }, CurvePoint { x: 255.0, y }]);
+        }
+
+        let mut wrapped = Vec::with_capacity(n + 4);
+        wrapped.extend(pts[n - 2..].iter().map(|p| CurvePoint { x: p.x - 255.0, y: p.y }));
+        wrapped.extend(pts.iter().cloned());
+        wrapped.extend(pts[..2].iter().map(|p| CurvePoint { x: p.x + 255.0, y: p.y }));
+        Self::from_points(wrapped)
+    }
+
+    fn from_points(pts: Vec<CurvePoint>) -> Self {
         let mut segments = Vec::with_capacity(pts.len().saturating_sub(1));
         for i in 0..pts.len().saturating_sub(1) {
             let p1 = pts[i].clone();
@@ -136,6 +168,86 @@ impl CurveRuntime {
     }
 }
 
+// Largest hue rotation of the hue-vs-hue curve, in degrees, at the top or bottom of the graph.
+const MAX_HUE_SHIFT: f32 = 60.0;
+
+// "Versus" curves: x is hue, luminance or saturation and y = 128 leaves the colour unchanged.
+#[derive(Clone)]
+pub struct HslCurvesRuntime {
+    pub hue_vs_hue: Option<CurveRuntime>,
+    pub hue_vs_sat: Option<CurveRuntime>,
+    pub hue_vs_lum: Option<CurveRuntime>,
+    pub lum_vs_sat: Option<CurveRuntime>,
+    pub sat_vs_sat: Option<CurveRuntime>,
+}
+
+// `None` for curves without points or that stay on the midline.
+fn versus_curve(points: &[CurvePointPayload], periodic: bool) -> Option<CurveRuntime> {
+    if points.iter().all(|p| (p.y - 128.0).abs() < 0.5) {
+        return None;
+    }
+    Some(if periodic {
+        CurveRuntime::periodic(points)
+    } else if points.len() < 2 {
+        CurveRuntime::from_points(vec![CurvePoint { x: 0.0, y: points[0].y }, CurvePoint { x: 255.0, y: points[0].y }])
+    } else {
+        CurveRuntime::from_payload(points)
+    })
+}
+
+impl HslCurvesRuntime {
+    pub fn from_payload(payload: &CurvesPayload) -> Option<Self> {
+        let curves = Self {
+            hue_vs_hue: versus_curve(&payload.hue_vs_hue, true),
+            hue_vs_sat: versus_curve(&payload.hue_vs_sat, true),
+            hue_vs_lum: versus_curve(&payload.hue_vs_lum, true),
+            lum_vs_sat: versus_curve(&payload.lum_vs_sat, false),
+            sat_vs_sat: versus_curve(&payload.sat_vs_sat, false),
+        };
+        let active = [&curves.hue_vs_hue, &curves.hue_vs_sat, &curves.hue_vs_lum, &curves.lum_vs_sat, &curves.sat_vs_sat]
+            .iter()
+            .any(|c| c.is_some());
+        active.then_some(curves)
+    }
+
+    // Works on display-referred sRGB like the tone curves, keeping luminance apart from the
+    // hue-vs-lum change.
+    pub fn apply(&self, color: [f32; 3]) -> [f32; 3] {
+        let [hue, sat, val] = crate::rgb_to_hsv(color);
+        if val <= 0.0001 {
+            return color;
+        }
+        // Curve value as roughly -1..1 around the y = 128 midline.
+        let offset = |curve: &Option<CurveRuntime>, x: f32| curve.as_ref().map_or(0.0, |c| (c.eval(x) * 255.0 - 128.0) / 128.0);
+        let luma = get_luma(color);
+        let h = hue / 360.0;
+        // Greys have no meaningful hue; same ramps as the HSL panel.
+        let hue_weight = crate::smoothstep(0.05, 0.20, sat);
+        let lum_weight = crate::smoothstep(0.0, 1.0, sat);
+
+        let hue_shift = offset(&self.hue_vs_hue, h) * MAX_HUE_SHIFT * hue_weight;
+        let sat_mult = (1.0 + offset(&self.hue_vs_sat, h) * hue_weight).max(0.0)
+            * (1.0 + offset(&self.lum_vs_sat, luma.clamp(0.0, 1.0))).max(0.0)
+            * (1.0 + offset(&self.sat_vs_sat, sat)).max(0.0);
+        let target_luma = luma * 2f32.powf(offset(&self.hue_vs_lum, h) * lum_weight);
+
+        let shifted = crate::hsv_to_rgb((hue + hue_shift).rem_euclid(360.0), (sat * sat_mult).clamp(0.0, 1.0), val);
+        let shifted_luma = get_luma(shifted);
+        if shifted_luma < 0.0001 {
+            let v = target_luma.clamp(0.0, 1.0);
+            return [v, v, v];
+        }
+        let scale = target_luma / shifted_luma;
+        let out = shifted.map(|c| c * scale);
+        let max_comp = out[0].max(out[1]).max(out[2]);
+        if max_comp > 1.0 {
+            out.map(|c| c / max_comp)
+        } else {
+            out
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct CurvesRuntime {
     pub luma: CurveRuntime,
@@ -143,6 +255,7 @@ pub struct CurvesRuntime {
     pub green: CurveRuntime,
     pub blue: CurveRuntime,
     pub rgb_curves_are_active: bool,
+    pub hsl: Option<HslCurvesRuntime>,
 }
 
 impl CurvesRuntime {
@@ -157,10 +270,19 @@ impl CurvesRuntime {
             green,
             blue,
             rgb_curves_are_active,
+            hsl: HslCurvesRuntime::from_payload(payload),
         }
     }
 
     pub fn apply_all(&self, color: [f32; 3]) -> [f32; 3] {
+        let color = self.apply_tone(color);
+        match &self.hsl {
+            Some(hsl) => hsl.apply(color),
+            None => color,
+        }
+    }
+
+    fn apply_tone(&self, color: [f32; 3]) -> [f32; 3] {
         if self.rgb_curves_are_active {
             let color_graded = [
                 self.red.eval(color[0]),
@@ -199,7 +321,7 @@ impl CurvesRuntime {
     }
 
     pub fn is_default(&self) -> bool {
-        self.luma.is_default() && !self.rgb_curves_are_active
+        self.luma.is_default() && !self.rgb_curves_are_active && self.hsl.is_none()
     }
 }
 
@@ -8,6 +8,7 @@ pub use adjustments::{
     ColorGradeSettings,
     ColorGradingValues,
     HslColorValues,
+    MonochromeValues,
     ToneMapper,
     ADJUSTMENT_SCALES,
 };
@@ -16,20 +17,49 @@ pub use payloads::{
     AdjustmentsPayload,
     AiEnvironmentMaskParameters,
     AiSubjectMaskParameters,
+    AnalysisMode,
+    AnalysisPayload,
+    BorderUnit,
+    BoundaryFill,
     BrushLinePayload,
     BrushMaskParameters,
     BrushPointPayload,
+    CanvasBackground,
+    CanvasPayload,
+    CaptionPayload,
+    ChromaSubsampling,
     ColorGradingPayload,
+    CompareMode,
+    ComparePayload,
     CropPayload,
     CurvesPayload,
+    ExportFormat,
+    ExportPayload,
+    EyeCorrectionPayload,
+    EyeKind,
     HueSatLumPayload,
     HslPanelPayload,
     LegacyMaskPayload,
     LinearMaskParameters,
     MaskAdjustmentsPayload,
     MaskDefinitionPayload,
+    MetadataPayload,
+    OutputColorSpace,
+    OutputSharpening,
+    OutputSizePayload,
+    PerspectivePayload,
     PreviewPayload,
+    PrintUnit,
     RadialMaskParameters,
+    ResampleFilter,
+    ResizeFit,
+    ResizeMode,
+    RetouchMode,
+    RetouchSpotPayload,
     SubMaskMode,
     SubMaskPayload,
+    TiffCompression,
+    WatermarkAnchor,
+    WatermarkBlend,
+    WatermarkPayload,
 };
@@ -3,9 +3,12 @@ use serde_json::Value;
 
 use super::adjustments::{
     AdjustmentValues,
+    CalibrationValues,
+    ChannelMixerValues,
     ColorGradeSettings,
     ColorGradingValues,
     HslColorValues,
+    MonochromeValues,
     ToneMapper,
 };
 
@@ -117,12 +120,19 @@ pub(crate) fn default_curve_points() -> Vec<CurvePointPayload> {
 }
 
 #[derive(Clone, Debug, Deserialize)]
-#[serde(default)]
+#[serde(default, rename_all = "camelCase")]
 pub struct CurvesPayload {
     pub luma: Vec<CurvePointPayload>,
     pub red: Vec<CurvePointPayload>,
     pub green: Vec<CurvePointPayload>,
     pub blue: Vec<CurvePointPayload>,
+    // Colour curves on the same 0-255 grid, flat at y = 128 for no change; empty means off.
+    // Hue runs from red at 0 round to red again at 255.
+    pub hue_vs_hue: Vec<CurvePointPayload>,
+    pub hue_vs_sat: Vec<CurvePointPayload>,
+    pub hue_vs_lum: Vec<CurvePointPayload>,
+    pub lum_vs_sat: Vec<CurvePointPayload>,
+    pub sat_vs_sat: Vec<CurvePointPayload>,
 }
 
 impl Default for CurvesPayload {
@@ -132,6 +142,11 @@ impl Default for CurvesPayload {
             red: default_curve_points(),
             green: default_curve_points(),
             blue: default_curve_points(),
+            hue_vs_hue: Vec::new(),
+            hue_vs_sat: Vec::new(),
+            hue_vs_lum: Vec::new(),
+            lum_vs_sat: Vec::new(),
+            sat_vs_sat: Vec::new(),
         }
     }
 }
@@ -250,6 +265,158 @@ impl HslPanelPayload {
     }
 }
 
+// Camera calibration, applied to the linear camera colours before any other adjustment.
+// Everything runs from -100 to 100.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CalibrationPayload {
+    pub red_hue: f32,
+    pub red_saturation: f32,
+    pub green_hue: f32,
+    pub green_saturation: f32,
+    pub blue_hue: f32,
+    pub blue_saturation: f32,
+    // Positive values push the shadows towards magenta, negative towards green.
+    pub shadow_tint: f32,
+}
+
+impl CalibrationPayload {
+    pub fn to_values(&self) -> CalibrationValues {
+        CalibrationValues {
+            hue: [self.red_hue, self.green_hue, self.blue_hue],
+            saturation: [self.red_saturation, self.green_saturation, self.blue_saturation],
+            shadow_tint: self.shadow_tint,
+        }
+    }
+}
+
+// Each output channel as percentages of the R, G and B inputs, applied right after calibration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ChannelMixerPayload {
+    pub red: [f32; 3],
+    pub green: [f32; 3],
+    pub blue: [f32; 3],
+}
+
+impl Default for ChannelMixerPayload {
+    fn default() -> Self {
+        Self {
+            red: [100.0, 0.0, 0.0],
+            green: [0.0, 100.0, 0.0],
+            blue: [0.0, 0.0, 100.0],
+        }
+    }
+}
+
+impl ChannelMixerPayload {
+    pub fn to_values(&self) -> ChannelMixerValues {
+        ChannelMixerValues {
+            matrix: [self.red, self.green, self.blue].map(|row| row.map(|v| v / 100.0)),
+        }
+    }
+}
+
+// Black & white conversion with a per-hue mixer, applied before tone mapping.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MonochromePayload {
+    pub enabled: bool,
+    // How much brighter (up to 100) or darker (down to -100) each colour range turns out.
+    pub reds: f32,
+    pub oranges: f32,
+    pub yellows: f32,
+    pub greens: f32,
+    pub aquas: f32,
+    pub blues: f32,
+    pub purples: f32,
+    pub magentas: f32,
+    // Channel mixer contributions in percent; the defaults are Rec. 709 luminance.
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    // Tint over the converted image: hue in degrees and strength from 0 to 100.
+    pub tone_hue: f32,
+    pub tone_saturation: f32,
+}
+
+impl Default for MonochromePayload {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            reds: 0.0,
+            oranges: 0.0,
+            yellows: 0.0,
+            greens: 0.0,
+            aquas: 0.0,
+            blues: 0.0,
+            purples: 0.0,
+            magentas: 0.0,
+            red: 21.26,
+            green: 71.52,
+            blue: 7.22,
+            tone_hue: 35.0,
+            tone_saturation: 0.0,
+        }
+    }
+}
+
+impl MonochromePayload {
+    pub fn to_values(&self) -> MonochromeValues {
+        MonochromeValues {
+            enabled: self.enabled,
+            mixer: [
+                self.reds,
+                self.oranges,
+                self.yellows,
+                self.greens,
+                self.aquas,
+                self.blues,
+                self.purples,
+                self.magentas,
+            ],
+            weights: [self.red, self.green, self.blue],
+            tone_hue: self.tone_hue,
+            tone_saturation: self.tone_saturation,
+        }
+    }
+}
+
+// Keystone correction, applied to the oriented image before rotation and crop.
+// `vertical`, `horizontal` and `aspect` run from -100 to 100; `scale` is a percentage.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PerspectivePayload {
+    // Positive values widen the top, straightening verticals that converge upwards.
+    pub vertical: f32,
+    // Positive values widen the right side.
+    pub horizontal: f32,
+    // Positive values stretch horizontally.
+    pub aspect: f32,
+    pub scale: f32,
+}
+
+impl Default for PerspectivePayload {
+    fn default() -> Self {
+        Self {
+            vertical: 0.0,
+            horizontal: 0.0,
+            aspect: 0.0,
+            scale: 100.0,
+        }
+    }
+}
+
+// What fills output areas that rotation or keystone correction leave without source pixels.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum BoundaryFill {
+    #[default]
+    None,
+    // The image mirrored across its edge, blurring with distance.
+    Extend,
+}
+
 #[derive(Clone, Default, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct CropPayload {
@@ -265,6 +432,415 @@ pub struct PreviewPayload {
     pub use_zoom: bool,
     pub roi: Option<CropPayload>,
     pub max_dimension: Option<u32>,
+    // Return histograms with the session render.
+    pub histogram: bool,
+    pub analysis: AnalysisPayload,
+    pub compare: ComparePayload,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CompareMode {
+    #[default]
+    None,
+    // Baseline before the split position, current adjustments after it.
+    Split,
+    // Baseline and current renders next to each other in one image.
+    SideBySide,
+}
+
+// Before/after comparison. Both sides share the current payload's geometry; only tone, colour,
+// detail and masks come from the baseline.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ComparePayload {
+    pub mode: CompareMode,
+    // Split position as a fraction of the width, or of the height when `horizontal`.
+    pub position: f32,
+    // Split along a horizontal line with the baseline on top, and stack side-by-side renders.
+    pub horizontal: bool,
+    // Adjustments JSON of a snapshot; default adjustments when absent.
+    pub baseline: Option<Value>,
+}
+
+impl Default for ComparePayload {
+    fn default() -> Self {
+        Self {
+            mode: CompareMode::None,
+            position: 0.5,
+            horizontal: false,
+            baseline: None,
+        }
+    }
+}
+
+// Exposure and focus aids drawn over the preview. Export ignores them.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AnalysisMode {
+    #[default]
+    None,
+    // Output pixels at exactly 255 drawn red, at exactly 0 blue. Only hard clipping in the
+    // finished 8-bit preview is reported; levels close to the limits are not flagged.
+    Clipping,
+    FocusPeaking,
+    // Camera-style exposure bands of the output level, over a grey image.
+    FalseColor,
+    Zebra,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AnalysisPayload {
+    pub mode: AnalysisMode,
+    // Clipping per colour channel rather than on luminance; a single clipped channel then shows
+    // in its own colour.
+    pub per_channel: bool,
+    // 0 to 100; higher marks softer edges too.
+    pub peaking_sensitivity: f32,
+    // Display luminance in percent above which zebra stripes are drawn.
+    pub zebra_threshold: f32,
+}
+
+impl Default for AnalysisPayload {
+    fn default() -> Self {
+        Self {
+            mode: AnalysisMode::None,
+            per_channel: false,
+            peaking_sensitivity: 50.0,
+            zebra_threshold: 95.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputColorSpace {
+    #[default]
+    Srgb,
+    DisplayP3,
+    AdobeRgb,
+    // 16-bit formats only.
+    ProPhoto,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Tiff,
+    Webp,
+    JpegXl,
+    // SDR JPEG base plus a gain map carrying the untone-mapped highlights.
+    UltraHdr,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ChromaSubsampling {
+    #[default]
+    Yuv444,
+    Yuv422,
+    Yuv420,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TiffCompression {
+    None,
+    #[default]
+    Deflate,
+    Lzw,
+}
+
+// Kernel for downscaled exports. Mitchell rings less; Lanczos3 is sharper.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ResampleFilter {
+    #[default]
+    Lanczos3,
+    Mitchell,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ResizeMode {
+    // Full resolution, or the legacy `max_dimension` box when one is passed.
+    #[default]
+    None,
+    Dimensions,
+    LongEdge,
+    ShortEdge,
+    Megapixels,
+    Percentage,
+    PrintSize,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ResizeFit {
+    // Inside the box, keeping the aspect ratio.
+    #[default]
+    Fit,
+    // Covers the box exactly, centre-cropping the overflow.
+    Fill,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PrintUnit {
+    #[default]
+    Inches,
+    Centimeters,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputSharpening {
+    #[default]
+    None,
+    Screen,
+    Matte,
+    Glossy,
+}
+
+// Final pixel size, measured on the cropped and rotated image.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OutputSizePayload {
+    pub mode: ResizeMode,
+    // `Dimensions` in pixels; `PrintSize` in `print_unit`.
+    pub width: f32,
+    pub height: f32,
+    pub fit: ResizeFit,
+    // Pixels for `LongEdge` / `ShortEdge`, megapixels or percent otherwise.
+    pub value: f32,
+    pub print_unit: PrintUnit,
+    // Written into the file for every mode; `PrintSize` also sizes by it.
+    pub dpi: u16,
+    pub dont_enlarge: bool,
+}
+
+impl Default for OutputSizePayload {
+    fn default() -> Self {
+        Self {
+            mode: ResizeMode::None,
+            width: 0.0,
+            height: 0.0,
+            fit: ResizeFit::Fit,
+            value: 0.0,
+            print_unit: PrintUnit::Inches,
+            dpi: 72,
+            dont_enlarge: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WatermarkAnchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    #[default]
+    BottomRight,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WatermarkBlend {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    SoftLight,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WatermarkPayload {
+    // PNG/JPEG data URL; used instead of `text` when present.
+    pub image_data_base64: Option<String>,
+    pub text: Option<String>,
+    // "#RRGGBB"
+    pub text_color: String,
+    // TTF/OTF data URL; falls back to the system sans-serif.
+    pub font_data_base64: Option<String>,
+    pub anchor: WatermarkAnchor,
+    // Watermark width as a fraction of the image width.
+    pub scale: f32,
+    // Distance from the anchored edges as a fraction of the shorter image side.
+    pub margin: f32,
+    pub opacity: f32,
+    pub blend: WatermarkBlend,
+}
+
+impl Default for WatermarkPayload {
+    fn default() -> Self {
+        Self {
+            image_data_base64: None,
+            text: None,
+            text_color: "#FFFFFF".to_string(),
+            font_data_base64: None,
+            anchor: WatermarkAnchor::default(),
+            scale: 0.2,
+            margin: 0.03,
+            opacity: 0.8,
+            blend: WatermarkBlend::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum BorderUnit {
+    #[default]
+    Pixels,
+    // Percent of the photo's longer side.
+    Percent,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CanvasBackground {
+    #[default]
+    Color,
+    // A blurred, enlarged copy of the photo behind it.
+    Blur,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CaptionPayload {
+    pub text: Option<String>,
+    // Adds a line like "X-T5 · XF 33mm F1.4 · 1/250 s · f/2.8 · ISO 200 · 33 mm".
+    pub show_exif: bool,
+    // "#RRGGBB"
+    pub text_color: String,
+    // TTF/OTF data URL; falls back to the system sans-serif.
+    pub font_data_base64: Option<String>,
+    // Line height as a fraction of the photo's longer side.
+    pub size: f32,
+}
+
+impl Default for CaptionPayload {
+    fn default() -> Self {
+        Self {
+            text: None,
+            show_exif: false,
+            text_color: "#404040".to_string(),
+            font_data_base64: None,
+            size: 0.02,
+        }
+    }
+}
+
+// Space added around the cropped photo at export. Per-side values override `border`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CanvasPayload {
+    pub unit: BorderUnit,
+    pub border: f32,
+    pub top: Option<f32>,
+    pub right: Option<f32>,
+    pub bottom: Option<f32>,
+    pub left: Option<f32>,
+    pub background: CanvasBackground,
+    // "#RRGGBB"
+    pub color: String,
+    // Pads the bordered photo evenly out to this width:height ratio (e.g. 4:5); 0 disables.
+    pub aspect_width: f32,
+    pub aspect_height: f32,
+    // Placed under the photo, inside the bottom border.
+    pub caption: Option<CaptionPayload>,
+}
+
+impl Default for CanvasPayload {
+    fn default() -> Self {
+        Self {
+            unit: BorderUnit::default(),
+            border: 0.0,
+            top: None,
+            right: None,
+            bottom: None,
+            left: None,
+            background: CanvasBackground::default(),
+            color: "#FFFFFF".to_string(),
+            aspect_width: 0.0,
+            aspect_height: 0.0,
+            caption: None,
+        }
+    }
+}
+
+fn default_export_bit_depth() -> u8 {
+    8
+}
+
+// What capture metadata an export keeps, plus fields supplied at export time.
+// Injected fields override the ones read from the RAW.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MetadataPayload {
+    // Drops everything but copyright, creator and description.
+    pub copyright_only: bool,
+    pub strip_location: bool,
+    // Camera and lens serial numbers and the owner name.
+    pub strip_identifiers: bool,
+    pub copyright: Option<String>,
+    pub creator: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExportPayload {
+    pub color_space: OutputColorSpace,
+    pub format: ExportFormat,
+    // 8 or 16 for PNG/TIFF; 32 selects float32 TIFF. JPEG is always 8, ProPhoto at least 16.
+    // Float32 TIFF holds the same display-encoded 0..1 values as the integer depths, not linear light.
+    #[serde(default = "default_export_bit_depth")]
+    pub bit_depth: u8,
+    pub tiff_compression: TiffCompression,
+    // 1..=100 for JPEG and lossy WebP; `None` keeps the engine default. Ignored for JPEG XL.
+    pub quality: Option<u8>,
+    pub chroma_subsampling: ChromaSubsampling,
+    // WebP only; JPEG XL output is always lossless.
+    pub lossless: bool,
+    pub metadata: MetadataPayload,
+    pub resample_filter: ResampleFilter,
+    pub size: OutputSizePayload,
+    pub sharpening: OutputSharpening,
+    pub watermark: Option<WatermarkPayload>,
+    pub canvas: Option<CanvasPayload>,
+}
+
+impl Default for ExportPayload {
+    fn default() -> Self {
+        Self {
+            color_space: OutputColorSpace::default(),
+            format: ExportFormat::default(),
+            bit_depth: default_export_bit_depth(),
+            tiff_compression: TiffCompression::default(),
+            quality: None,
+            chroma_subsampling: ChromaSubsampling::default(),
+            lossless: false,
+            metadata: MetadataPayload::default(),
+            resample_filter: ResampleFilter::default(),
+            size: OutputSizePayload::default(),
+            sharpening: OutputSharpening::default(),
+            watermark: None,
+            canvas: None,
+        }
+    }
 }
 
 #[derive(Clone, Default, Deserialize)]
@@ -280,6 +856,8 @@ pub struct AdjustmentsPayload {
     pub orientation_steps: u8,
     #[serde(default)]
     pub crop: Option<CropPayload>,
+    pub perspective: PerspectivePayload,
+    pub boundary_fill: BoundaryFill,
     pub exposure: f32,
     pub brightness: f32,
     pub contrast: f32,
@@ -313,8 +891,18 @@ pub struct AdjustmentsPayload {
     #[serde(default)]
     pub hsl: HslPanelPayload,
     #[serde(default)]
+    pub monochrome: MonochromePayload,
+    #[serde(default)]
+    pub calibration: CalibrationPayload,
+    #[serde(default)]
+    pub channel_mixer: ChannelMixerPayload,
+    #[serde(default)]
     pub preview: PreviewPayload,
+    #[serde(default)]
+    pub export: ExportPayload,
     pub masks: Vec<Value>,
+    pub retouch: Vec<RetouchSpotPayload>,
+    pub eyes: Vec<EyeCorrectionPayload>,
 }
 
 impl AdjustmentsPayload {
@@ -347,6 +935,85 @@ impl AdjustmentsPayload {
             tone_mapper: self.tone_mapper,
             color_grading: self.color_grading.to_values(),
             hsl: self.hsl.to_values(),
+            monochrome: self.monochrome.to_values(),
+            calibration: self.calibration.to_values(),
+            channel_mixer: self.channel_mixer.to_values(),
+            color_matrix: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RetouchMode {
+    // Copies texture from the source and blends its colour into the surroundings.
+    #[default]
+    Heal,
+    Clone,
+}
+
+// Positions are normalized to the image as shot, before orientation, keystone, rotation and crop
+// edits. `radius` is a fraction of the shorter side; `feather` is the soft fraction of the radius.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RetouchSpotPayload {
+    pub mode: RetouchMode,
+    pub x: f32,
+    pub y: f32,
+    pub source_x: f32,
+    pub source_y: f32,
+    pub radius: f32,
+    pub feather: f32,
+    pub opacity: f32,
+}
+
+impl Default for RetouchSpotPayload {
+    fn default() -> Self {
+        Self {
+            mode: RetouchMode::Heal,
+            x: 0.5,
+            y: 0.5,
+            source_x: 0.5,
+            source_y: 0.5,
+            radius: 0.02,
+            feather: 0.5,
+            opacity: 100.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum EyeKind {
+    #[default]
+    Red,
+    // Green, yellow or blue tapetum reflection in animal eyes.
+    Pet,
+}
+
+// Geometry follows radial masks: the centre is normalized to the output frame and `radius` to
+// its shorter side.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EyeCorrectionPayload {
+    pub kind: EyeKind,
+    pub center_x: f32,
+    pub center_y: f32,
+    pub radius: f32,
+    pub feather: f32,
+    // 0 leaves the pupil at the brightness of its darkest channel; 100 makes it much darker.
+    pub darken: f32,
+}
+
+impl Default for EyeCorrectionPayload {
+    fn default() -> Self {
+        Self {
+            kind: EyeKind::Red,
+            center_x: 0.5,
+            center_y: 0.5,
+            radius: 0.01,
+            feather: 0.3,
+            darken: 50.0,
         }
     }
 }
@@ -416,6 +1083,10 @@ pub struct MaskAdjustmentsPayload {
     pub color_grading: ColorGradingPayload,
     #[serde(default)]
     pub hsl: HslPanelPayload,
+    #[serde(default)]
+    pub calibration: CalibrationPayload,
+    #[serde(default)]
+    pub channel_mixer: ChannelMixerPayload,
 }
 
 impl MaskAdjustmentsPayload {
@@ -443,6 +1114,8 @@ impl MaskAdjustmentsPayload {
             chromatic_aberration_blue_yellow: self.chromatic_aberration_blue_yellow,
             color_grading: self.color_grading.to_values(),
             hsl: self.hsl.to_values(),
+            calibration: self.calibration.to_values(),
+            channel_mixer: self.channel_mixer.to_values(),
             ..AdjustmentValues::default()
         }
     }
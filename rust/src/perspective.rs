@@ -0,0 +1,101 @@
+// Keystone correction as a planar homography. Both the virtual sampler (`TransformState`) and the
+// physical preview path use the same matrix, so masks and crops line up between them.
+
+use crate::model::PerspectivePayload;
+
+// Projective strength at +-100; 0.4 lets the far edge grow to about 1.7x.
+const KEYSTONE_STRENGTH: f32 = 0.4;
+// Aspect +-100 stretches one axis by up to 1.41x relative to the other.
+const ASPECT_STRENGTH: f32 = 0.25;
+
+type Matrix = [[f32; 3]; 3];
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0f32; 3]; 3];
+    for (r, row) in out.iter_mut().enumerate() {
+        for (c, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[r][k] * b[k][c]).sum();
+        }
+    }
+    out
+}
+
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let cof = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let adj = [
+        [cof(1, 2, 1, 2), -cof(0, 2, 1, 2), cof(0, 1, 1, 2)],
+        [-cof(1, 2, 0, 2), cof(0, 2, 0, 2), -cof(0, 1, 0, 2)],
+        [cof(1, 2, 0, 1), -cof(0, 2, 0, 1), cof(0, 1, 0, 1)],
+    ];
+    let det = m[0][0] * adj[0][0] + m[0][1] * adj[1][0] + m[0][2] * adj[2][0];
+    if det.abs() < 1.0e-12 {
+        return None;
+    }
+    Some(adj.map(|row| row.map(|v| v / det)))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Homography {
+    // Corrected pixel -> uncorrected pixel.
+    inverse: Matrix,
+    // Uncorrected pixel -> corrected pixel.
+    forward: Matrix,
+}
+
+impl Homography {
+    // `width` x `height` is the oriented image; the correction pivots on its centre. `None` when
+    // the settings are neutral.
+    pub(crate) fn keystone(payload: &PerspectivePayload, width: u32, height: u32) -> Option<Self> {
+        let finite = |v: f32, default: f32| if v.is_finite() { v } else { default };
+        let vertical = finite(payload.vertical, 0.0).clamp(-100.0, 100.0) / 100.0;
+        let horizontal = finite(payload.horizontal, 0.0).clamp(-100.0, 100.0) / 100.0;
+        let aspect = finite(payload.aspect, 0.0).clamp(-100.0, 100.0) / 100.0;
+        let scale = finite(payload.scale, 100.0).clamp(50.0, 150.0) / 100.0;
+        if vertical == 0.0 && horizontal == 0.0 && aspect == 0.0 && scale == 1.0 {
+            return None;
+        }
+
+        // Normalized coordinates: centred, with the longer half-side as the unit.
+        let cx = (width as f32 - 1.0) / 2.0;
+        let cy = (height as f32 - 1.0) / 2.0;
+        let r = (width.max(height) as f32 / 2.0).max(1.0);
+        let to_unit = [[1.0 / r, 0.0, -cx / r], [0.0, 1.0 / r, -cy / r], [0.0, 0.0, 1.0]];
+        let from_unit = [[r, 0.0, cx], [0.0, r, cy], [0.0, 0.0, 1.0]];
+
+        // w = 1 - k*h*x + k*v*y. The side where w < 1 is enlarged, so positive `vertical`
+        // widens the top.
+        let keystone = [
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [-horizontal * KEYSTONE_STRENGTH, vertical * KEYSTONE_STRENGTH, 1.0],
+        ];
+        let stretch = 2f32.powf(aspect * ASPECT_STRENGTH);
+        let resize = [[scale * stretch, 0.0, 0.0], [0.0, scale / stretch, 0.0], [0.0, 0.0, 1.0]];
+
+        let forward = mul(&from_unit, &mul(&resize, &mul(&keystone, &to_unit)));
+        let inverse = invert(&forward)?;
+        Some(Self { inverse, forward })
+    }
+
+    #[inline]
+    fn project(m: &Matrix, x: f32, y: f32) -> Option<(f32, f32)> {
+        let w = m[2][0] * x + m[2][1] * y + m[2][2];
+        // Points on or behind the horizon line have no image.
+        if w <= 1.0e-6 {
+            return None;
+        }
+        Some(((m[0][0] * x + m[0][1] * y + m[0][2]) / w, (m[1][0] * x + m[1][1] * y + m[1][2]) / w))
+    }
+
+    // Corrected position -> position in the uncorrected image.
+    #[inline]
+    pub(crate) fn unwarp(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        Self::project(&self.inverse, x, y)
+    }
+
+    // Uncorrected position -> corrected position.
+    #[inline]
+    pub(crate) fn warp(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        Self::project(&self.forward, x, y)
+    }
+}
@@ -0,0 +1,195 @@
+// Separable resampling for downscaling and higher-order interpolation for rotations.
+// The decoded RAW data is scene-linear, so filtering it directly is filtering in linear light.
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+
+use crate::model::ResampleFilter;
+use crate::try_alloc_vec;
+
+// Output rows per parallel band; each band filters only the source rows it needs.
+const BAND_ROWS: usize = 32;
+
+impl ResampleFilter {
+    fn support(self) -> f32 {
+        match self {
+            ResampleFilter::Lanczos3 => 3.0,
+            ResampleFilter::Mitchell => 2.0,
+        }
+    }
+
+    fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            ResampleFilter::Lanczos3 => {
+                if x < 1.0e-6 {
+                    1.0
+                } else if x < 3.0 {
+                    let px = std::f32::consts::PI * x;
+                    3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            }
+            // B = C = 1/3
+            ResampleFilter::Mitchell => {
+                if x < 1.0 {
+                    (7.0 * x * x * x - 12.0 * x * x + 16.0 / 3.0) / 6.0
+                } else if x < 2.0 {
+                    (-7.0 / 3.0 * x * x * x + 12.0 * x * x - 20.0 * x + 32.0 / 3.0) / 6.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+// Normalized taps for every output position along one axis.
+struct Taps {
+    start: Vec<usize>,
+    weights: Vec<Vec<f32>>,
+}
+
+impl Taps {
+    fn new(src_len: u32, dst_len: u32, filter: ResampleFilter) -> Self {
+        let scale = src_len as f32 / dst_len as f32;
+        // Widen the kernel when shrinking so it also acts as the anti-aliasing low-pass.
+        let stretch = scale.max(1.0);
+        let radius = filter.support() * stretch;
+        let mut start = Vec::with_capacity(dst_len as usize);
+        let mut weights = Vec::with_capacity(dst_len as usize);
+        for i in 0..dst_len {
+            let center = (i as f32 + 0.5) * scale - 0.5;
+            let lo = ((center - radius).ceil().max(0.0)) as usize;
+            let hi = ((center + radius).floor() as i64).min(src_len as i64 - 1).max(lo as i64) as usize;
+            let mut w: Vec<f32> = (lo..=hi).map(|s| filter.weight((s as f32 - center) / stretch)).collect();
+            let sum: f32 = w.iter().sum();
+            if sum.abs() > 1.0e-6 {
+                w.iter_mut().for_each(|v| *v /= sum);
+            } else {
+                w = vec![0.0; hi - lo + 1];
+                w[((center.round().max(0.0) as usize).clamp(lo, hi)) - lo] = 1.0;
+            }
+            start.push(lo);
+            weights.push(w);
+        }
+        Self { start, weights }
+    }
+
+    fn range(&self, from: usize, to: usize) -> (usize, usize) {
+        let lo = self.start[from];
+        let hi = (from..to).map(|i| self.start[i] + self.weights[i].len()).max().unwrap_or(lo);
+        (lo, hi)
+    }
+}
+
+// Resizes an interleaved RGB u16 buffer. Negative lobes are clamped at black and white.
+pub(crate) fn resize_rgb16(
+    src: &[u16],
+    src_w: u32,
+    src_h: u32,
+    dst_w: u32,
+    dst_h: u32,
+    filter: ResampleFilter,
+) -> Result<Vec<u16>> {
+    let len = (dst_w as usize)
+        .checked_mul(dst_h as usize)
+        .and_then(|v| v.checked_mul(3))
+        .context("Resample buffer size overflow")?;
+    let mut out = try_alloc_vec(len, 0u16)?;
+    let h_taps = Taps::new(src_w, dst_w, filter);
+    let v_taps = Taps::new(src_h, dst_h, filter);
+    let src_row = src_w as usize * 3;
+    let dst_row = dst_w as usize * 3;
+
+    out.par_chunks_mut(dst_row * BAND_ROWS).enumerate().for_each(|(band, chunk)| {
+        let y0 = band * BAND_ROWS;
+        let rows = chunk.len() / dst_row;
+        let (lo, hi) = v_taps.range(y0, y0 + rows);
+
+        // Horizontal pass over just the source rows this band touches.
+        let mut horizontal = vec![0f32; (hi - lo) * dst_row];
+        for (sy, line) in (lo..hi).zip(horizontal.chunks_exact_mut(dst_row)) {
+            let row = &src[sy * src_row..(sy + 1) * src_row];
+            for (x, px) in line.chunks_exact_mut(3).enumerate() {
+                let mut acc = [0f32; 3];
+                for (k, &w) in h_taps.weights[x].iter().enumerate() {
+                    let idx = (h_taps.start[x] + k) * 3;
+                    acc[0] += row[idx] as f32 * w;
+                    acc[1] += row[idx + 1] as f32 * w;
+                    acc[2] += row[idx + 2] as f32 * w;
+                }
+                px.copy_from_slice(&acc);
+            }
+        }
+
+        for (r, out_row) in chunk.chunks_exact_mut(dst_row).enumerate() {
+            let y = y0 + r;
+            let first = v_taps.start[y] - lo;
+            for (i, dst) in out_row.iter_mut().enumerate() {
+                let mut acc = 0f32;
+                for (k, &w) in v_taps.weights[y].iter().enumerate() {
+                    acc += horizontal[(first + k) * dst_row + i] * w;
+                }
+                *dst = acc.round().clamp(0.0, 65535.0) as u16;
+            }
+        }
+    });
+    Ok(out)
+}
+
+// Catmull-Rom weights for the four taps around a sample at fractional offset `t`.
+#[inline]
+fn catmull_rom(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+// Bicubic (Catmull-Rom) interpolation for rotations and fractional crops; `pixel` is only
+// called with in-bounds coordinates. Integer positions return the source pixel unchanged.
+#[inline]
+pub(crate) fn sample_bicubic(
+    width: u32,
+    height: u32,
+    x: f32,
+    y: f32,
+    pixel: impl Fn(u32, u32) -> [f32; 3],
+) -> [f32; 3] {
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let tx = x - x0;
+    let ty = y - y0;
+    let (ix, iy) = (x0 as i64, y0 as i64);
+    let max_x = width as i64 - 1;
+    let max_y = height as i64 - 1;
+    if tx < 1.0e-4 && ty < 1.0e-4 {
+        return pixel(ix.clamp(0, max_x) as u32, iy.clamp(0, max_y) as u32);
+    }
+
+    let wx = catmull_rom(tx);
+    let wy = catmull_rom(ty);
+    let mut out = [0f32; 3];
+    for (j, &wyj) in wy.iter().enumerate() {
+        let sy = (iy - 1 + j as i64).clamp(0, max_y) as u32;
+        let mut row = [0f32; 3];
+        for (i, &wxi) in wx.iter().enumerate() {
+            let sx = (ix - 1 + i as i64).clamp(0, max_x) as u32;
+            let p = pixel(sx, sy);
+            row[0] += p[0] * wxi;
+            row[1] += p[1] * wxi;
+            row[2] += p[2] * wxi;
+        }
+        out[0] += row[0] * wyj;
+        out[1] += row[1] * wyj;
+        out[2] += row[2] * wyj;
+    }
+    // Overshoot below black has no meaning in linear light.
+    [out[0].max(0.0), out[1].max(0.0), out[2].max(0.0)]
+}
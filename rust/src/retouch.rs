@@ -0,0 +1,230 @@
+// Heal and clone spots. Spots are stored against the image as shot, so they stay on the content
+// when orientation, keystone, rotation or crop change. Each spot is resolved once into a patch
+// of finished source pixels; the physical preview path writes the patches into its buffer and the
+// virtual exporter looks them up while sampling, so both agree.
+
+use crate::model::{RetouchMode, RetouchSpotPayload};
+
+// Grids at or below this many pixels per side are solved directly.
+const DIRECT_SOLVE_SIZE: u32 = 24;
+const SOR_OMEGA: f32 = 1.8;
+// Relaxation sweeps on each level after the coarser solution is carried up.
+const REFINE_SWEEPS: usize = 24;
+
+struct Patch {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    rgb: Vec<f32>,
+}
+
+impl Patch {
+    fn get(&self, x: u32, y: u32) -> Option<[f32; 3]> {
+        if x < self.x || y < self.y || x >= self.x + self.width || y >= self.y + self.height {
+            return None;
+        }
+        let i = (((y - self.y) * self.width + (x - self.x)) * 3) as usize;
+        Some([self.rgb[i], self.rgb[i + 1], self.rgb[i + 2]])
+    }
+}
+
+pub(crate) struct Retouch {
+    patches: Vec<Patch>,
+}
+
+// Later patches already contain the earlier ones, so the last match wins.
+fn lookup(patches: &[Patch], x: u32, y: u32) -> Option<[f32; 3]> {
+    patches.iter().rev().find_map(|p| p.get(x, y))
+}
+
+// Fills the unknown cells of `values` with the membrane (harmonic interpolation) between the known
+// ones. Solves coarse-to-fine so large spots converge without thousands of sweeps.
+fn solve_membrane(values: &mut [[f32; 3]], known: &[bool], width: u32, height: u32) {
+    let (w, h) = (width as usize, height as usize);
+    let sweeps = if width.max(height) <= DIRECT_SOLVE_SIZE {
+        let mut mean = [0f32; 3];
+        let mut count = 0f32;
+        for (v, _) in values.iter().zip(known).filter(|(_, k)| **k) {
+            (0..3).for_each(|c| mean[c] += v[c]);
+            count += 1.0;
+        }
+        let mean = mean.map(|m| m / count.max(1.0));
+        for (v, _) in values.iter_mut().zip(known).filter(|(_, k)| !**k) {
+            *v = mean;
+        }
+        (4 * width.max(height)) as usize
+    } else {
+        let (cw, ch) = ((width / 2).max(1), (height / 2).max(1));
+        let mut coarse = vec![[0f32; 3]; (cw * ch) as usize];
+        let mut coarse_known = vec![false; coarse.len()];
+        for cy in 0..ch as usize {
+            for cx in 0..cw as usize {
+                let mut acc = [0f32; 3];
+                let mut count = 0f32;
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let (x, y) = ((2 * cx + dx).min(w - 1), (2 * cy + dy).min(h - 1));
+                    if known[y * w + x] {
+                        (0..3).for_each(|c| acc[c] += values[y * w + x][c]);
+                        count += 1.0;
+                    }
+                }
+                if count > 0.0 {
+                    coarse[cy * cw as usize + cx] = acc.map(|v| v / count);
+                    coarse_known[cy * cw as usize + cx] = true;
+                }
+            }
+        }
+        solve_membrane(&mut coarse, &coarse_known, cw, ch);
+        for y in 0..h {
+            for x in 0..w {
+                if !known[y * w + x] {
+                    let (cx, cy) = ((x / 2).min(cw as usize - 1), (y / 2).min(ch as usize - 1));
+                    values[y * w + x] = coarse[cy * cw as usize + cx];
+                }
+            }
+        }
+        REFINE_SWEEPS
+    };
+
+    for _ in 0..sweeps {
+        for y in 0..h {
+            for x in 0..w {
+                let i = y * w + x;
+                if known[i] {
+                    continue;
+                }
+                let mut acc = [0f32; 3];
+                let mut count = 0f32;
+                let neighbours = [
+                    (x > 0).then(|| i - 1),
+                    (x + 1 < w).then(|| i + 1),
+                    (y > 0).then(|| i - w),
+                    (y + 1 < h).then(|| i + w),
+                ];
+                for n in neighbours.into_iter().flatten() {
+                    (0..3).for_each(|c| acc[c] += values[n][c]);
+                    count += 1.0;
+                }
+                for c in 0..3 {
+                    let target = acc[c] / count.max(1.0);
+                    values[i][c] += SOR_OMEGA * (target - values[i][c]);
+                }
+            }
+        }
+    }
+}
+
+impl Retouch {
+    // `width` x `height` is the pixel grid the patches are built on. `to_pixel` maps a normalized
+    // position in the image as shot onto that grid, and `pixel` reads it.
+    pub(crate) fn new(
+        spots: &[RetouchSpotPayload],
+        width: u32,
+        height: u32,
+        to_pixel: impl Fn(f32, f32) -> (f32, f32),
+        pixel: impl Fn(u32, u32) -> [f32; 3],
+    ) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let mut patches: Vec<Patch> = Vec::new();
+        // Spot radii are relative to the shorter side, which orientation doesn't change.
+        let base = width.min(height) as f32;
+        let (max_x, max_y) = ((width - 1) as f32, (height - 1) as f32);
+
+        for spot in spots {
+            let values = [spot.x, spot.y, spot.source_x, spot.source_y, spot.radius, spot.feather, spot.opacity];
+            if values.iter().any(|v| !v.is_finite()) {
+                continue;
+            }
+            let opacity = (spot.opacity / 100.0).clamp(0.0, 1.0);
+            let radius = (spot.radius * base).max(1.0);
+            if opacity <= 0.0 {
+                continue;
+            }
+            let (dx, dy) = to_pixel(spot.x.clamp(0.0, 1.0), spot.y.clamp(0.0, 1.0));
+            let (sx, sy) = to_pixel(spot.source_x.clamp(0.0, 1.0), spot.source_y.clamp(0.0, 1.0));
+            let inner = radius * (1.0 - spot.feather.clamp(0.0, 1.0));
+
+            // One pixel of margin around the disc keeps a known ring for the heal solve.
+            let x0 = (dx - radius - 1.0).floor().clamp(0.0, max_x) as u32;
+            let y0 = (dy - radius - 1.0).floor().clamp(0.0, max_y) as u32;
+            let x1 = (dx + radius + 1.0).ceil().clamp(0.0, max_x) as u32;
+            let y1 = (dy + radius + 1.0).ceil().clamp(0.0, max_y) as u32;
+            let (pw, ph) = (x1 - x0 + 1, y1 - y0 + 1);
+
+            let current = |x: u32, y: u32| lookup(&patches, x, y).unwrap_or_else(|| pixel(x, y));
+            let source_at = |x: f32, y: f32| {
+                let (fx, fy) = (x.clamp(0.0, max_x), y.clamp(0.0, max_y));
+                let (ix, iy) = (fx as u32, fy as u32);
+                let (jx, jy) = ((ix + 1).min(width - 1), (iy + 1).min(height - 1));
+                let (tx, ty) = (fx - ix as f32, fy - iy as f32);
+                let (a, b, c, d) = (current(ix, iy), current(jx, iy), current(ix, jy), current(jx, jy));
+                [0, 1, 2].map(|i| {
+                    let top = a[i] + (b[i] - a[i]) * tx;
+                    top + (c[i] + (d[i] - c[i]) * tx - top) * ty
+                })
+            };
+
+            let len = (pw * ph) as usize;
+            let mut under = Vec::with_capacity(len);
+            let mut copied = Vec::with_capacity(len);
+            let mut alpha = Vec::with_capacity(len);
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    under.push(current(x, y));
+                    copied.push(source_at(x as f32 - dx + sx, y as f32 - dy + sy));
+                    let dist = (x as f32 - dx).hypot(y as f32 - dy);
+                    let a = if dist <= inner {
+                        1.0
+                    } else if dist >= radius {
+                        0.0
+                    } else {
+                        crate::smoothstep(radius, inner, dist)
+                    };
+                    alpha.push(a * opacity);
+                }
+            }
+
+            let replaced = match spot.mode {
+                RetouchMode::Clone => copied,
+                RetouchMode::Heal => {
+                    // Gradient-domain heal: keep the copied texture, but bend its colour so it
+                    // meets the destination at the disc's rim.
+                    let known: Vec<bool> = alpha.iter().map(|a| *a <= 0.0).collect();
+                    let mut offset: Vec<[f32; 3]> =
+                        under.iter().zip(&copied).map(|(u, c)| [u[0] - c[0], u[1] - c[1], u[2] - c[2]]).collect();
+                    solve_membrane(&mut offset, &known, pw, ph);
+                    copied.iter().zip(&offset).map(|(c, o)| [c[0] + o[0], c[1] + o[1], c[2] + o[2]]).collect()
+                }
+            };
+
+            let mut rgb = Vec::with_capacity(len * 3);
+            for ((u, r), a) in under.iter().zip(&replaced).zip(&alpha) {
+                rgb.extend((0..3).map(|c| u[c] + (r[c] - u[c]) * a));
+            }
+            patches.push(Patch { x: x0, y: y0, width: pw, height: ph, rgb });
+        }
+
+        (!patches.is_empty()).then_some(Self { patches })
+    }
+
+    // Retouched value of pixel (x, y), if a spot covers it.
+    #[inline]
+    pub(crate) fn get(&self, x: u32, y: u32) -> Option<[f32; 3]> {
+        lookup(&self.patches, x, y)
+    }
+
+    // Writes every patch into an interleaved RGB buffer of the grid the spots were built on.
+    pub(crate) fn apply(&self, rgb: &mut [f32], width: u32) {
+        for patch in &self.patches {
+            for row in 0..patch.height {
+                let dst = (((patch.y + row) * width + patch.x) * 3) as usize;
+                let src = (row * patch.width * 3) as usize;
+                let n = (patch.width * 3) as usize;
+                rgb[dst..dst + n].copy_from_slice(&patch.rgb[src..src + n]);
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+// Output sharpening, applied to the display-encoded rows after resizing and before the watermark,
+// gain map and output transform. Rows stream through with a few rows of look-behind and
+// look-ahead so the tiled exporter can feed it bands.
+
+use crate::get_luma;
+use crate::model::OutputSharpening;
+
+pub(crate) struct OutputSharpener {
+    width: usize,
+    radius: usize,
+    kernel: Vec<f32>,
+    amount: f32,
+    // Rows kept for context plus rows not yet emitted, oldest first.
+    rows: Vec<f32>,
+    // Index (in the image) of the first row held in `rows`.
+    first_row: usize,
+    // Rows emitted so far.
+    emitted: usize,
+}
+
+impl OutputSharpener {
+    pub(crate) fn new(mode: OutputSharpening, width: u32) -> Option<Self> {
+        // (sigma, amount): prints lose more micro-contrast than screens, matte paper most of all.
+        let (sigma, amount) = match mode {
+            OutputSharpening::None => return None,
+            OutputSharpening::Screen => (0.6f32, 0.45f32),
+            OutputSharpening::Glossy => (0.8, 0.7),
+            OutputSharpening::Matte => (1.0, 1.0),
+        };
+        let radius = (sigma * 3.0).ceil() as usize;
+        let mut kernel: Vec<f32> = (0..=2 * radius)
+            .map(|i| {
+                let d = i as f32 - radius as f32;
+                (-d * d / (2.0 * sigma * sigma)).exp()
+            })
+            .collect();
+        let sum: f32 = kernel.iter().sum();
+        kernel.iter_mut().for_each(|k| *k /= sum);
+        Some(Self {
+            width: width as usize,
+            radius,
+            kernel,
+            amount,
+            rows: Vec::new(),
+            first_row: 0,
+            emitted: 0,
+        })
+    }
+
+    fn row_len(&self) -> usize {
+        self.width * 3
+    }
+
+    // Takes the next band of rows; returns the rows that now have enough context, and the
+    // image row they start at.
+    pub(crate) fn push(&mut self, band: &[f32]) -> (Vec<f32>, u32) {
+        self.rows.extend_from_slice(band);
+        let received = self.first_row + self.rows.len() / self.row_len();
+        let ready = received.saturating_sub(self.radius);
+        self.emit(ready, received)
+    }
+
+    // Flushes the remaining rows, treating the bottom edge as repeated.
+    pub(crate) fn finish(&mut self) -> (Vec<f32>, u32) {
+        let received = self.first_row + self.rows.len() / self.row_len();
+        self.emit(received, received)
+    }
+
+    fn emit(&mut self, ready: usize, received: usize) -> (Vec<f32>, u32) {
+        let start = self.emitted;
+        if ready <= start {
+            return (Vec::new(), start as u32);
+        }
+        let row_len = self.row_len();
+        let held = received - self.first_row;
+        let luma: Vec<f32> = self
+            .rows
+            .chunks_exact(3)
+            .map(|p| get_luma([p[0], p[1], p[2]]))
+            .collect();
+
+        // Horizontal blur of the luma for every held row, then vertical for the emitted ones.
+        let mut horizontal = vec![0f32; held * self.width];
+        for (src, dst) in luma.chunks_exact(self.width).zip(horizontal.chunks_exact_mut(self.width)) {
+            for (x, out) in dst.iter_mut().enumerate() {
+                *out = self
+                    .kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| {
+                        let sx = (x + k).saturating_sub(self.radius).min(self.width - 1);
+                        src[sx] * w
+                    })
+                    .sum();
+            }
+        }
+
+        let mut out = Vec::with_capacity((ready - start) * row_len);
+        for y in start..ready {
+            let local = y - self.first_row;
+            for x in 0..self.width {
+                let blurred: f32 = self
+                    .kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| {
+                        // Only clamps at the image edges; interior taps are always held.
+                        let sy = (y + k).saturating_sub(self.radius).clamp(self.first_row, received - 1);
+                        horizontal[(sy - self.first_row) * self.width + x] * w
+                    })
+                    .sum();
+                let detail = (luma[local * self.width + x] - blurred) * self.amount;
+                let idx = local * row_len + x * 3;
+                for c in 0..3 {
+                    // Wide-gamut values beyond the display range pass through unsharpened.
+                    let v = self.rows[idx + c];
+                    let clipped = v.clamp(0.0, 1.0);
+                    out.push((clipped + detail).clamp(0.0, 1.0) + (v - clipped));
+                }
+            }
+        }
+        self.emitted = ready;
+
+        // Keep `radius` rows above the next unemitted row as context.
+        let keep_from = ready.saturating_sub(self.radius).max(self.first_row);
+        self.rows.drain(..(keep_from - self.first_row) * row_len);
+        self.first_row = keep_from;
+        (out, start as u32)
+    }
+}
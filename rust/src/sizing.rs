@@ -0,0 +1,81 @@
+// Resolves the export size options against the cropped and rotated image size.
+
+use crate::model::{OutputSizePayload, PrintUnit, ResizeFit, ResizeMode};
+
+const DEFAULT_DPI: u16 = 72;
+
+pub(crate) struct OutputSize {
+    // Uniform scale from the natural output size; the source is resampled by at most this.
+    pub(crate) scale: f32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    // Centre-crop the scaled image to `width` x `height` instead of fitting it.
+    pub(crate) fill: bool,
+}
+
+pub(crate) fn density(size: &OutputSizePayload) -> u16 {
+    if size.dpi > 0 {
+        size.dpi
+    } else {
+        DEFAULT_DPI
+    }
+}
+
+pub(crate) fn resolve(size: &OutputSizePayload, natural_w: u32, natural_h: u32) -> OutputSize {
+    let (w, h) = (natural_w.max(1) as f32, natural_h.max(1) as f32);
+    let positive = |v: f32| v.is_finite() && v > 0.0;
+
+    // (scale, exact box for fill)
+    let boxed = |bw: f32, bh: f32| -> (f32, Option<(f32, f32)>) {
+        if !positive(bw) || !positive(bh) {
+            return (1.0, None);
+        }
+        match size.fit {
+            ResizeFit::Fit => ((bw / w).min(bh / h), Some((bw, bh))),
+            ResizeFit::Fill => ((bw / w).max(bh / h), Some((bw, bh))),
+        }
+    };
+    let single = |scale: f32| -> (f32, Option<(f32, f32)>) {
+        if positive(scale) {
+            (scale, None)
+        } else {
+            (1.0, None)
+        }
+    };
+
+    let (mut scale, bounds) = match size.mode {
+        ResizeMode::None => (1.0, None),
+        ResizeMode::Dimensions => boxed(size.width.round(), size.height.round()),
+        ResizeMode::LongEdge => single(size.value / w.max(h)),
+        ResizeMode::ShortEdge => single(size.value / w.min(h)),
+        ResizeMode::Megapixels => single((size.value * 1.0e6 / (w * h)).sqrt()),
+        ResizeMode::Percentage => single(size.value / 100.0),
+        ResizeMode::PrintSize => {
+            let per_unit = match size.print_unit {
+                PrintUnit::Inches => density(size) as f32,
+                PrintUnit::Centimeters => density(size) as f32 / 2.54,
+            };
+            boxed((size.width * per_unit).round(), (size.height * per_unit).round())
+        }
+    };
+    if size.dont_enlarge {
+        scale = scale.min(1.0);
+    }
+
+    let fill = size.fit == ResizeFit::Fill && bounds.is_some();
+    let scaled = |v: f32| (v * scale).round().max(1.0);
+    let (mut out_w, mut out_h) = (scaled(w), scaled(h));
+    if let Some((bw, bh)) = bounds {
+        // Fit lands on the box edge exactly; fill crops the overflow (or whatever is left
+        // after `dont_enlarge`).
+        out_w = out_w.min(bw);
+        out_h = out_h.min(bh);
+    }
+
+    OutputSize {
+        scale,
+        width: out_w as u32,
+        height: out_h as u32,
+        fill,
+    }
+}
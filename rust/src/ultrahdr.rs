@@ -0,0 +1,224 @@
+// Ultra HDR (gain map JPEG) assembly: an SDR base JPEG followed by a quarter-resolution
+// greyscale gain map, described by Adobe hdrgm XMP (Android 14) and ISO 21496-1 (Android 15+),
+// and linked through an MPF index.
+
+use anyhow::{anyhow, Result};
+use jpeg_encoder::ColorType;
+
+use crate::metadata::XMP_NAMESPACE;
+use crate::{get_luma, srgb_to_linear, try_alloc_vec};
+
+const GAIN_MAP_SCALE: u32 = 4;
+const GAIN_MAP_QUALITY: u8 = 90;
+// Matches the offsets libultrahdr uses; keeps blacks from producing unbounded ratios.
+const GAIN_OFFSET: f32 = 1.0 / 64.0;
+// Below this the map would only encode rounding noise.
+const MIN_CONTENT_BOOST_LOG2: f32 = 0.05;
+
+const ISO_NAMESPACE: &[u8] = b"urn:iso:std:iso:ts:21496:-1\0";
+
+pub(crate) struct GainMapBuilder {
+    width: u32,
+    height: u32,
+    map_w: u32,
+    map_h: u32,
+    // Sum of log2 gains per map cell.
+    log_sum: Vec<f32>,
+}
+
+impl GainMapBuilder {
+    pub(crate) fn new(width: u32, height: u32) -> Result<Self> {
+        let map_w = (width + GAIN_MAP_SCALE - 1) / GAIN_MAP_SCALE;
+        let map_h = (height + GAIN_MAP_SCALE - 1) / GAIN_MAP_SCALE;
+        if map_w > u16::MAX as u32 || map_h > u16::MAX as u32 {
+            return Err(anyhow!("Gain map dimensions exceed JPEG limits"));
+        }
+        Ok(Self {
+            width,
+            height,
+            map_w,
+            map_h,
+            log_sum: try_alloc_vec((map_w * map_h) as usize, 0f32)?,
+        })
+    }
+
+    // `sdr` is the final display-encoded sRGB pixel; `hdr_ratio` is the luminance of the
+    // untone-mapped composite over its tone-mapped counterpart.
+    #[inline]
+    pub(crate) fn add(&mut self, x: u32, y: u32, sdr: [f32; 3], hdr_ratio: f32) {
+        let sdr_luma = get_luma([srgb_to_linear(sdr[0]), srgb_to_linear(sdr[1]), srgb_to_linear(sdr[2])]);
+        // The HDR rendition never goes below the SDR one; tone mapping only compresses highlights.
+        let hdr_luma = sdr_luma * hdr_ratio.max(1.0);
+        let gain = ((hdr_luma + GAIN_OFFSET) / (sdr_luma + GAIN_OFFSET)).log2();
+        let idx = (y / GAIN_MAP_SCALE * self.map_w + x / GAIN_MAP_SCALE) as usize;
+        self.log_sum[idx] += if gain.is_finite() { gain } else { 0.0 };
+    }
+
+    // Splices the gain map into `primary`, a finished baseline JPEG. `description` is an extra
+    // rdf:Description for the primary's XMP, since a JPEG may carry only one XMP packet.
+    pub(crate) fn finish(self, primary: Vec<u8>, description: Option<String>) -> Result<Vec<u8>> {
+        let mut log_gains = self.log_sum;
+        let mut max_log = 0f32;
+        for my in 0..self.map_h {
+            for mx in 0..self.map_w {
+                let area = GAIN_MAP_SCALE.min(self.width - mx * GAIN_MAP_SCALE)
+                    * GAIN_MAP_SCALE.min(self.height - my * GAIN_MAP_SCALE);
+                let v = &mut log_gains[(my * self.map_w + mx) as usize];
+                *v /= area as f32;
+                max_log = max_log.max(*v);
+            }
+        }
+        let max_log = max_log.max(MIN_CONTENT_BOOST_LOG2);
+        let map: Vec<u8> = log_gains
+            .iter()
+            .map(|&v| ((v / max_log).clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        drop(log_gains);
+
+        let mut gain_map_jpeg = Vec::new();
+        let mut encoder = jpeg_encoder::Encoder::new(&mut gain_map_jpeg, GAIN_MAP_QUALITY);
+        encoder.add_app_segment(1, [XMP_NAMESPACE, gain_map_xmp(max_log).as_bytes()].concat())?;
+        encoder.add_app_segment(2, [ISO_NAMESPACE, &iso_metadata(max_log)].concat())?;
+        encoder.encode(&map, self.map_w as u16, self.map_h as u16, ColorType::Luma)?;
+
+        assemble(primary, &gain_map_jpeg, description.as_deref().unwrap_or(""))
+    }
+}
+
+fn gain_map_xmp(max_log: f32) -> String {
+    format!(
+        concat!(
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\" x:xmptk=\"KawaiiRawEditor\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description xmlns:hdrgm=\"http://ns.adobe.com/hdr-gain-map/1.0/\"",
+            " hdrgm:Version=\"1.0\" hdrgm:GainMapMin=\"0\" hdrgm:GainMapMax=\"{max:.6}\" hdrgm:Gamma=\"1\"",
+            " hdrgm:OffsetSDR=\"{offset:.6}\" hdrgm:OffsetHDR=\"{offset:.6}\"",
+            " hdrgm:HDRCapacityMin=\"0\" hdrgm:HDRCapacityMax=\"{max:.6}\" hdrgm:BaseRenditionIsHDR=\"False\"/>",
+            "</rdf:RDF></x:xmpmeta>"
+        ),
+        max = max_log,
+        offset = GAIN_OFFSET,
+    )
+}
+
+fn primary_xmp(gain_map_len: usize, description: &str) -> String {
+    format!(
+        concat!(
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\" x:xmptk=\"KawaiiRawEditor\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description xmlns:Container=\"http://ns.google.com/photos/1.0/container/\"",
+            " xmlns:Item=\"http://ns.google.com/photos/1.0/container/item/\"",
+            " xmlns:hdrgm=\"http://ns.adobe.com/hdr-gain-map/1.0/\" hdrgm:Version=\"1.0\">",
+            "<Container:Directory><rdf:Seq>",
+            "<rdf:li rdf:parseType=\"Resource\"><Container:Item Item:Semantic=\"Primary\" Item:Mime=\"image/jpeg\"/></rdf:li>",
+            "<rdf:li rdf:parseType=\"Resource\"><Container:Item Item:Semantic=\"GainMap\" Item:Mime=\"image/jpeg\" Item:Length=\"{len}\"/></rdf:li>",
+            "</rdf:Seq></Container:Directory></rdf:Description>{description}</rdf:RDF></x:xmpmeta>"
+        ),
+        len = gain_map_len,
+        description = description,
+    )
+}
+
+// ISO 21496-1 gain map metadata, single channel, SDR base with the HDR rendition as alternate.
+fn iso_metadata(max_log: f32) -> Vec<u8> {
+    const DENOMINATOR: u32 = 1_000_000;
+    let max_n = (max_log as f64 * DENOMINATOR as f64).round() as u32;
+    let mut out = Vec::with_capacity(64);
+    out.extend_from_slice(&0u16.to_be_bytes()); // minimum_version
+    out.extend_from_slice(&0u16.to_be_bytes()); // writer_version
+    out.push(0x40); // single channel, apply in the base colour space
+    for (n, d) in [(0, 1), (max_n, DENOMINATOR)] {
+        // base / alternate HDR headroom
+        out.extend_from_slice(&n.to_be_bytes());
+        out.extend_from_slice(&d.to_be_bytes());
+    }
+    for (n, d) in [(0, 1), (max_n, DENOMINATOR), (1, 1), (1, 64), (1, 64)] {
+        // gain map min / max, gamma, base / alternate offset
+        out.extend_from_slice(&n.to_be_bytes());
+        out.extend_from_slice(&d.to_be_bytes());
+    }
+    out
+}
+
+fn app_segment(marker: u8, payload: &[&[u8]]) -> Result<Vec<u8>> {
+    let len: usize = payload.iter().map(|p| p.len()).sum::<usize>() + 2;
+    if len > u16::MAX as usize {
+        return Err(anyhow!("JPEG APP segment too large"));
+    }
+    let mut out = vec![0xFF, marker];
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+    for part in payload {
+        out.extend_from_slice(part);
+    }
+    Ok(out)
+}
+
+// Segments go after the JFIF header, which must stay directly behind SOI, and the Exif APP1.
+fn insertion_point(jpeg: &[u8]) -> Result<usize> {
+    if jpeg.len() < 4 || jpeg[0] != 0xFF || jpeg[1] != 0xD8 {
+        return Err(anyhow!("Base image is not a JPEG"));
+    }
+    let mut at = 2;
+    while at + 10 <= jpeg.len() && jpeg[at] == 0xFF {
+        let is_jfif = jpeg[at + 1] == 0xE0;
+        let is_exif = jpeg[at + 1] == 0xE1 && &jpeg[at + 4..at + 10] == b"Exif\0\0";
+        if !is_jfif && !is_exif {
+            break;
+        }
+        at += 2 + u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+    }
+    Ok(at)
+}
+
+// MPF (CIPA DC-007) index: big-endian TIFF header, one IFD with three tags, two MP entries.
+const MPF_PAYLOAD_LEN: usize = 4 + 8 + 2 + 3 * 12 + 4 + 2 * 16;
+
+fn mpf_payload(primary_len: u32, gain_map_len: u32, gain_map_offset: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(MPF_PAYLOAD_LEN);
+    out.extend_from_slice(b"MPF\0");
+    out.extend_from_slice(b"MM\0\x2A");
+    out.extend_from_slice(&8u32.to_be_bytes());
+    out.extend_from_slice(&3u16.to_be_bytes());
+    let mut entry = |tag: u16, field_type: u16, count: u32, value: [u8; 4]| {
+        out.extend_from_slice(&tag.to_be_bytes());
+        out.extend_from_slice(&field_type.to_be_bytes());
+        out.extend_from_slice(&count.to_be_bytes());
+        out.extend_from_slice(&value);
+    };
+    entry(0xB000, 7, 4, *b"0100"); // MPFVersion
+    entry(0xB001, 4, 1, 2u32.to_be_bytes()); // NumberOfImages
+    entry(0xB002, 7, 32, (8 + 2 + 3 * 12 + 4u32).to_be_bytes()); // MPEntry
+    out.extend_from_slice(&0u32.to_be_bytes()); // no next IFD
+    for (attribute, size, offset) in [(0x0003_0000u32, primary_len, 0u32), (0, gain_map_len, gain_map_offset)] {
+        out.extend_from_slice(&attribute.to_be_bytes());
+        out.extend_from_slice(&size.to_be_bytes());
+        out.extend_from_slice(&offset.to_be_bytes());
+        out.extend_from_slice(&[0; 4]); // dependent images
+    }
+    out
+}
+
+fn assemble(primary: Vec<u8>, gain_map: &[u8], description: &str) -> Result<Vec<u8>> {
+    let at = insertion_point(&primary)?;
+    let xmp = app_segment(0xE1, &[XMP_NAMESPACE, primary_xmp(gain_map.len(), description).as_bytes()])?;
+    let iso = app_segment(0xE2, &[ISO_NAMESPACE, &[0, 0, 0, 0]])?;
+    let mpf_len = MPF_PAYLOAD_LEN + 4;
+    let primary_len = primary.len() + xmp.len() + iso.len() + mpf_len;
+    // MP offsets are relative to the MPF TIFF header.
+    let tiff_header_pos = at + xmp.len() + iso.len() + 4 + 4;
+    let total = u32::try_from(primary_len + gain_map.len()).map_err(|_| anyhow!("Ultra HDR output exceeds 4 GiB"))?;
+    let mpf = app_segment(
+        0xE2,
+        &[&mpf_payload(primary_len as u32, gain_map.len() as u32, (primary_len - tiff_header_pos) as u32)],
+    )?;
+    debug_assert_eq!(mpf.len(), mpf_len);
+
+    let mut out = Vec::with_capacity(total as usize);
+    out.extend_from_slice(&primary[..at]);
+    out.extend_from_slice(&xmp);
+    out.extend_from_slice(&iso);
+    out.extend_from_slice(&mpf);
+    out.extend_from_slice(&primary[at..]);
+    out.extend_from_slice(gain_map);
+    Ok(out)
+}
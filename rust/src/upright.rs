@@ -0,0 +1,419 @@
+// Automatic upright: finds the dominant straight lines in a preview with a gradient-guided Hough
+// transform, then searches for the rotation and keystone values that make them level and plumb.
+// Works on the oriented, flipped image, the frame where perspective and rotation are applied.
+
+use crate::model::PerspectivePayload;
+use crate::perspective::Homography;
+use crate::{get_luma, LinearImage};
+
+// Longer side the analysis runs at; enough for architectural lines, cheap for the Hough space.
+const ANALYSIS_SIZE: u32 = 1024;
+// Half-degree angle bins over [0, 180).
+const THETA_BINS: usize = 360;
+// Each edge pixel only votes within this many bins of its gradient direction; stair-stepped
+// edges of nearly straight lines report directions a few degrees off.
+const THETA_SPREAD: i64 = 10;
+const MAX_LINES: usize = 48;
+// Lines further than this from horizontal or vertical are ignored.
+const CANDIDATE_ANGLE: f32 = 30.0;
+// Residual angles are clipped here so a stray diagonal can't dominate the fit.
+const OUTLIER_ANGLE: f32 = 5.0;
+const MAX_ROTATION: f32 = 15.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Vertical,
+    Horizontal,
+}
+
+struct Line {
+    a: (f32, f32),
+    b: (f32, f32),
+    weight: f32,
+    kind: LineKind,
+}
+
+pub(crate) struct UprightSuggestion {
+    pub(crate) rotation: f32,
+    pub(crate) perspective: PerspectivePayload,
+    // Lines the suggestion was fitted to.
+    pub(crate) lines: usize,
+}
+
+pub(crate) struct UprightAnalysis {
+    // Rotation only, from horizontal lines (vertical ones if there are none).
+    pub(crate) level: UprightSuggestion,
+    // Rotation and vertical keystone.
+    pub(crate) vertical: UprightSuggestion,
+    // Rotation and both keystones.
+    pub(crate) full: UprightSuggestion,
+    pub(crate) detected: usize,
+}
+
+// Perceptual luma, box-downsampled so the longer side is at most `ANALYSIS_SIZE`.
+fn analysis_luma(image: &LinearImage) -> (usize, usize, Vec<f32>) {
+    let (src_w, src_h) = (image.width() as usize, image.height() as usize);
+    let factor = ((src_w.max(src_h) as u32 + ANALYSIS_SIZE - 1) / ANALYSIS_SIZE).max(1) as usize;
+    let (w, h) = ((src_w / factor).max(1), (src_h / factor).max(1));
+    let src = image.as_raw();
+    let mut luma = vec![0f32; w * h];
+    for (y, row) in luma.chunks_exact_mut(w).enumerate() {
+        for (x, out) in row.iter_mut().enumerate() {
+            let mut sum = 0.0;
+            for sy in y * factor..((y + 1) * factor).min(src_h) {
+                for sx in x * factor..((x + 1) * factor).min(src_w) {
+                    let i = (sy * src_w + sx) * 3;
+                    sum += get_luma([src[i], src[i + 1], src[i + 2]]);
+                }
+            }
+            // Edges in shadows matter as much as edges in highlights.
+            *out = (sum / (factor * factor) as f32).max(0.0).powf(1.0 / 2.2);
+        }
+    }
+    (w, h, luma)
+}
+
+struct Edge {
+    x: f32,
+    y: f32,
+    // Gradient direction folded into [0, pi): the normal of the line through the pixel.
+    normal: f32,
+}
+
+// Sobel gradients, thinned by non-maximum suppression and kept above the 90th percentile.
+fn detect_edges(w: usize, h: usize, luma: &[f32]) -> Vec<Edge> {
+    if w < 3 || h < 3 {
+        return Vec::new();
+    }
+    let mut gx = vec![0f32; w * h];
+    let mut gy = vec![0f32; w * h];
+    let mut magnitude = vec![0f32; w * h];
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let p = |dx: i64, dy: i64| luma[(y as i64 + dy) as usize * w + (x as i64 + dx) as usize];
+            let sx = p(1, -1) + 2.0 * p(1, 0) + p(1, 1) - p(-1, -1) - 2.0 * p(-1, 0) - p(-1, 1);
+            let sy = p(-1, 1) + 2.0 * p(0, 1) + p(1, 1) - p(-1, -1) - 2.0 * p(0, -1) - p(1, -1);
+            let i = y * w + x;
+            gx[i] = sx;
+            gy[i] = sy;
+            magnitude[i] = (sx * sx + sy * sy).sqrt();
+        }
+    }
+
+    let mut sorted: Vec<f32> = magnitude.iter().copied().filter(|&m| m > 0.0).collect();
+    if sorted.is_empty() {
+        return Vec::new();
+    }
+    let nth = sorted.len() * 9 / 10;
+    let (_, &mut percentile, _) = sorted.select_nth_unstable_by(nth, f32::total_cmp);
+    let threshold = percentile.max(0.08);
+
+    let mut edges = Vec::new();
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let i = y * w + x;
+            let m = magnitude[i];
+            if m < threshold {
+                continue;
+            }
+            // Compare against the two neighbours across the edge.
+            let (ox, oy) = ((gx[i] / m).round() as i64, (gy[i] / m).round() as i64);
+            let at = |dx: i64, dy: i64| magnitude[(y as i64 + dy) as usize * w + (x as i64 + dx) as usize];
+            if m < at(ox, oy) || m < at(-ox, -oy) {
+                continue;
+            }
+            let mut normal = gy[i].atan2(gx[i]);
+            if normal < 0.0 {
+                normal += std::f32::consts::PI;
+            }
+            edges.push(Edge { x: x as f32, y: y as f32, normal });
+        }
+    }
+    edges
+}
+
+struct Segment {
+    // Relative to the image centre.
+    mean: (f32, f32),
+    direction: (f32, f32),
+    // Extent along `direction` from `mean`.
+    lo: f32,
+    hi: f32,
+    support: u32,
+}
+
+// Least-squares line through the edge pixels within `tolerance` of the line through `point`
+// with unit `normal`, whose gradients agree with that normal. Coordinates are relative to
+// `center`.
+fn fit_segment(edges: &[Edge], center: (f32, f32), point: (f32, f32), normal: (f32, f32), tolerance: f32) -> Option<Segment> {
+    use std::f32::consts::PI;
+    let max_dn = (THETA_SPREAD as f32 * 180.0 / THETA_BINS as f32).to_radians();
+    let mut theta = normal.1.atan2(normal.0);
+    if theta < 0.0 {
+        theta += PI;
+    }
+    let offset = point.0 * normal.0 + point.1 * normal.1;
+    let mut support = Vec::new();
+    for e in edges {
+        let (dx, dy) = (e.x - center.0, e.y - center.1);
+        let dn = (e.normal - theta).abs();
+        if (dx * normal.0 + dy * normal.1 - offset).abs() > tolerance || dn.min(PI - dn) > max_dn {
+            continue;
+        }
+        support.push((dx as f64, dy as f64));
+    }
+    if support.len() < 2 {
+        return None;
+    }
+
+    let n = support.len() as f64;
+    let mx = support.iter().map(|p| p.0).sum::<f64>() / n;
+    let my = support.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut cxx, mut cyy, mut cxy) = (0f64, 0f64, 0f64);
+    for &(x, y) in &support {
+        cxx += (x - mx) * (x - mx);
+        cyy += (y - my) * (y - my);
+        cxy += (x - mx) * (y - my);
+    }
+    let angle = 0.5 * (2.0 * cxy).atan2(cxx - cyy);
+    let direction = (angle.cos(), angle.sin());
+    let (mut lo, mut hi) = (f64::MAX, f64::MIN);
+    for &(x, y) in &support {
+        let along = (x - mx) * direction.0 + (y - my) * direction.1;
+        lo = lo.min(along);
+        hi = hi.max(along);
+    }
+    Some(Segment {
+        mean: (mx as f32, my as f32),
+        direction: (direction.0 as f32, direction.1 as f32),
+        lo: lo as f32,
+        hi: hi as f32,
+        support: support.len() as u32,
+    })
+}
+
+fn detect_lines(w: usize, h: usize, edges: &[Edge]) -> Vec<Line> {
+    use std::f32::consts::PI;
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let max_rho = (cx * cx + cy * cy).sqrt().ceil() as i64;
+    let rho_bins = (2 * max_rho + 1) as usize;
+    let trig: Vec<(f32, f32)> = (0..THETA_BINS)
+        .map(|t| {
+            let theta = t as f32 * PI / THETA_BINS as f32;
+            (theta.cos(), theta.sin())
+        })
+        .collect();
+
+    let mut votes = vec![0u32; THETA_BINS * rho_bins];
+    for e in edges {
+        let center = (e.normal / PI * THETA_BINS as f32).round() as i64;
+        for dt in -THETA_SPREAD..=THETA_SPREAD {
+            // Angles wrap at 180 degrees; rho is computed for the wrapped angle, so it stays consistent.
+            let t = (center + dt).rem_euclid(THETA_BINS as i64) as usize;
+            let (cos, sin) = trig[t];
+            let rho = (e.x - cx) * cos + (e.y - cy) * sin;
+            let r = (rho.round() as i64 + max_rho).clamp(0, rho_bins as i64 - 1);
+            votes[t * rho_bins + r as usize] += 1;
+        }
+    }
+
+    let min_votes = (w.min(h) as u32 / 12).max(20);
+    let mut peaks: Vec<(u32, usize, usize)> = Vec::new();
+    for t in 0..THETA_BINS {
+        let theta_deg = t as f32 * 180.0 / THETA_BINS as f32;
+        let from_vertical = theta_deg.min(180.0 - theta_deg);
+        let from_horizontal = (theta_deg - 90.0).abs();
+        if from_vertical > CANDIDATE_ANGLE && from_horizontal > CANDIDATE_ANGLE {
+            continue;
+        }
+        for r in 0..rho_bins {
+            let v = votes[t * rho_bins + r];
+            if v < min_votes {
+                continue;
+            }
+            let is_max = (-2i64..=2).all(|dt| {
+                (-2i64..=2).all(|dr| {
+                    let raw = t as i64 + dt;
+                    let tt = raw.rem_euclid(THETA_BINS as i64) as usize;
+                    // Across the 180 degree wrap the same line has the opposite rho.
+                    let wrapped = raw < 0 || raw >= THETA_BINS as i64;
+                    let rr = if wrapped { 2 * max_rho - (r as i64 + dr) } else { r as i64 + dr };
+                    (dt == 0 && dr == 0) || rr < 0 || rr >= rho_bins as i64 || votes[tt * rho_bins + rr as usize] <= v
+                })
+            });
+            if is_max {
+                peaks.push((v, t, r));
+            }
+        }
+    }
+    peaks.sort_unstable_by_key(|p| std::cmp::Reverse(p.0));
+
+    let mut lines = Vec::new();
+    let mut accepted: Vec<(usize, i64)> = Vec::new();
+    for (_, t, r) in peaks {
+        if lines.len() >= MAX_LINES {
+            break;
+        }
+        let rho = r as i64 - max_rho;
+        // Skip near-duplicates of a stronger line.
+        if accepted.iter().any(|&(at, ar)| {
+            let dt = (at as i64 - t as i64).abs();
+            let dr = if dt > THETA_BINS as i64 / 2 { (ar + rho).abs() } else { (ar - rho).abs() };
+            dt.min(THETA_BINS as i64 - dt) <= 4 && dr <= 6
+        }) {
+            continue;
+        }
+        accepted.push((t, rho));
+
+        // The Hough peak only locates the line to within a bin, so refit it by least squares
+        // through its edge pixels, then once more around the fitted line.
+        let (cos, sin) = trig[t];
+        let theta = t as f32 * PI / THETA_BINS as f32;
+        let Some(coarse) = fit_segment(edges, (cx, cy), (rho as f32 * cos, rho as f32 * sin), (cos, sin), 2.5) else {
+            continue;
+        };
+        let normal = (-coarse.direction.1, coarse.direction.0);
+        let Some(segment) = fit_segment(edges, (cx, cy), coarse.mean, normal, 1.5) else {
+            continue;
+        };
+        if segment.support < min_votes || segment.hi - segment.lo < 8.0 {
+            continue;
+        }
+        let (mx, my) = segment.mean;
+        let (dir_x, dir_y) = segment.direction;
+        let point = |s: f32| (cx + mx + s * dir_x, cy + my + s * dir_y);
+        let theta_deg = theta.to_degrees();
+        let kind = if theta_deg.min(180.0 - theta_deg) <= CANDIDATE_ANGLE {
+            LineKind::Vertical
+        } else {
+            LineKind::Horizontal
+        };
+        lines.push(Line { a: point(segment.lo), b: point(segment.hi), weight: segment.support as f32, kind });
+    }
+    lines
+}
+
+// Angle of each line from its target direction after rotating by `rotation` degrees
+// (clockwise) on top of the keystone correction.
+fn residual(line: &Line, homography: Option<&Homography>, rotation: f32) -> Option<f32> {
+    let map = |p: (f32, f32)| match homography {
+        Some(h) => h.warp(p.0, p.1),
+        None => Some(p),
+    };
+    let (a, b) = (map(line.a)?, map(line.b)?);
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (rx, ry) = (dx * cos - dy * sin, dx * sin + dy * cos);
+    let angle = match line.kind {
+        LineKind::Vertical => rx.atan2(ry),
+        LineKind::Horizontal => ry.atan2(rx),
+    };
+    // Fold the direction of travel away; only the tilt matters.
+    let half_pi = std::f32::consts::FRAC_PI_2;
+    Some(if angle > half_pi { angle - 2.0 * half_pi } else if angle < -half_pi { angle + 2.0 * half_pi } else { angle })
+}
+
+struct Fit<'a> {
+    lines: &'a [Line],
+    width: u32,
+    height: u32,
+    vertical_weight: f32,
+    horizontal_weight: f32,
+}
+
+impl Fit<'_> {
+    fn cost(&self, [rotation, vertical, horizontal]: [f32; 3]) -> f32 {
+        let payload = PerspectivePayload { vertical, horizontal, ..PerspectivePayload::default() };
+        let homography = Homography::keystone(&payload, self.width, self.height);
+        let clip = OUTLIER_ANGLE.to_radians().powi(2);
+        let mut cost = 0.0;
+        for line in self.lines {
+            let weight = match line.kind {
+                LineKind::Vertical => self.vertical_weight,
+                LineKind::Horizontal => self.horizontal_weight,
+            } * line.weight;
+            if weight <= 0.0 {
+                continue;
+            }
+            cost += weight * residual(line, homography.as_ref(), rotation).map_or(clip, |r| (r * r).min(clip));
+        }
+        // Among equally good answers, prefer the gentlest correction.
+        cost + 1.0e-7 * (rotation * rotation + 0.01 * (vertical * vertical + horizontal * horizontal))
+    }
+
+    // Grid search refined twice around the best point. `free` selects which of
+    // [rotation, vertical, horizontal] may move.
+    fn solve(&self, free: [bool; 3]) -> [f32; 3] {
+        let limits = [MAX_ROTATION, 100.0, 100.0];
+        let mut best = [0f32; 3];
+        for (step, span) in [([0.5, 10.0, 10.0], None), ([0.1, 2.0, 2.0], Some(5.0)), ([0.02, 0.4, 0.4], Some(5.0))] {
+            let axis = |i: usize| -> Vec<f32> {
+                if !free[i] {
+                    return vec![best[i]];
+                }
+                let s: f32 = step[i];
+                let (lo, hi) = match span {
+                    None => (-limits[i], limits[i]),
+                    Some(n) => ((best[i] - n * s).max(-limits[i]), (best[i] + n * s).min(limits[i])),
+                };
+                let count = ((hi - lo) / s).round() as usize;
+                (0..=count).map(|k| lo + k as f32 * s).collect()
+            };
+            let (rs, vs, hs) = (axis(0), axis(1), axis(2));
+            let mut best_cost = f32::MAX;
+            let mut next = best;
+            for &r in &rs {
+                for &v in &vs {
+                    for &h in &hs {
+                        let c = self.cost([r, v, h]);
+                        if c < best_cost {
+                            best_cost = c;
+                            next = [r, v, h];
+                        }
+                    }
+                }
+            }
+            best = next;
+        }
+        best
+    }
+}
+
+fn suggestion(values: [f32; 3], lines: usize) -> UprightSuggestion {
+    UprightSuggestion {
+        rotation: (values[0] * 100.0).round() / 100.0,
+        perspective: PerspectivePayload {
+            vertical: (values[1] * 10.0).round() / 10.0,
+            horizontal: (values[2] * 10.0).round() / 10.0,
+            ..PerspectivePayload::default()
+        },
+        lines,
+    }
+}
+
+pub(crate) fn analyze(image: &LinearImage) -> UprightAnalysis {
+    let (w, h, luma) = analysis_luma(image);
+    let edges = detect_edges(w, h, &luma);
+    let lines = detect_lines(w, h, &edges);
+    let verticals = lines.iter().filter(|l| l.kind == LineKind::Vertical).count();
+    let horizontals = lines.len() - verticals;
+
+    let fit = |vertical_weight: f32, horizontal_weight: f32| Fit {
+        lines: &lines,
+        width: w as u32,
+        height: h as u32,
+        vertical_weight,
+        horizontal_weight,
+    };
+
+    let level = if horizontals > 0 {
+        suggestion(fit(0.0, 1.0).solve([true, false, false]), horizontals)
+    } else {
+        suggestion(fit(1.0, 0.0).solve([true, false, false]), verticals)
+    };
+    // A keystone needs at least two lines to converge; with fewer, only rotation is fitted.
+    let vertical = suggestion(fit(1.0, 0.25).solve([true, verticals >= 2, false]), lines.len());
+    let full = suggestion(fit(1.0, 1.0).solve([true, verticals >= 2, horizontals >= 2]), lines.len());
+
+    UprightAnalysis { level, vertical, full, detected: lines.len() }
+}
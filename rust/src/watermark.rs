@@ -0,0 +1,212 @@
+// Watermark stage for the tiled exporter. The logo or text is rasterized once at its final
+// size; each output pixel then only needs a bounds check and a blend.
+
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use anyhow::{anyhow, Context, Result};
+use image::imageops::FilterType;
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+use crate::decode_data_url_base64;
+use crate::model::{WatermarkAnchor, WatermarkBlend, WatermarkPayload};
+
+const SYSTEM_FONT: &str = "/system/fonts/Roboto-Regular.ttf";
+// Glyphs are laid out at this size first to measure the text, then rasterized at the final size.
+const MEASURE_PX: f32 = 100.0;
+
+pub(crate) struct Watermark {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    // Straight (non-premultiplied) RGBA, display-encoded.
+    rgba: Vec<u8>,
+    opacity: f32,
+    blend: WatermarkBlend,
+}
+
+pub(crate) fn load_font(data_url: Option<&str>) -> Result<FontVec> {
+    let bytes = match data_url {
+        Some(url) => decode_data_url_base64(url).context("Invalid font data")?,
+        None => std::fs::read(SYSTEM_FONT).context("System font not available")?,
+    };
+    FontVec::try_from_vec(bytes).map_err(|_| anyhow!("Unsupported font"))
+}
+
+pub(crate) fn parse_hex_color(hex: &str) -> Option<[f32; 3]> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let v = u32::from_str_radix(hex, 16).ok()?;
+    Some([(v >> 16) as u8, (v >> 8) as u8, v as u8].map(|c| c as f32 / 255.0))
+}
+
+fn text_width(font: &FontVec, text: &str, px: f32) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(px));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(prev) = previous {
+            width += scaled.kern(prev, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+// Single-line text coverage at `px` pixels per em: (width, height, alpha).
+pub(crate) fn rasterize_text(font: &FontVec, text: &str, px: f32) -> (u32, u32, Vec<f32>) {
+    let scaled = font.as_scaled(PxScale::from(px));
+    let width = text_width(font, text, px).ceil().max(1.0) as u32;
+    let height = (scaled.ascent() - scaled.descent()).ceil().max(1.0) as u32;
+    let mut coverage = vec![0f32; (width * height) as usize];
+
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(prev) = previous {
+            caret += scaled.kern(prev, id);
+        }
+        let glyph = id.with_scale_and_position(px, ab_glyph::point(caret, scaled.ascent()));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, c| {
+            let x = bounds.min.x as i64 + gx as i64;
+            let y = bounds.min.y as i64 + gy as i64;
+            if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+                let idx = (y as u32 * width + x as u32) as usize;
+                coverage[idx] = (coverage[idx] + c).min(1.0);
+            }
+        });
+    }
+    (width, height, coverage)
+}
+
+// Resamples in premultiplied alpha, so the colour of fully transparent pixels can't bleed into
+// the logo's edges, and returns straight RGBA again.
+fn resize_logo(logo: &RgbaImage, width: u32, height: u32) -> Vec<u8> {
+    let premultiplied = ImageBuffer::from_fn(logo.width(), logo.height(), |x, y| {
+        let [r, g, b, a] = logo.get_pixel(x, y).0;
+        let premultiply = |c: u8| (c as u32 * a as u32 * 257 / 255) as u16;
+        Rgba([premultiply(r), premultiply(g), premultiply(b), a as u16 * 257])
+    });
+    let resized = image::imageops::resize(&premultiplied, width, height, FilterType::Lanczos3);
+    resized
+        .pixels()
+        .flat_map(|p| {
+            let [r, g, b, a] = p.0;
+            let color = [r, g, b].map(|c| {
+                if a == 0 {
+                    0
+                } else {
+                    (c as f32 / a as f32 * 255.0).round().min(255.0) as u8
+                }
+            });
+            [color[0], color[1], color[2], (a / 257) as u8]
+        })
+        .collect()
+}
+
+impl Watermark {
+    pub(crate) fn new(payload: &WatermarkPayload, image_w: u32, image_h: u32) -> Result<Option<Self>> {
+        let target_w = (payload.scale.clamp(0.0, 1.0) * image_w as f32).round() as u32;
+        let opacity = payload.opacity.clamp(0.0, 1.0);
+        if target_w == 0 || opacity <= 0.0 {
+            return Ok(None);
+        }
+
+        let (width, height, rgba) = if let Some(url) = payload.image_data_base64.as_deref() {
+            let bytes = decode_data_url_base64(url).context("Invalid watermark image data")?;
+            let logo = image::load_from_memory(&bytes).context("Failed to decode watermark image")?.to_rgba8();
+            let target_h = ((logo.height() as f32 * target_w as f32 / logo.width().max(1) as f32).round() as u32).max(1);
+            (target_w, target_h, resize_logo(&logo, target_w, target_h))
+        } else if let Some(text) = payload.text.as_deref().filter(|t| !t.trim().is_empty()) {
+            let font = load_font(payload.font_data_base64.as_deref())?;
+            let measured = text_width(&font, text, MEASURE_PX).max(1.0);
+            let (w, h, coverage) = rasterize_text(&font, text, MEASURE_PX * target_w as f32 / measured);
+            let color = parse_hex_color(&payload.text_color).unwrap_or([1.0; 3]).map(|c| (c * 255.0).round() as u8);
+            let rgba = coverage
+                .iter()
+                .flat_map(|&a| [color[0], color[1], color[2], (a * 255.0).round() as u8])
+                .collect();
+            (w, h, rgba)
+        } else {
+            return Ok(None);
+        };
+
+        let margin = (payload.margin.clamp(0.0, 0.5) * image_w.min(image_h) as f32).round() as i64;
+        let place = |free: i64, start: bool, center: bool| -> u32 {
+            let pos = if center {
+                free / 2
+            } else if start {
+                margin
+            } else {
+                free - margin
+            };
+            pos.clamp(0, free.max(0)) as u32
+        };
+        let free_x = image_w as i64 - width as i64;
+        let free_y = image_h as i64 - height as i64;
+        use WatermarkAnchor::*;
+        let x = match payload.anchor {
+            TopLeft | Left | BottomLeft => place(free_x, true, false),
+            Top | Center | Bottom => place(free_x, false, true),
+            TopRight | Right | BottomRight => place(free_x, false, false),
+        };
+        let y = match payload.anchor {
+            TopLeft | Top | TopRight => place(free_y, true, false),
+            Left | Center | Right => place(free_y, false, true),
+            BottomLeft | Bottom | BottomRight => place(free_y, false, false),
+        };
+
+        Ok(Some(Self { x, y, width, height, rgba, opacity, blend: payload.blend }))
+    }
+
+    // Blends into a display-encoded pixel; also returns how much of the pixel the watermark covers.
+    #[inline]
+    pub(crate) fn apply(&self, x: u32, y: u32, base: [f32; 3]) -> ([f32; 3], f32) {
+        if x < self.x || y < self.y || x >= self.x + self.width || y >= self.y + self.height {
+            return (base, 0.0);
+        }
+        let idx = (((y - self.y) * self.width + (x - self.x)) * 4) as usize;
+        let alpha = self.rgba[idx + 3] as f32 / 255.0 * self.opacity;
+        if alpha <= 0.0 {
+            return (base, 0.0);
+        }
+        let mut out = base;
+        for c in 0..3 {
+            let a = base[c].clamp(0.0, 1.0);
+            let b = self.rgba[idx + c] as f32 / 255.0;
+            let blended = match self.blend {
+                WatermarkBlend::Normal => b,
+                WatermarkBlend::Multiply => a * b,
+                WatermarkBlend::Screen => 1.0 - (1.0 - a) * (1.0 - b),
+                WatermarkBlend::Overlay => {
+                    if a < 0.5 {
+                        2.0 * a * b
+                    } else {
+                        1.0 - 2.0 * (1.0 - a) * (1.0 - b)
+                    }
+                }
+                // W3C soft light.
+                WatermarkBlend::SoftLight => {
+                    if b <= 0.5 {
+                        a - (1.0 - 2.0 * b) * a * (1.0 - a)
+                    } else {
+                        let d = if a <= 0.25 { ((16.0 * a - 12.0) * a + 4.0) * a } else { a.sqrt() };
+                        a + (2.0 * b - 1.0) * (d - a)
+                    }
+                }
+            };
+            out[c] = a + (blended - a) * alpha;
+        }
+        (out, alpha)
+    }
+}